@@ -1,151 +1,1621 @@
 use colored::*;
 use dialoguer::{theme::ColorfulTheme, Confirm, Input, Select};
 use indicatif::{ProgressBar, ProgressStyle};
-use std::collections::HashMap;
+use serde::Serialize;
+use std::cell::Cell;
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap, HashSet};
 use std::fs;
+use std::hash::{BuildHasherDefault, DefaultHasher};
+use std::io::{BufRead, BufReader, BufWriter, IsTerminal, Write};
 use std::path::Path;
-use std::time::Instant;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use terminal_size::{terminal_size, Width};
 
-const INF: i32 = i32::MAX / 2;
+/// Distance type of the matrix, the DP and the reports: `i32` by default,
+/// `i64` with the `wide` feature for weights beyond `i32::MAX` (e.g.
+/// meters across a continent), at twice the memo memory.
+#[cfg(not(feature = "wide"))]
+type Weight = i32;
+#[cfg(feature = "wide")]
+type Weight = i64;
+/// Twice as wide as `Weight`, for sums and sentinels that must not overflow.
+#[cfg(not(feature = "wide"))]
+type WideWeight = i64;
+#[cfg(feature = "wide")]
+type WideWeight = i128;
+
+const INF: Weight = Weight::MAX / 2;
+/// The city every tour starts and ends at.
+const DEPOT: usize = 0;
+/// Exact international mile, for `--convert`.
+const KM_PER_MILE: f64 = 1.609344;
+/// Earth radius of the TSPLIB `GEO` formula, in kilometres.
+const GEO_EARTH_RADIUS: f64 = 6378.388;
+/// The truncated π of the TSPLIB `GEO` reference code; published optima
+/// were computed with it, so `std::f64::consts::PI` would drift from them.
+#[allow(clippy::approx_constant)]
+const GEO_PI: f64 = 3.141592;
+/// Largest instance for which the flat `Vec` memo table is the default.
+const VEC_MEMO_MAX_CITIES: usize = 20;
+/// Most cities a `usize` bitmask can hold with room for `1 << n`.
+const MAX_BITMASK_CITIES: usize = usize::BITS as usize - 1;
+/// Subgradient steps for the Held-Karp bound unless `--bound-iterations` says otherwise.
+const DEFAULT_BOUND_ITERATIONS: usize = 100;
+/// File extensions `--dir` picks up as instances.
+const BATCH_EXTENSIONS: [&str; 3] = ["txt", "tsp", "csv"];
+/// Batch mode can't stop to ask, so it refuses instances above this size.
+const BATCH_MAX_CITIES: usize = 20;
+/// Largest instance `--trace-states` will print every DP state for.
+const TRACE_MAX_CITIES: usize = 8;
+/// Random instances `--selftest` checks, and their largest size (kept small
+/// enough for exhaustive search).
+const SELFTEST_RUNS: usize = 200;
+const SELFTEST_MAX_CITIES: usize = 8;
+/// Largest instance `--state-graph` will export.
+const STATE_GRAPH_MAX_CITIES: usize = 10;
+/// Largest instance `--grade-log` will write, about a million lines.
+const GRADE_LOG_MAX_CITIES: usize = 16;
+/// Shortest gap between two `--tui` redraws while the DP runs.
+#[cfg(feature = "tui")]
+const DASHBOARD_REDRAW: Duration = Duration::from_millis(50);
+/// Upper bound on queue pops in `solve_k_best`, against tie-heavy blowups.
+const K_BEST_MAX_EXPANSIONS: usize = 5_000_000;
+/// Most optimal tours `--prefer-uniform` compares.
+const UNIFORM_MAX_TOURS: usize = 10_000;
+const CHECKPOINT_HEADER: &str = "TSP-DP-CHECKPOINT v1";
+const MATRIX_CACHE_HEADER: &str = "TSP-DP-MATRIX-CACHE v1";
+/// `--emit-binary` files start with this magic and a little-endian `u32`
+/// version.
+const BINARY_MAGIC: &[u8; 4] = b"TSPB";
+const BINARY_VERSION: u32 = 1;
+/// Stands for `INF` in the binary format, whatever `INF` is in this build.
+const BINARY_INF: i32 = i32::MAX;
+/// Default number of DP states between two `--checkpoint` saves.
+const DEFAULT_CHECKPOINT_EVERY: usize = 1_000_000;
+/// Widest the banner and reports get, and the width used for non-TTY output.
+const REPORT_WIDTH: usize = 70;
+/// Below this width text is left-aligned instead of centered.
+const MIN_CENTERED_WIDTH: usize = 40;
+/// Largest instance for which `--evaluate` also computes the exact optimum.
+const EVALUATE_EXACT_LIMIT: usize = 15;
+/// Largest `2^groups × cities` table `--groups` will allocate.
+const GROUPS_MAX_STATES: usize = 1 << 23;
+/// Refusal for a matrix whose off-diagonal entries are all `INF`.
+const NO_FINITE_EDGES: &str = "No finite edges — did you forget to fill the matrix?";
+/// Attempts `read_file` makes before giving up on a transient error.
+const READ_ATTEMPTS: u32 = 3;
+/// Wait before the first retry; doubled for each further one.
+const READ_RETRY_DELAY: Duration = Duration::from_millis(100);
+/// Largest instance checked for triangle-inequality violations (`O(n³)`).
+const METRIC_CHECK_MAX_CITIES: usize = 200;
+
+/// Writes timestamped milestone lines to a file, independent of the terminal
+/// progress bar. Write failures are ignored so logging never aborts a solve.
+struct RunLogger {
+    file: fs::File,
+    start: Instant,
+}
+
+impl RunLogger {
+    fn create(path: &str) -> std::io::Result<Self> {
+        Ok(RunLogger {
+            file: fs::File::create(path)?,
+            start: Instant::now(),
+        })
+    }
+
+    fn log(&mut self, message: &str) {
+        let _ = writeln!(
+            self.file,
+            "[{}] [+{:.3}s] {}",
+            utc_timestamp(SystemTime::now()),
+            self.start.elapsed().as_secs_f64(),
+            message
+        );
+    }
+}
+
+/// Formats a system time as an ISO 8601 UTC timestamp with millisecond precision.
+fn utc_timestamp(time: SystemTime) -> String {
+    let since_epoch = time.duration_since(UNIX_EPOCH).unwrap_or_default();
+    let secs = since_epoch.as_secs();
+    let (days, day_secs) = ((secs / 86_400) as i64, secs % 86_400);
+
+    // Civil-from-days conversion (proleptic Gregorian calendar)
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+        year,
+        month,
+        day,
+        day_secs / 3_600,
+        day_secs % 3_600 / 60,
+        day_secs % 60,
+        since_epoch.subsec_millis()
+    )
+}
+
+/// Storage for DP values keyed by `(mask, pos)`, so the DP code doesn't
+/// depend on how the table is laid out.
+trait MemoStore {
+    fn get(&self, mask: usize, pos: usize) -> Option<Weight>;
+    fn insert(&mut self, mask: usize, pos: usize, value: Weight);
+    fn entries(&self) -> Box<dyn Iterator<Item = (usize, usize, Weight)> + '_>;
+    /// Number of states currently stored
+    fn len(&self) -> usize;
+    /// Rough size of the table in bytes
+    fn memory_bytes(&self) -> usize;
+}
+
+/// Hasher with fixed keys: the same fill stores the states in the same
+/// order on every run, so anything iterating the table (`--dump-dp`,
+/// checkpoints) is reproducible. The keys are small integers the program
+/// generates, so `RandomState`'s protection against crafted keys isn't needed.
+type FixedState = BuildHasherDefault<DefaultHasher>;
+
+/// Only holds the states that were actually stored; suits sparse tables.
+struct HashMapMemo {
+    values: HashMap<(usize, usize), Weight, FixedState>,
+}
+
+impl MemoStore for HashMapMemo {
+    fn get(&self, mask: usize, pos: usize) -> Option<Weight> {
+        self.values.get(&(mask, pos)).copied()
+    }
+
+    fn insert(&mut self, mask: usize, pos: usize, value: Weight) {
+        self.values.insert((mask, pos), value);
+    }
+
+    fn entries(&self) -> Box<dyn Iterator<Item = (usize, usize, Weight)> + '_> {
+        Box::new(self.values.iter().map(|(&(mask, pos), &value)| (mask, pos, value)))
+    }
+
+    fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    fn memory_bytes(&self) -> usize {
+        // One bucket plus one control byte per slot of capacity
+        self.values.capacity() * (std::mem::size_of::<((usize, usize), Weight)>() + 1)
+    }
+}
+
+/// Flat table with a slot for every odd mask and position, allocated up
+/// front; faster than hashing when most states are filled anyway.
+struct VecMemo {
+    n: usize,
+    values: Vec<Weight>,
+    stored: usize,
+}
+
+impl VecMemo {
+    /// Marks a slot that hasn't been written yet
+    const EMPTY: Weight = Weight::MIN;
+
+    fn new(n: usize) -> Self {
+        VecMemo {
+            n,
+            values: vec![Self::EMPTY; n << n.saturating_sub(1)],
+            stored: 0,
+        }
+    }
+
+    fn index(&self, mask: usize, pos: usize) -> usize {
+        debug_assert!(mask & 1 == 1, "every DP state contains city 0");
+        (mask >> 1) * self.n + pos
+    }
+}
+
+impl MemoStore for VecMemo {
+    fn get(&self, mask: usize, pos: usize) -> Option<Weight> {
+        let value = self.values[self.index(mask, pos)];
+        (value != Self::EMPTY).then_some(value)
+    }
+
+    fn insert(&mut self, mask: usize, pos: usize, value: Weight) {
+        let index = self.index(mask, pos);
+        if self.values[index] == Self::EMPTY {
+            self.stored += 1;
+        }
+        self.values[index] = value;
+    }
+
+    fn entries(&self) -> Box<dyn Iterator<Item = (usize, usize, Weight)> + '_> {
+        Box::new(
+            self.values
+                .iter()
+                .enumerate()
+                .filter(|&(_, &value)| value != Self::EMPTY)
+                .map(|(index, &value)| ((index / self.n) << 1 | 1, index % self.n, value)),
+        )
+    }
+
+    fn len(&self) -> usize {
+        self.stored
+    }
+
+    fn memory_bytes(&self) -> usize {
+        self.values.len() * std::mem::size_of::<Weight>()
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum MemoBackend {
+    HashMap,
+    Vec,
+}
+
+impl MemoBackend {
+    fn parse(text: &str) -> Result<Self, String> {
+        match text.to_lowercase().as_str() {
+            "hashmap" => Ok(MemoBackend::HashMap),
+            "vec" => Ok(MemoBackend::Vec),
+            _ => Err(format!("Unknown memo backend '{}' (expected hashmap or vec)", text)),
+        }
+    }
+
+    /// The flat table is preferred while it still fits comfortably in memory.
+    fn default_for(n: usize) -> Self {
+        if n <= VEC_MEMO_MAX_CITIES {
+            MemoBackend::Vec
+        } else {
+            MemoBackend::HashMap
+        }
+    }
+
+    fn create(self, n: usize) -> Box<dyn MemoStore> {
+        match self {
+            MemoBackend::HashMap => Box::new(HashMapMemo { values: HashMap::default() }),
+            MemoBackend::Vec => Box::new(VecMemo::new(n)),
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            MemoBackend::HashMap => "hashmap",
+            MemoBackend::Vec => "vec",
+        }
+    }
+}
 
 struct TSPSolver {
     n: usize,
-    dist: Vec<Vec<i32>>,
-    dp: HashMap<(usize, usize), i32>,
+    dist: Vec<Vec<Weight>>,
+    dp: Box<dyn MemoStore>,
+    memo_backend: MemoBackend,
     progress_bar: Option<ProgressBar>,
     total_states: usize,
+    /// States filled by the DP; path reconstruction doesn't add to this
     computed_states: usize,
+    /// Lookups of successor states that were answered from the table
+    cache_hits: Cell<usize>,
+    dp_time: Duration,
+    reconstruct_time: Duration,
+    maximize: bool,
+    logger: Option<RunLogger>,
+    logged_decile: u64,
+    /// Next (odd) mask the bottom-up fill still has to compute
+    next_mask: usize,
+    checkpoint: Option<(String, usize)>,
+    last_checkpoint: usize,
+    /// No forbidden edges, so transitions can skip the `INF` edge guard
+    complete: bool,
+    /// `(city, stop)`: only tours visiting `city` as stop number `stop`
+    fixed_position: Option<(usize, usize)>,
+    /// `predecessors[c]` is the mask of cities that must come before `c`
+    predecessors: Vec<usize>,
+    /// Print every state as it is computed (`--trace-states`)
+    trace: bool,
+    /// Print the "Solving..." line when the DP starts
+    announce: bool,
+    /// Fill the table even where a closed form exists, for tools that
+    /// inspect it afterwards
+    force_dp: bool,
+    /// Time spent filling each layer (indexed by visited-city count), when
+    /// `--timing-histogram` asks for it
+    layer_times: Option<Vec<Duration>>,
+    /// `--optional` cities as a mask, and the penalty for each one the tour
+    /// leaves out
+    optional: Option<(usize, Weight)>,
+    /// `--leg-profile`: leg `k` of the tour costs `profile[k]` times its
+    /// edge (the last entry covers any later legs)
+    leg_profile: Option<Vec<Weight>>,
+    /// `--tui` dashboard, fed every state the DP fills
+    #[cfg(feature = "tui")]
+    dashboard: Option<Box<dyn DashboardSink>>,
+}
+
+/// What the solver itself can fail with, as opposed to a malformed input.
+#[derive(Clone, PartialEq, Eq)]
+enum TspError {
+    /// More cities than a `usize` bitmask can hold
+    TooManyCities { cities: usize },
+    /// A solved path that isn't a tour: a solver bug, with what's needed to
+    /// report it
+    ReconstructionBug { problem: String, cities: usize, cost: Weight, path: Vec<usize> },
+}
+
+impl std::fmt::Display for TspError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            TspError::TooManyCities { cities } => write!(
+                f,
+                "Too many cities: {} is more than the {} a {}-bit bitmask can hold",
+                cities,
+                MAX_BITMASK_CITIES,
+                usize::BITS
+            ),
+            TspError::ReconstructionBug { problem, cities, cost, path } => write!(
+                f,
+                "Reconstruction bug: {} ({} cities, cost {}, raw path {:?})",
+                problem, cities, cost, path
+            ),
+        }
+    }
+}
+
+// `main` reports its error with `Debug`, which should read like the message
+impl std::fmt::Debug for TspError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        std::fmt::Display::fmt(self, f)
+    }
+}
+
+impl std::error::Error for TspError {}
+
+impl From<TspError> for String {
+    fn from(error: TspError) -> Self {
+        error.to_string()
+    }
+}
+
+/// The DP's city sets are `usize` bitmasks, so larger instances are refused
+/// up front rather than shifted into garbage.
+fn check_bitmask_cities(n: usize) -> Result<(), TspError> {
+    if n > MAX_BITMASK_CITIES {
+        return Err(TspError::TooManyCities { cities: n });
+    }
+    Ok(())
 }
 
 impl TSPSolver {
-    fn new(distances: Vec<Vec<i32>>) -> Self {
+    /// A solver for `distances`; refuses instances whose city sets don't fit
+    /// in a `usize` bitmask, where `1 << n` would overflow.
+    fn new(distances: Vec<Vec<Weight>>) -> Result<Self, TspError> {
         let n = distances.len();
-        let total_states = n * (1 << n);
+        check_bitmask_cities(n)?;
+        // States (mask, pos) with city 0 in mask and pos in mask, pos == 0
+        // only for the start state, excluding the full mask (the base case).
+        // Only a progress total, so it saturates near the bitmask limit
+        let total_states = if n >= 2 {
+            (n - 1).saturating_mul(1 << (n - 2)) - (n - 2)
+        } else {
+            0
+        };
         
-        TSPSolver {
+        Ok(TSPSolver {
             n,
+            complete: is_complete(&distances),
             dist: distances,
-            dp: HashMap::new(),
+            dp: MemoBackend::default_for(n).create(n),
+            memo_backend: MemoBackend::default_for(n),
             progress_bar: None,
             total_states,
             computed_states: 0,
-        }
+            cache_hits: Cell::new(0),
+            dp_time: Duration::ZERO,
+            reconstruct_time: Duration::ZERO,
+            maximize: false,
+            logger: None,
+            logged_decile: 0,
+            next_mask: if n >= 2 { (1 << n) - 3 } else { 1 },
+            checkpoint: None,
+            last_checkpoint: 0,
+            fixed_position: None,
+            predecessors: vec![0; n],
+            trace: false,
+            announce: true,
+            force_dp: false,
+            layer_times: None,
+            optional: None,
+            leg_profile: None,
+            #[cfg(feature = "tui")]
+            dashboard: None,
+        })
     }
 
     fn set_progress_bar(&mut self, pb: ProgressBar) {
         self.progress_bar = Some(pb);
     }
 
-    fn solve(&mut self) -> (i32, Vec<usize>) {
+    fn set_logger(&mut self, logger: RunLogger) {
+        self.logger = Some(logger);
+    }
+
+    fn log(&mut self, message: &str) {
+        if let Some(ref mut logger) = self.logger {
+            logger.log(message);
+        }
+    }
+
+    /// Look for the longest tour instead of the shortest. Forbidden (`INF`)
+    /// edges are never used in either mode.
+    fn set_maximize(&mut self, maximize: bool) {
+        self.maximize = maximize;
+    }
+
+    /// Value of a state from which no valid tour can be completed.
+    fn worst_cost(&self) -> Weight {
+        if self.maximize {
+            -INF
+        } else {
+            INF
+        }
+    }
+
+    fn improves(&self, candidate: Weight, best: Weight) -> bool {
+        if self.maximize {
+            candidate > best
+        } else {
+            candidate < best
+        }
+    }
+
+    /// Cost of the edge `pos → city` followed by a completion costing `rest`,
+    /// or `None` when the edge is forbidden or the completion is impossible.
+    /// Forbidden moves are skipped rather than added, so `INF` never takes
+    /// part in arithmetic and sums can't wrap around to a small value. A
+    /// negative `rest` can't make a forbidden edge look affordable either,
+    /// and `check_weight_range` keeps real sums clear of `±INF`.
+    fn transition_cost(&self, mask: usize, pos: usize, city: usize, rest: Weight) -> Option<Weight> {
+        let edge = self.leg(mask, pos, city);
+        if (!self.complete && edge >= INF) || is_infeasible(rest) {
+            return None;
+        }
+        let cost = edge.saturating_add(rest);
+        (!is_infeasible(cost)).then_some(cost)
+    }
+
+    /// Weight of the edge `pos → city` when it is taken with `mask` visited:
+    /// the matrix entry, times the `--leg-profile` multiplier for leg
+    /// number `|mask| - 1`. `INF` stays `INF`.
+    fn leg(&self, mask: usize, pos: usize, city: usize) -> Weight {
+        let edge = self.dist[pos][city];
+        match self.leg_profile {
+            Some(ref profile) if edge < INF => {
+                let leg = (mask.count_ones() as usize - 1).min(profile.len() - 1);
+                edge.saturating_mul(profile[leg])
+            }
+            _ => edge,
+        }
+    }
+
+    /// Cost of the closed (or, with `--optional`, partial) tour `path`
+    /// under the leg profile; `path_cost` when there is none.
+    fn tour_cost(&self, path: &[usize]) -> Weight {
+        if self.leg_profile.is_none() {
+            return tour_cost(&self.dist, path).unwrap_or(INF);
+        }
+        let mut mask = 0;
+        let mut total: Weight = 0;
+        for leg in path.windows(2) {
+            mask |= 1 << leg[0];
+            let edge = self.leg(mask, leg[0], leg[1]);
+            if edge >= INF {
+                return INF;
+            }
+            total = total.saturating_add(edge);
+        }
+        total
+    }
+
+    /// Scale leg `k` of every tour by `profile[k]` (a basic time-dependent
+    /// TSP, e.g. traffic getting worse as the day goes on). The multiplier
+    /// depends only on how many cities were visited before, which the
+    /// mask's popcount already tells the DP, so the state space is unchanged.
+    fn set_leg_profile(&mut self, profile: &[Weight]) {
+        self.leg_profile = (!profile.is_empty()).then(|| profile.to_vec());
+    }
+
+    /// Only allow tours that visit `a` before `b` for every pair `(a, b)`.
+    fn set_precedence(&mut self, pairs: &[(usize, usize)]) {
+        for &(before, after) in pairs {
+            self.predecessors[after] |= 1 << before;
+        }
+    }
+
+    /// Let the tour leave out any of `cities`, at `penalty` each: the DP then
+    /// minimizes the tour cost plus the penalties (prize-collecting TSP).
+    fn set_optional(&mut self, cities: &[usize], penalty: Weight) {
+        let mask = cities.iter().fold(0, |mask, &city| mask | (1 << city));
+        self.optional = (mask != 0).then_some((mask, penalty));
+    }
+
+    /// Cost of heading back to City0 from `pos` with only `mask` visited: the
+    /// return leg plus the penalties for the optional cities still left.
+    /// `None` while a required city is unvisited, before the first stop, or
+    /// when the way back is forbidden.
+    fn early_return(&self, mask: usize, pos: usize) -> Option<Weight> {
+        let (optional, penalty) = self.optional?;
+        let unvisited = self.full_mask() & !mask;
+        if mask == 1 || unvisited == 0 || unvisited & !optional != 0 {
+            return None;
+        }
+        self.transition_cost(mask, pos, 0, penalty * unvisited.count_ones() as Weight)
+    }
+
+    /// Total penalty for the optional cities `path` leaves out.
+    fn skip_penalty(&self, path: &[usize]) -> Weight {
+        let Some((optional, penalty)) = self.optional else {
+            return 0;
+        };
+        let visited = path.iter().fold(0, |mask, &city| mask | (1 << city));
+        penalty * (optional & !visited).count_ones() as Weight
+    }
+
+    fn has_precedence(&self) -> bool {
+        self.predecessors.iter().any(|&mask| mask != 0)
+    }
+
+    /// Replaces the (still empty) memo table with the given backend.
+    fn set_memo_backend(&mut self, backend: MemoBackend) {
+        self.memo_backend = backend;
+        self.dp = backend.create(self.n);
+    }
+
+    /// Periodically save the DP table to `path`, roughly every `every` states.
+    fn set_checkpoint(&mut self, path: String, every: usize) {
+        self.checkpoint = Some((path, every.max(1)));
+    }
+
+    /// Whether `city` may be the next stop once `mask` has been visited.
+    /// City 0 is stop 0, so the next stop number is the size of `mask`.
+    fn allows_step(&self, mask: usize, city: usize) -> bool {
+        if self.predecessors[city] & !mask != 0 {
+            return false;
+        }
+        match self.fixed_position {
+            Some((fixed, stop)) => (mask.count_ones() as usize == stop) == (city == fixed),
+            None => true,
+        }
+    }
+
+    fn full_mask(&self) -> usize {
+        (1 << self.n) - 1
+    }
+
+    fn solve(&mut self) -> (Weight, Vec<usize>) {
         if self.n <= 1 {
             return (0, vec![0]);
         }
+        if let Some(result) = self.solve_trivial() {
+            return result;
+        }
 
-        println!("{}", "🔍 Solving TSP using Dynamic Programming...".bright_cyan());
-        
-        // Solve TSP using dynamic programming with bitmask
-        let min_cost = self.tsp_dp(1, 0); // Start from city 0, visited only city 0
+        let min_cost = self.run_dp();
+        if is_infeasible(min_cost) {
+            return (min_cost, vec![0]);
+        }
         
         if let Some(ref pb) = self.progress_bar {
             pb.set_message("Reconstructing optimal path...");
         }
+        self.log("DP finished, reconstructing path");
         
+        let reconstruct_start = Instant::now();
         let path = self.reconstruct_path();
+        self.reconstruct_time = reconstruct_start.elapsed();
+        debug_assert!(
+            self.verify_path(min_cost, &path).is_ok(),
+            "reconstructed path disagrees with the DP: {:?}",
+            self.verify_path(min_cost, &path)
+        );
         
         if let Some(ref pb) = self.progress_bar {
             pb.finish_with_message("✅ TSP solved successfully!");
         }
+        self.log(&format!(
+            "Solved: cost {} ({} DP states computed)",
+            min_cost, self.computed_states
+        ));
         
         (min_cost, path)
     }
 
-    fn tsp_dp(&mut self, mask: usize, pos: usize) -> i32 {
-        // Update progress
-        self.computed_states += 1;
+    /// Just the optimal cost: fills the DP table but never reconstructs the
+    /// path, so `reconstruct_time` stays zero.
+    fn solve_cost(&mut self) -> Weight {
+        if self.n <= 1 {
+            return 0;
+        }
+        if let Some((cost, _)) = self.solve_trivial() {
+            return cost;
+        }
+
+        let min_cost = self.run_dp();
+        if !is_infeasible(min_cost) {
+            if let Some(ref pb) = self.progress_bar {
+                pb.finish_with_message("✅ Optimal cost found!");
+            }
+            self.log(&format!(
+                "Solved: cost {} ({} DP states computed, path not reconstructed)",
+                min_cost, self.computed_states
+            ));
+        }
+        min_cost
+    }
+
+    /// Re-checks a solved tour against the matrix: it must be a closed tour
+    /// from City0 that honours the constraints and costs exactly `cost`.
+    /// Debug builds run this after every reconstruction; `--self-check` also
+    /// runs it in release builds.
+    fn verify_path(&self, cost: Weight, path: &[usize]) -> Result<(), String> {
+        if is_infeasible(cost) || (self.n <= 1 && path == [0]) {
+            return Ok(());
+        }
+        self.check_visits(path)?;
+
+        let mut mask = 1;
+        for &city in &path[1..path.len() - 1] {
+            if !self.allows_step(mask, city) {
+                return Err(format!("path {} breaks a constraint at City{}", format_path(path), city));
+            }
+            mask |= 1 << city;
+        }
+
+        let actual = self.tour_cost(path) + self.skip_penalty(path);
+        if actual != cost {
+            return Err(format!(
+                "path {} costs {} but the DP optimum is {}",
+                format_path(path),
+                actual,
+                cost
+            ));
+        }
+        Ok(())
+    }
+
+    /// The structural half of `verify_path`, cheap enough to run after every
+    /// solve: the path (minus its return) is a permutation of the cities,
+    /// or of the required ones and some optional ones with `--optional`. A
+    /// failure is a solver bug rather than a bad instance, so the error
+    /// carries the details needed to report it.
+    fn check_reconstruction(&self, cost: Weight, path: &[usize]) -> Result<(), TspError> {
+        if is_infeasible(cost) || (self.n <= 1 && path == [0]) {
+            return Ok(());
+        }
+        self.check_visits(path).map_err(|problem| TspError::ReconstructionBug {
+            problem,
+            cities: self.n,
+            cost,
+            path: path.to_vec(),
+        })
+    }
+
+    fn check_visits(&self, path: &[usize]) -> Result<(), String> {
+        if path.first() != Some(&0) || path.last() != Some(&0) {
+            return Err(format!("path {} doesn't start and end at City0", format_path(path)));
+        }
+        match self.optional {
+            None => {
+                validate_tour(path, self.n)
+                    .map_err(|e| format!("path {} is not a tour: {}", format_path(path), e))?;
+            }
+            Some((optional, _)) => {
+                let mut seen = 0;
+                for &city in &path[..path.len() - 1] {
+                    if city >= self.n {
+                        return Err(format!("path {} has no City{}", format_path(path), city));
+                    }
+                    if seen & (1 << city) != 0 {
+                        return Err(format!("path {} visits City{} more than once", format_path(path), city));
+                    }
+                    seen |= 1 << city;
+                }
+                if let Some(missing) = (0..self.n).find(|&city| (seen | optional) & (1 << city) == 0) {
+                    return Err(format!("path {} leaves out the required City{}", format_path(path), missing));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Closed form for 2 and 3 cities, where the only tours are `0 → 1 → 0`
+    /// or the two directions around the triangle. Ties go to the tour the DP
+    /// would pick (lower city first). Constrained solves, and solves whose
+    /// table is inspected later, always use the DP.
+    fn solve_trivial(&mut self) -> Option<(Weight, Vec<usize>)> {
+        if self.n > 3 || self.fixed_position.is_some() || self.has_precedence() || self.optional.is_some() || self.trace || self.force_dp {
+            return None;
+        }
+        let candidates: &[&[usize]] = if self.n == 2 {
+            &[&[0, 1, 0]]
+        } else {
+            &[&[0, 1, 2, 0], &[0, 2, 1, 0]]
+        };
+
+        let mut best = (self.worst_cost(), vec![0]);
+        for &path in candidates {
+            let cost = self.tour_cost(path);
+            if !is_infeasible(cost) && self.improves(cost, best.0) {
+                best = (cost, path.to_vec());
+            }
+        }
+
         if let Some(ref pb) = self.progress_bar {
-            if self.computed_states % 100 == 0 {
-                let progress = (self.computed_states as f64 / self.total_states as f64 * 100.0) as u64;
-                pb.set_position(progress.min(95)); // Keep some room for path reconstruction
+            pb.finish_with_message(if is_infeasible(best.0) {
+                "❌ No valid tour exists"
+            } else {
+                "✅ TSP solved successfully!"
+            });
+        }
+        self.log(&format!("Solved {} cities in closed form: cost {}", self.n, best.0));
+        Some(best)
+    }
+
+    /// Fills the DP table (timed as `dp_time`) and returns the optimum. An
+    /// infeasible result already finishes the progress bar and log.
+    fn run_dp(&mut self) -> Weight {
+        // Nothing to search: every tour would need an edge that isn't there
+        if !has_finite_edge(&self.dist) {
+            if let Some(ref pb) = self.progress_bar {
+                pb.finish_with_message("❌ No valid tour exists");
+            }
+            self.log("Solved: no finite edges, DP skipped");
+            return self.worst_cost();
+        }
+        if self.announce {
+            println!("{}", "🔍 Solving TSP using Dynamic Programming...".bright_cyan());
+        }
+        self.log(&format!(
+            "DP started ({} cities, {} memo)",
+            self.n,
+            self.memo_backend.name()
+        ));
+        
+        // Solve TSP using dynamic programming with bitmask
+        let dp_start = Instant::now();
+        let min_cost = self.fill_table(); // Start from city 0, visited only city 0
+        self.dp_time = dp_start.elapsed();
+
+        if is_infeasible(min_cost) {
+            if let Some(ref pb) = self.progress_bar {
+                pb.finish_with_message("❌ No valid tour exists");
             }
+            self.log(&format!(
+                "Solved: no valid tour ({} DP states computed)",
+                self.computed_states
+            ));
         }
+        min_cost
+    }
 
+    /// Best cost of visiting every city outside `mask` (with `--optional`,
+    /// every required one plus skip penalties), starting at `pos`, and
+    /// returning to city 0.
+    fn state_value(&self, mask: usize, pos: usize) -> Weight {
         // Base case: if all cities are visited, return cost to start city
-        if mask == (1 << self.n) - 1 {
-            return self.dist[pos][0];
+        if mask == self.full_mask() {
+            let back = self.leg(mask, pos, 0);
+            return if back >= INF { self.worst_cost() } else { back };
         }
 
-        // Check if already computed
-        if let Some(&result) = self.dp.get(&(mask, pos)) {
-            return result;
+        match self.dp.get(mask, pos) {
+            Some(value) => {
+                self.cache_hits.set(self.cache_hits.get() + 1);
+                value
+            }
+            None => self.worst_cost(),
+        }
+    }
+
+    /// Fills the DP table bottom-up and returns the value of the start state.
+    ///
+    /// Every visited set contains city 0, so only odd masks are states. A
+    /// mask's supersets are numerically larger, so walking the masks
+    /// downwards guarantees each state's successors are already final.
+    fn fill_table(&mut self) -> Weight {
+        let mut mask = self.next_mask;
+
+        if self.trace {
+            println!("   {}", "Masks are read right to left: the last bit is City0".dimmed());
+            let full = self.full_mask();
+            for pos in 1..self.n {
+                let value = self.state_value(full, pos);
+                self.trace_state(full, pos, value, Some(0));
+            }
+        }
+
+        loop {
+            let mask_start = self.layer_times.is_some().then(Instant::now);
+            for pos in 0..self.n {
+                // City 0 is only the current position before anything else is visited
+                if (mask & (1 << pos)) == 0 || (pos == 0 && mask != 1) {
+                    continue;
+                }
+                let (value, next) = self.compute_state(mask, pos);
+                if self.trace {
+                    self.trace_state(mask, pos, value, next);
+                }
+                self.dp.insert(mask, pos, value);
+                self.count_state();
+                #[cfg(feature = "tui")]
+                if let Some(dashboard) = self.dashboard.as_mut() {
+                    let (computed, total) = (self.computed_states, self.total_states);
+                    dashboard.update(DashboardState { n: self.n, computed, total, mask, pos, value, next });
+                }
+            }
+            if let (Some(start), Some(times)) = (mask_start, self.layer_times.as_mut()) {
+                times[mask.count_ones() as usize] += start.elapsed();
+            }
+
+            if mask == 1 {
+                break;
+            }
+            mask -= 2;
+            self.next_mask = mask;
+            self.maybe_checkpoint();
         }
 
-        let mut ans = INF;
+        self.state_value(1, 0)
+    }
+
+    /// Value of a state and the next city that achieves it, if any.
+    fn compute_state(&self, mask: usize, pos: usize) -> (Weight, Option<usize>) {
+        let mut ans = self.worst_cost();
+        let mut best = None;
 
         // Try to go to every city that hasn't been visited
         for city in 0..self.n {
-            if (mask & (1 << city)) == 0 { // City not visited
-                let new_mask = mask | (1 << city);
-                let cost = self.dist[pos][city] + self.tsp_dp(new_mask, city);
-                ans = ans.min(cost);
+            if (mask & (1 << city)) == 0 && self.allows_step(mask, city) { // City not visited
+                let rest = self.state_value(mask | (1 << city), city);
+                if let Some(cost) = self.transition_cost(mask, pos, city, rest) {
+                    if self.improves(cost, ans) {
+                        ans = cost;
+                        best = Some(city);
+                    }
+                }
             }
         }
 
-        self.dp.insert((mask, pos), ans);
-        ans
+        // With --optional, going home now and skipping the rest is a move too
+        if let Some(cost) = self.early_return(mask, pos) {
+            if self.improves(cost, ans) {
+                ans = cost;
+                best = Some(0);
+            }
+        }
+
+        (ans, best)
+    }
+
+    /// What was solved, in words, and the recurrence `compute_state`
+    /// evaluates for it, for the report footer.
+    fn recurrence(&self, format: WeightFormat) -> (String, String) {
+        let (extreme, objective) = if self.maximize { ("max", "Longest") } else { ("min", "Shortest") };
+        let mut objective = match self.optional {
+            Some((_, penalty)) => format!(
+                "{} closed tour from City0 through every required city, plus {} per skipped optional city",
+                objective,
+                format.render(penalty)
+            ),
+            None => format!("{} closed tour from City0 through every city", objective),
+        };
+        let mut choice = "j ∉ S".to_string();
+        if self.has_precedence() {
+            choice += " whose predecessors are all in S";
+            objective += ", respecting the precedence pairs";
+        }
+        if let Some((city, stop)) = self.fixed_position {
+            choice += &format!(", j = {} exactly when |S| = {}", city, stop);
+            objective += &format!(", with City{} as stop {}", city, stop);
+        }
+        let mut recurrence = if self.leg_profile.is_some() {
+            objective += ", leg k weighted by the profile multiplier m(k)";
+            format!(
+                "C(S, i) = {} over {} of m(|S|) × d(i, j) + C(S ∪ {{j}}, j); C(V, i) = m(n) × d(i, 0)",
+                extreme, choice
+            )
+        } else {
+            format!("C(S, i) = {} over {} of d(i, j) + C(S ∪ {{j}}, j); C(V, i) = d(i, 0)", extreme, choice)
+        };
+        if self.optional.is_some() {
+            recurrence += ", or stop early with d(i, 0) + p × |optional ∖ S| once S holds every required city";
+        }
+        (objective, recurrence)
+    }
+
+    /// Keep `solve` from printing anything, e.g. when many instances are
+    /// solved side by side.
+    fn set_announce(&mut self, announce: bool) {
+        self.announce = announce;
+    }
+
+    #[cfg(feature = "tui")]
+    fn set_dashboard(&mut self, dashboard: Box<dyn DashboardSink>) {
+        self.dashboard = Some(dashboard);
+    }
+
+    /// Draws the `--tui` dashboard a last time and gives the terminal back.
+    fn close_dashboard(&mut self) {
+        #[cfg(feature = "tui")]
+        if let Some(mut dashboard) = self.dashboard.take() {
+            dashboard.finish();
+        }
+    }
+
+    /// Enable `--trace-states` output; meant for small instances only.
+    fn set_trace(&mut self, trace: bool) {
+        self.trace = trace;
+    }
+
+    /// Time every mask of the fill and add it to its layer. Costs a clock
+    /// read per mask, so it is off unless asked for.
+    fn set_layer_timing(&mut self, enabled: bool) {
+        self.layer_times = enabled.then(|| vec![Duration::ZERO; self.n + 1]);
+    }
+
+    /// Fill time per number of visited cities (City0 included), one entry
+    /// for every layer the table has, or `None` without `set_layer_timing`.
+    fn layer_timings(&self) -> Option<Vec<(usize, Duration)>> {
+        let times = self.layer_times.as_ref()?;
+        Some((1..self.n).map(|visited| (visited, times[visited])).collect())
+    }
+
+    /// Always solve with the DP, so the table exists for tracing and exports.
+    fn set_force_dp(&mut self, force_dp: bool) {
+        self.force_dp = force_dp;
+    }
+
+    fn trace_state(&self, mask: usize, pos: usize, value: Weight, next: Option<usize>) {
+        let kind = if mask == self.full_mask() { "base" } else { "state" };
+        let line = match next {
+            Some(city) if !is_infeasible(value) => format!(
+                "   {:5} mask {:0width$b} at City{}: {} via City{}",
+                kind,
+                mask,
+                pos,
+                value,
+                city,
+                width = self.n
+            ),
+            _ => format!(
+                "   {:5} mask {:0width$b} at City{}: no valid continuation",
+                kind,
+                mask,
+                pos,
+                width = self.n
+            ),
+        };
+        match self.progress_bar {
+            Some(ref pb) => pb.suspend(|| println!("{}", line)),
+            None => println!("{}", line),
+        }
+    }
+
+    fn count_state(&mut self) {
+        // Update progress
+        self.computed_states += 1;
+        if self.computed_states.is_multiple_of(100) {
+            let progress = (self.computed_states as f64 / self.total_states as f64 * 100.0) as u64;
+            if let Some(ref pb) = self.progress_bar {
+                pb.set_position(progress.min(95)); // Keep some room for path reconstruction
+                if let Some(rate) = format_throughput(self.computed_states, pb.elapsed()) {
+                    pb.set_prefix(rate);
+                }
+            }
+            let decile = progress.min(90) / 10;
+            if decile > self.logged_decile {
+                self.logged_decile = decile;
+                self.log(&format!("Progress: {}%", decile * 10));
+            }
+        }
     }
 
-    fn reconstruct_path(&mut self) -> Vec<usize> {
+    /// Walks the table forward from City0, each step taking the city whose
+    /// leg `dist[pos][city]` plus stored value matches the optimum. Only
+    /// forward legs are looked at, so on a directed matrix the path is the
+    /// one the DP costed, never its reverse.
+    fn reconstruct_path(&self) -> Vec<usize> {
         let mut path = Vec::new();
         let mut mask = 1; // Start with only city 0 visited
         let mut pos = 0;
         
         path.push(0);
 
-        while mask != (1 << self.n) - 1 {
+        while mask != self.full_mask() {
             let mut next_city = 0;
-            let mut min_cost = INF;
+            let mut best_cost = self.worst_cost();
 
             for city in 0..self.n {
-                if (mask & (1 << city)) == 0 { // City not visited
+                if (mask & (1 << city)) == 0 && self.allows_step(mask, city) { // City not visited
                     let new_mask = mask | (1 << city);
+                    let dp_value = self.state_value(new_mask, city);
                     
-                    // Get the DP value, compute if not exists
-                    let dp_value = if let Some(&val) = self.dp.get(&(new_mask, city)) {
-                        val
-                    } else {
-                        self.tsp_dp(new_mask, city)
+                    let Some(cost) = self.transition_cost(mask, pos, city, dp_value) else {
+                        continue;
                     };
                     
-                    let cost = self.dist[pos][city] + dp_value;
-                    
-                    if cost < min_cost {
-                        min_cost = cost;
+                    if self.improves(cost, best_cost) {
+                        best_cost = cost;
                         next_city = city;
                     }
                 }
             }
+            if self.early_return(mask, pos).is_some_and(|cost| self.improves(cost, best_cost)) {
+                break; // Skip the optional cities that are left
+            }
 
             path.push(next_city);
             mask |= 1 << next_city;
             pos = next_city;
-        }
+
+            // The last 5% of the bar belongs to reconstruction
+            if let Some(ref pb) = self.progress_bar {
+                pb.set_position(95 + (5 * (path.len() - 1) / self.n) as u64);
+                if self.n > 15 {
+                    pb.set_message(format!("Reconstructing optimal path ({}/{} cities)...", path.len(), self.n));
+                }
+            }
+        }
 
         path.push(0); // Return to start
         path
     }
+
+    /// Returns up to `k` distinct tours, best first, as `(cost, path)`.
+    ///
+    /// Best-first search over partial tours, ranked by cost so far plus the
+    /// DP value of the state they reach. That value is the exact best
+    /// completion, so complete tours come off the queue in order and every
+    /// partial tour that gets expanded leads to one of them: roughly
+    /// O(k·n²·log(k·n²)) time once the table is filled. Heavy ties can still
+    /// blow this up, hence the `K_BEST_MAX_EXPANSIONS` cap. On symmetric
+    /// instances a tour and its reverse count as the same tour.
+    fn solve_k_best(&mut self, k: usize) -> Vec<(Weight, Vec<usize>)> {
+        if self.n <= 1 {
+            return vec![(0, vec![0])];
+        }
+        if self.dp.get(1, 0).is_none() {
+            self.fill_table();
+        }
+
+        let symmetric = self.reverse_is_same_tour();
+        let full = self.full_mask();
+        // Max-heap, so minimizing ranks by the negated bound
+        let key = |bound: Weight| if self.maximize { WideWeight::from(bound) } else { -WideWeight::from(bound) };
+
+        let mut queue = BinaryHeap::new();
+        let mut seen_cycles = HashSet::new();
+        let mut tours = Vec::new();
+        let mut expansions = 0;
+
+        let start_bound = self.state_value(1, 0);
+        if !is_infeasible(start_bound) {
+            queue.push((key(start_bound), Reverse(vec![0]), 0, 1usize));
+        }
+
+        // Ties pop the lexicographically smallest path first
+        while let Some((_, Reverse(path), cost_so_far, mask)) = queue.pop() {
+            if tours.len() >= k || expansions >= K_BEST_MAX_EXPANSIONS {
+                break;
+            }
+            expansions += 1;
+            let pos = path[path.len() - 1];
+
+            // A closed tour: its cost is final
+            if path.len() == self.n + 1 {
+                let canonical = if symmetric {
+                    let reversed: Vec<usize> = path.iter().rev().copied().collect();
+                    path.clone().min(reversed)
+                } else {
+                    path.clone()
+                };
+                if seen_cycles.insert(canonical) {
+                    tours.push((cost_so_far, path));
+                }
+                continue;
+            }
+
+            if mask == full {
+                let back = self.leg(mask, pos, 0);
+                if back < INF {
+                    let cost = cost_so_far + back;
+                    let mut closed = path;
+                    closed.push(0);
+                    queue.push((key(cost), Reverse(closed), cost, mask));
+                }
+                continue;
+            }
+
+            for city in 0..self.n {
+                if (mask & (1 << city)) != 0 || !self.allows_step(mask, city) {
+                    continue;
+                }
+                let new_mask = mask | (1 << city);
+                let rest = self.state_value(new_mask, city);
+                if let Some(step) = self.transition_cost(mask, pos, city, rest) {
+                    let edge = self.leg(mask, pos, city);
+                    let mut next = path.clone();
+                    next.push(city);
+                    queue.push((key(cost_so_far + step), Reverse(next), cost_so_far + edge, new_mask));
+                }
+            }
+        }
+
+        tours
+    }
+
+    /// `--prefer-uniform`: of the tours reaching the optimum `cost`, the one
+    /// with the fewest distinct leg weights, so the objective is
+    /// lexicographic: cost first, then uniformity. The optimal tours come
+    /// from `solve_k_best`, at most `UNIFORM_MAX_TOURS` of them, and equally
+    /// uniform tours keep its order. Returns the tour, its distinct weight
+    /// count and the number of tours compared.
+    fn most_uniform_optimal(&mut self, cost: Weight) -> Option<(Vec<usize>, usize, usize)> {
+        if is_infeasible(cost) {
+            return None;
+        }
+        let count = self.distinct_optimal_tours(cost).min(UNIFORM_MAX_TOURS as u128) as usize;
+        let optimal: Vec<Vec<usize>> = self
+            .solve_k_best(count.max(1))
+            .into_iter()
+            .take_while(|&(tour_cost, _)| tour_cost == cost)
+            .map(|(_, path)| path)
+            .collect();
+        let compared = optimal.len();
+        optimal
+            .into_iter()
+            .map(|path| (distinct_leg_weights(&self.dist, &path), path))
+            .min_by_key(|&(distinct, _)| distinct)
+            .map(|(distinct, path)| (path, distinct, compared))
+    }
+
+    /// Best tour that visits `city` as stop number `stop` (City0 is stop 0),
+    /// found by re-solving with that stop fixed. Uses the same objective and
+    /// memo backend as this solver.
+    fn solve_at_position(&self, city: usize, stop: usize) -> Result<(Weight, Vec<usize>), String> {
+        if city == 0 || city >= self.n {
+            return Err(format!("City must be between 1 and {}", self.n.saturating_sub(1)));
+        }
+        if stop == 0 || stop >= self.n {
+            return Err(format!("Stop must be between 1 and {}", self.n - 1));
+        }
+
+        let mut constrained = TSPSolver::new(self.dist.clone())?;
+        constrained.set_maximize(self.maximize);
+        constrained.set_memo_backend(self.memo_backend);
+        constrained.fixed_position = Some((city, stop));
+        constrained.predecessors = self.predecessors.clone();
+        constrained.leg_profile = self.leg_profile.clone();
+        Ok(constrained.solve())
+    }
+
+    /// Number of tours reaching the optimum `cost`, each direction counted
+    /// separately, reduced modulo `modulus` when given. Only states on
+    /// optimal paths are visited, read from the filled table; tiny instances
+    /// solved in closed form are counted directly.
+    fn count_optimal_tours(&self, cost: Weight, modulus: Option<u64>) -> u128 {
+        let reduce = |count: u128| modulus.map_or(count, |m| count % u128::from(m));
+        if is_infeasible(cost) {
+            return 0;
+        }
+        if !self.has_table() {
+            let tours: &[&[usize]] = match self.n {
+                0 | 1 => &[&[0]],
+                2 => &[&[0, 1, 0]],
+                _ => &[&[0, 1, 2, 0], &[0, 2, 1, 0]],
+            };
+            let count = tours.iter().filter(|tour| self.tour_cost(tour) == cost).count();
+            return reduce(count as u128);
+        }
+
+        self.count_from(1, 0, &mut HashMap::new(), &reduce)
+    }
+
+    /// Whether a tour and its reverse are the same tour: the matrix is
+    /// symmetric and nothing depends on direction. Constraints and leg
+    /// profiles can forbid a tour's reverse or cost it differently.
+    fn reverse_is_same_tour(&self) -> bool {
+        self.fixed_position.is_none() && !self.has_precedence() && self.leg_profile.is_none() && is_symmetric(&self.dist)
+    }
+
+    /// Number of distinct optimal tours, counting a tour and its reverse
+    /// once where `reverse_is_same_tour`, as `solve_k_best` does.
+    fn distinct_optimal_tours(&self, cost: Weight) -> u128 {
+        let count = self.count_optimal_tours(cost, None);
+        if self.n >= 3 && self.reverse_is_same_tour() {
+            count / 2
+        } else {
+            count
+        }
+    }
+
+    /// Optimal completions from `(mask, pos)` for `count_optimal_tours`.
+    fn count_from(
+        &self,
+        mask: usize,
+        pos: usize,
+        memo: &mut HashMap<(usize, usize), u128>,
+        reduce: &dyn Fn(u128) -> u128,
+    ) -> u128 {
+        if mask == self.full_mask() {
+            return 1;
+        }
+        if let Some(&known) = memo.get(&(mask, pos)) {
+            return known;
+        }
+        let best = self.stored_value(mask, pos);
+        let mut total = 0;
+        for city in 0..self.n {
+            if mask & (1 << city) != 0 || !self.allows_step(mask, city) {
+                continue;
+            }
+            let next = mask | (1 << city);
+            if self.transition_cost(mask, pos, city, self.stored_value(next, city)) == Some(best) {
+                total = reduce(total + self.count_from(next, city, memo, reduce));
+            }
+        }
+        memo.insert((mask, pos), total);
+        total
+    }
+
+    /// Like `state_value`, but leaves the cache-hit statistics alone.
+    fn stored_value(&self, mask: usize, pos: usize) -> Weight {
+        if mask == self.full_mask() {
+            let back = self.leg(mask, pos, 0);
+            return if back >= INF { self.worst_cost() } else { back };
+        }
+        self.dp.get(mask, pos).unwrap_or(self.worst_cost())
+    }
+
+    /// Every state reachable from the start over allowed, non-forbidden
+    /// moves, in order of subset size, with the move the DP chose there.
+    fn state_graph(&self) -> Vec<StateNode> {
+        let full = self.full_mask();
+        let mut seen = HashSet::from([(1, 0)]);
+        let mut queue = std::collections::VecDeque::from([(1, 0)]);
+        let mut nodes = Vec::new();
+
+        while let Some((mask, pos)) = queue.pop_front() {
+            let value = self.stored_value(mask, pos);
+            if mask == full {
+                let back = self.leg(mask, pos, 0);
+                nodes.push(StateNode { mask, pos, value, next: (back < INF).then_some((0, back)) });
+                continue;
+            }
+
+            let mut best: Option<(usize, Weight)> = None;
+            let mut best_cost = self.worst_cost();
+            for city in 0..self.n {
+                if mask & (1 << city) != 0 || !self.allows_step(mask, city) || self.dist[pos][city] >= INF {
+                    continue;
+                }
+                let next = (mask | (1 << city), city);
+                if seen.insert(next) {
+                    queue.push_back(next);
+                }
+                if let Some(cost) = self.transition_cost(mask, pos, city, self.stored_value(next.0, city)) {
+                    if self.improves(cost, best_cost) {
+                        best_cost = cost;
+                        best = Some((city, self.leg(mask, pos, city)));
+                    }
+                }
+            }
+            nodes.push(StateNode { mask, pos, value, next: best });
+        }
+        nodes
+    }
+
+    /// True once the DP table holds every state, so it can be queried.
+    /// Closed-form solves of tiny instances leave it empty.
+    fn has_table(&self) -> bool {
+        self.n > 1 && self.dp.get(1, 0).is_some()
+    }
+
+    /// The choices the DP weighed at stop `leg` of `path`: every city it
+    /// could move to next, with the edge and the best completion from there,
+    /// best first. `None` marks a move that is forbidden, breaks a
+    /// constraint or can't be completed to a tour.
+    fn leg_alternatives(&self, path: &[usize], leg: usize) -> Vec<(usize, Option<(Weight, Weight)>)> {
+        let mask = path[..=leg].iter().fold(0, |mask, &city| mask | (1 << city));
+        let pos = path[leg];
+        if mask == self.full_mask() {
+            let back = self.leg(mask, pos, 0);
+            return vec![(0, (back < INF).then_some((back, 0)))];
+        }
+
+        let mut options: Vec<(usize, Option<(Weight, Weight)>)> = (0..self.n)
+            .filter(|&city| mask & (1 << city) == 0)
+            .map(|city| {
+                let rest = self.state_value(mask | (1 << city), city);
+                let option = (self.allows_step(mask, city) && self.transition_cost(mask, pos, city, rest).is_some())
+                    .then(|| (self.leg(mask, pos, city), rest));
+                (city, option)
+            })
+            .collect();
+        options.sort_by_key(|&(city, option)| {
+            let cost = option.map(|(edge, rest)| WideWeight::from(edge) + WideWeight::from(rest));
+            let key = match cost {
+                Some(cost) if self.maximize => -cost,
+                Some(cost) => cost,
+                None => WideWeight::MAX,
+            };
+            (key, city)
+        });
+        options
+    }
+
+    /// For every leg of `path`, the best next city it didn't take and how
+    /// much worse the tour gets through it, from the stored DP values: a
+    /// large gap means the choice is locked in, `0` a tie. `None` where
+    /// nothing else could follow, on the forced return to City0, and
+    /// everywhere when the instance was solved without a table.
+    fn runner_ups(&self, path: &[usize]) -> Vec<Option<(usize, Weight)>> {
+        let legs = path.len().saturating_sub(1);
+        if !self.has_table() {
+            return vec![None; legs];
+        }
+        (0..legs)
+            .map(|leg| {
+                let options = self.leg_alternatives(path, leg);
+                let total = |option: Option<(Weight, Weight)>| option.map(|(edge, rest)| edge + rest);
+                let chosen = options.iter().find(|&&(city, _)| city == path[leg + 1]).and_then(|&(_, option)| total(option))?;
+                let (city, other) = options
+                    .iter()
+                    .filter(|&&(city, _)| city != path[leg + 1])
+                    .find_map(|&(city, option)| total(option).map(|other| (city, other)))?;
+                Some((city, if self.maximize { chosen - other } else { other - chosen }))
+            })
+            .collect()
+    }
+
+    /// The stops along `path` where the DP had more than one equally good
+    /// next city: `(stop, cities)`, with the cities in the order it weighed
+    /// them, so the first is the one `path` takes. The final return to City0
+    /// is forced and never a tie.
+    fn tie_points(&self, path: &[usize]) -> Vec<(usize, Vec<usize>)> {
+        let mut ties = Vec::new();
+        for leg in 0..path.len().saturating_sub(2) {
+            let options = self.leg_alternatives(path, leg);
+            let total = |option: Option<(Weight, Weight)>| option.map(|(edge, rest)| WideWeight::from(edge) + WideWeight::from(rest));
+            let Some(best) = options.first().and_then(|&(_, option)| total(option)) else {
+                continue;
+            };
+            let mut equal: Vec<usize> = options
+                .iter()
+                .filter(|&&(_, option)| total(option) == Some(best))
+                .map(|&(city, _)| city)
+                .collect();
+            if equal.len() > 1 {
+                // The path took the first city in index order, as the DP did
+                let taken = path[leg + 1];
+                equal.retain(|&city| city != taken);
+                equal.insert(0, taken);
+                ties.push((leg, equal));
+            }
+        }
+        ties
+    }
+
+    /// Stored and finite state counts per number of visited cities (City0
+    /// included), from the filled table. The full mask is the base case and
+    /// never stored, so layer `n` is absent.
+    fn layer_sizes(&self) -> Vec<(usize, usize, usize)> {
+        let mut layers = vec![(0, 0); self.n + 1];
+        for (mask, _, value) in self.dp.entries() {
+            let layer = &mut layers[mask.count_ones() as usize];
+            layer.0 += 1;
+            if !is_infeasible(value) {
+                layer.1 += 1;
+            }
+        }
+        layers
+            .into_iter()
+            .enumerate()
+            .filter(|&(_, (stored, _))| stored > 0)
+            .map(|(visited, (stored, finite))| (visited, stored, finite))
+            .collect()
+    }
+
+    /// Improves a given tour with 2-opt instead of running the DP; the table
+    /// is left untouched. The tour must visit every city once and start at
+    /// City0 (closing it with a final 0 is optional).
+    fn solve_heuristic_from(&self, initial: Vec<usize>) -> Result<(Weight, Vec<usize>), String> {
+        improve_tour(&self.dist, initial, self.maximize)
+    }
+
+    /// Held-Karp 1-tree lower bound on the optimal tour after `iterations`
+    /// subgradient steps, cheap enough for instances far beyond the DP.
+    /// Only meaningful when minimizing; `INF` means no tour exists.
+    fn held_karp_bound(&self, iterations: usize) -> Weight {
+        held_karp_bound(&self.dist, iterations)
+    }
+
+    /// Fingerprint of everything the DP values depend on, so a checkpoint is
+    /// never resumed against a different instance or objective (FNV-1a).
+    fn instance_hash(&self) -> u64 {
+        let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+        let mut feed = |value: u64| {
+            for byte in value.to_le_bytes() {
+                hash ^= u64::from(byte);
+                hash = hash.wrapping_mul(0x0100_0000_01b3);
+            }
+        };
+        feed(self.n as u64);
+        feed(u64::from(self.maximize));
+        for row in &self.dist {
+            for &d in row {
+                feed(d as u64);
+            }
+        }
+        if self.has_precedence() {
+            for &mask in &self.predecessors {
+                feed(mask as u64);
+            }
+        }
+        if let Some((optional, penalty)) = self.optional {
+            feed(optional as u64);
+            feed(penalty as u64);
+        }
+        for &multiplier in self.leg_profile.iter().flatten() {
+            feed(multiplier as u64);
+        }
+        hash
+    }
+
+    fn maybe_checkpoint(&mut self) {
+        let Some((ref path, every)) = self.checkpoint else {
+            return;
+        };
+        if self.computed_states - self.last_checkpoint < every {
+            return;
+        }
+
+        let path = path.clone();
+        match self.write_checkpoint(&path) {
+            Ok(()) => {
+                self.last_checkpoint = self.computed_states;
+                self.log(&format!("Checkpoint written ({} states)", self.computed_states));
+            }
+            Err(e) => {
+                let warning = format!("⚠️  Failed to write checkpoint '{}': {}", path, e);
+                match self.progress_bar {
+                    Some(ref pb) => pb.println(warning.yellow().to_string()),
+                    None => println!("{}", warning.yellow()),
+                }
+            }
+        }
+    }
+
+    /// Writes the table to a temporary file first and renames it into place,
+    /// so an interruption mid-write never destroys the previous checkpoint.
+    fn write_checkpoint(&self, path: &str) -> std::io::Result<()> {
+        let tmp_path = format!("{}.tmp", path);
+        {
+            let mut out = BufWriter::new(fs::File::create(&tmp_path)?);
+            writeln!(out, "{}", CHECKPOINT_HEADER)?;
+            writeln!(out, "hash {:016x}", self.instance_hash())?;
+            writeln!(out, "next_mask {}", self.next_mask)?;
+            writeln!(out, "computed {}", self.computed_states)?;
+            for (mask, pos, value) in self.dp.entries() {
+                writeln!(out, "{} {} {}", mask, pos, value)?;
+            }
+            out.flush()?;
+        }
+        fs::rename(&tmp_path, path)
+    }
+
+    /// Restores a table saved by `--checkpoint` so `solve` continues the fill
+    /// where the interrupted run stopped.
+    fn resume_from(&mut self, path: &str) -> Result<(), String> {
+        let content = read_file(path)
+            .map_err(|e| format!("Error reading checkpoint '{}': {}", path, e))?;
+        let mut lines = content.lines();
+
+        if lines.next() != Some(CHECKPOINT_HEADER) {
+            return Err(format!("'{}' is not a TSP solver checkpoint", path));
+        }
+
+        let mut header_value = |key: &str| -> Result<String, String> {
+            lines
+                .next()
+                .and_then(|line| line.strip_prefix(key))
+                .map(|value| value.trim().to_string())
+                .ok_or_else(|| format!("Checkpoint is missing '{}'", key))
+        };
+        let hash = header_value("hash")?;
+        let next_mask = header_value("next_mask")?;
+        let computed = header_value("computed")?;
+
+        if hash != format!("{:016x}", self.instance_hash()) {
+            return Err("Checkpoint was made for a different instance or mode".to_string());
+        }
+        let next_mask: usize = next_mask.parse()
+            .map_err(|_| "Invalid next_mask in checkpoint")?;
+        let computed: usize = computed.parse()
+            .map_err(|_| "Invalid state count in checkpoint")?;
+
+        let mut entries = Vec::new();
+        for (line_num, line) in lines.enumerate() {
+            let parts: Vec<&str> = line.split_whitespace().collect();
+            let entry = match parts.as_slice() {
+                [mask, pos, value] => mask.parse().ok().zip(pos.parse().ok()).zip(value.parse().ok()),
+                _ => None,
+            };
+            let Some(((mask, pos), value)) = entry else {
+                return Err(format!("Checkpoint line {}: Invalid DP entry", line_num + 5));
+            };
+            let mask: usize = mask;
+            if mask > self.full_mask() || mask & 1 == 0 || pos >= self.n {
+                return Err(format!("Checkpoint line {}: State out of range", line_num + 5));
+            }
+            entries.push((mask, pos, value));
+        }
+
+        for (mask, pos, value) in entries {
+            self.dp.insert(mask, pos, value);
+        }
+        self.next_mask = next_mask;
+        self.computed_states = computed;
+        self.last_checkpoint = computed;
+        self.logged_decile =
+            (computed as f64 / self.total_states as f64 * 10.0).min(9.0) as u64;
+        self.log(&format!("Resumed from checkpoint '{}' ({} states)", path, computed));
+        Ok(())
+    }
+}
+
+/// Width used for the banner and reports, clamped to the terminal width when
+/// stdout is a TTY. Piped output always uses the fixed `REPORT_WIDTH`.
+fn report_width() -> usize {
+    if !std::io::stdout().is_terminal() {
+        return REPORT_WIDTH;
+    }
+    match terminal_size() {
+        Some((Width(columns), _)) => (columns as usize).min(REPORT_WIDTH),
+        None => REPORT_WIDTH,
+    }
 }
 
 fn center_text(text: &str, width: usize) -> String {
-    let padding = if text.len() < width {
-        (width - text.len()) / 2
+    let len = visible_len(text);
+    let padding = if width >= MIN_CENTERED_WIDTH && len < width {
+        (width - len) / 2
     } else {
         0
     };
     format!("{}{}", " ".repeat(padding), text)
 }
 
+/// Characters of `text` on screen, leaving out the color escape sequences
+/// of a colored part such as the depot marker.
+fn visible_len(text: &str) -> usize {
+    let mut chars = text.chars();
+    let mut len = 0;
+    while let Some(c) = chars.next() {
+        if c == '\x1b' {
+            chars.by_ref().find(|&c| c == 'm');
+        } else {
+            len += 1;
+        }
+    }
+    len
+}
+
 fn print_banner() {
-    let width = 70;
+    let width = report_width();
     println!("{}", "═".repeat(width).bright_cyan());
     println!(
         "{}",
@@ -169,14 +1639,40 @@ fn print_banner() {
     println!();
 }
 
-fn print_matrix(matrix: &Vec<Vec<i32>>) {
+fn print_instructions() {
+    println!("🎮 Welcome to the advanced TSP Solver!");
+    println!("This program solves the Traveling Salesman Problem using Dynamic Programming with Bitmask.\n");
+    
+    println!("📋 Instructions:");
+    println!("  • Matrix format: n (first line), then n×n distance matrix");
+    println!("  • Edge format: n (first line), then edges as 'from to weight'");
+    println!("  • TSPLIB format: EUC_2D/CEIL_2D/ATT/GEO files with a NODE_COORD_SECTION");
+    println!("  • Use 0 for diagonal elements (city to itself)");
+    println!("  • Use INF or ∞ for unreachable paths");
+    println!("  • Blank lines and anything after '#' are ignored");
+    println!("  • Cities are numbered from 0 to n-1\n");
+}
+
+/// Prints the matrix, the depot's header and row label in the depot color
+/// (its header with 🏠) when `depot_highlight` is set.
+fn print_matrix(matrix: &[Vec<Weight>], depot_highlight: bool) {
+    let cells: Vec<Vec<Option<Weight>>> = matrix.iter().map(|row| row.iter().map(|&d| Some(d)).collect()).collect();
+    print_matrix_cells(&cells, depot_highlight);
+}
+
+/// `print_matrix` for a matrix still being entered: `None` cells show `?`.
+fn print_matrix_cells(matrix: &[Vec<Option<Weight>>], depot_highlight: bool) {
     println!("{}", "📊 Distance Matrix:".bright_green().bold());
     println!();
     
-    // Header
+    // Header; the marker is two columns wide but one character
     print!("     ");
     for i in 0..matrix.len() {
-        print!("{:>8}", format!("City{}", i).bright_blue());
+        if i == DEPOT && depot_highlight {
+            print!("{:>7}", depot_label(i));
+        } else {
+            print!("{:>8}", format!("City{}", i).bright_blue());
+        }
     }
     println!();
     
@@ -189,8 +1685,16 @@ fn print_matrix(matrix: &Vec<Vec<i32>>) {
     
     // Matrix rows
     for (i, row) in matrix.iter().enumerate() {
-        print!("{:>5}|", format!("City{}", i).bright_blue());
+        if i == DEPOT && depot_highlight {
+            print!("{:>5}|", format!("City{}", i).bright_yellow());
+        } else {
+            print!("{:>5}|", format!("City{}", i).bright_blue());
+        }
         for &val in row {
+            let Some(val) = val else {
+                print!("{:>8}", "?".yellow());
+                continue;
+            };
             if val == INF {
                 print!("{:>8}", "∞".red());
             } else if val == 0 {
@@ -204,292 +1708,8201 @@ fn print_matrix(matrix: &Vec<Vec<i32>>) {
     println!();
 }
 
-fn parse_input(content: &str) -> Result<Vec<Vec<i32>>, String> {
-    let lines: Vec<&str> = content.trim().lines().collect();
-    
-    if lines.is_empty() {
-        return Err("Empty input file".to_string());
-    }
+/// Number of distinct neighbours each city has a finite edge to or from.
+struct DegreeSummary {
+    degrees: Vec<usize>,
+}
 
-    // First line should contain number of cities
-    let n: usize = lines[0].trim().parse()
-        .map_err(|_| "Invalid number of cities")?;
+impl DegreeSummary {
+    fn new(dist: &[Vec<Weight>]) -> Self {
+        let n = dist.len();
+        let degrees = (0..n)
+            .map(|i| {
+                (0..n)
+                    .filter(|&j| j != i && (dist[i][j] < INF || dist[j][i] < INF))
+                    .count()
+            })
+            .collect();
+        DegreeSummary { degrees }
+    }
 
-    if n == 0 {
-        return Err("Number of cities must be greater than 0".to_string());
+    fn min(&self) -> usize {
+        self.degrees.iter().copied().min().unwrap_or(0)
     }
 
-    let mut distances = vec![vec![0; n]; n];
+    fn max(&self) -> usize {
+        self.degrees.iter().copied().max().unwrap_or(0)
+    }
 
-    // Format 1: Adjacency matrix (n+1 lines total)
-    if lines.len() == n + 1 {
-        for i in 0..n {
-            let row: Result<Vec<i32>, _> = lines[i + 1]
-                .trim()
-                .split_whitespace()
-                .map(|s| {
-                    let s = s.trim().to_uppercase();
-                    if s == "INF" || s == "∞" {
-                        Ok(INF)
-                    } else {
-                        s.parse::<i32>()
-                    }
-                })
-                .collect();
-            
-            match row {
-                Ok(values) => {
-                    if values.len() != n {
-                        return Err(format!("Row {} has {} values, expected {}", i, values.len(), n));
-                    }
-                    distances[i] = values;
-                }
-                Err(_) => return Err(format!("Invalid number in row {}", i + 1)),
-            }
+    fn average(&self) -> f64 {
+        if self.degrees.is_empty() {
+            return 0.0;
         }
+        self.degrees.iter().sum::<usize>() as f64 / self.degrees.len() as f64
     }
-    // Format 2: Edge list format
-    else {
-        // Initialize with infinity
-        for i in 0..n {
-            for j in 0..n {
-                distances[i][j] = if i == j { 0 } else { INF };
-            }
-        }
-
-        // Parse edges
-        for (line_num, line) in lines.iter().skip(1).enumerate() {
-            let parts: Vec<&str> = line.trim().split_whitespace().collect();
-            if parts.len() != 3 {
-                return Err(format!("Line {}: Expected 3 values (from to weight)", line_num + 2));
-            }
-
-            let from: usize = parts[0].parse()
-                .map_err(|_| format!("Line {}: Invalid 'from' city", line_num + 2))?;
-            let to: usize = parts[1].parse()
-                .map_err(|_| format!("Line {}: Invalid 'to' city", line_num + 2))?;
-            let weight: i32 = parts[2].parse()
-                .map_err(|_| format!("Line {}: Invalid weight", line_num + 2))?;
 
-            if from >= n || to >= n {
-                return Err(format!("Line {}: City index out of range", line_num + 2));
-            }
+    /// Cities without any finite edge; any one of them makes a tour impossible.
+    fn isolated(&self) -> Vec<usize> {
+        self.cities_with_degree(0)
+    }
 
-            distances[from][to] = weight;
-            distances[to][from] = weight; // Assume undirected graph
+    /// Cities reachable through a single neighbour only. A tour of three or
+    /// more cities must enter and leave through two different neighbours.
+    fn dead_ends(&self) -> Vec<usize> {
+        if self.degrees.len() < 3 {
+            return Vec::new();
         }
+        self.cities_with_degree(1)
     }
 
-    Ok(distances)
-}
-
-fn format_path(path: &[usize]) -> String {
-    path.iter()
-        .map(|&i| format!("City{}", i))
-        .collect::<Vec<_>>()
-        .join(" → ")
+    fn cities_with_degree(&self, degree: usize) -> Vec<usize> {
+        self.degrees
+            .iter()
+            .enumerate()
+            .filter(|&(_, &d)| d == degree)
+            .map(|(city, _)| city)
+            .collect()
+    }
 }
 
-fn print_solution(cost: i32, path: &[usize], elapsed: std::time::Duration, solver: &TSPSolver) {
-    let width = 70;
-    println!();
-    println!(
-        "{}",
-        center_text("✨ SOLUTION FOUND! ✨", width)
-            .bright_green()
-            .bold()
-    );
+fn print_degree_summary(summary: &DegreeSummary) {
+    println!("{}", "🔗 Connectivity Summary:".bright_green().bold());
     println!(
-        "{}",
-        center_text("═".repeat(width).as_str(), width).bright_cyan()
+        "   Degree: min {}, max {}, avg {:.2}",
+        summary.min().to_string().yellow(),
+        summary.max().to_string().yellow(),
+        summary.average()
     );
 
-    if cost >= INF {
-        println!(
-            "{}",
-            center_text("❌ No valid tour found!", width).red().bold()
-        );
-        println!(
-            "{}",
-            center_text("The graph might not be connected.", width).yellow()
-        );
-    } else {
-        println!(
-            "{}",
-            center_text(&format!("🎯 Minimum Cost: {}", cost), width)
-                .bright_yellow()
-                .bold()
-        );
-        println!(
-            "{}",
-            center_text(&format!("🗺️  Optimal Path: {}", format_path(path)), width).bright_white()
-        );
-        println!(
-            "{}",
-            center_text(&format!("⏱️  Computation Time: {:.3?}", elapsed), width).white()
-        );
+    let isolated = summary.isolated();
+    let dead_ends = summary.dead_ends();
+    if isolated.is_empty() && dead_ends.is_empty() {
+        println!("   {}", "✅ No isolated cities".green());
+    }
+    if !isolated.is_empty() {
         println!(
-            "{}",
-            center_text(&format!("🏙️  Cities Visited: {}", path.len() - 1), width).white()
+            "   {} {} (no tour can exist)",
+            "❌ Isolated cities:".red().bold(),
+            format_city_list(&isolated)
         );
+    }
+    if !dead_ends.is_empty() {
         println!(
-            "{}",
-            center_text(&format!("🔢 DP States Computed: {}", solver.computed_states), width).dimmed()
+            "   {} {} (only one neighbour, no tour can exist)",
+            "❌ Dead-end cities:".red().bold(),
+            format_city_list(&dead_ends)
         );
     }
+    println!();
+}
 
-    println!(
-        "{}",
-        center_text("═".repeat(width).as_str(), width).bright_cyan()
-    );
-
-    // Detailed route for small instances
-    if path.len() <= 12 && cost < INF {
-        println!();
-        println!("{}", "📍 Detailed Route:".bright_magenta().bold());
-        for i in 0..path.len() - 1 {
-            let from = path[i];
-            let to = path[i + 1];
-            let distance = solver.dist[from][to];
-            println!(
-                "   Step {}: {} → {} (distance: {})",
-                format!("{:2}", i + 1).bright_blue(),
-                format!("City{}", from).bright_cyan(),
-                format!("City{}", to).bright_cyan(),
-                distance.to_string().yellow()
-            );
-        }
-        println!();
+fn print_city_names(names: &[String]) {
+    println!("{}", "🏷️  City Names:".bright_green().bold());
+    for (city, name) in names.iter().enumerate() {
+        println!("   {} = {}", format!("City{}", city).bright_blue(), name);
     }
+    println!();
 }
 
-fn get_input_method() -> Result<bool, Box<dyn std::error::Error>> {
-    let options = vec![
-        "📁 Load from file",
-        "⌨️  Enter matrix manually",
-    ];
+fn format_named_path(path: &[usize], names: &[String]) -> String {
+    path.iter()
+        .map(|&city| names[city].as_str())
+        .collect::<Vec<_>>()
+        .join(" → ")
+}
 
-    let selection = Select::with_theme(&ColorfulTheme::default())
-        .with_prompt("How would you like to input the distance matrix?")
-        .items(&options)
-        .default(0)
-        .interact()?;
+/// `--cycle-notation`: the closed tour `path` as its cities in order, then
+/// the return leg to the start and its cost, e.g. `(0 3 1 2 | back to 0, +5)`.
+fn format_cycle_notation(dist: &[Vec<Weight>], path: &[usize], format: WeightFormat) -> String {
+    let [cycle @ .., last, start] = path else {
+        return format!("({})", path.iter().map(usize::to_string).collect::<Vec<_>>().join(" "));
+    };
+    let cities: Vec<String> = cycle.iter().chain([last]).map(usize::to_string).collect();
+    format!("({} | back to {}, +{})", cities.join(" "), start, format.render(dist[*last][*start]))
+}
 
-    Ok(selection == 0)
+fn format_city_list(cities: &[usize]) -> String {
+    cities
+        .iter()
+        .map(|&city| format!("City{}", city))
+        .collect::<Vec<_>>()
+        .join(", ")
 }
 
-fn get_file_path() -> Result<String, Box<dyn std::error::Error>> {
-    loop {
-        let file_path: String = Input::with_theme(&ColorfulTheme::default())
-            .with_prompt("Enter the path to your matrix file")
-            .interact_text()?;
+/// Strips `#` comments and blank lines so they don't affect format
+/// detection. Each remaining line keeps its 1-based number in the file.
+fn content_lines(content: &str) -> Vec<(usize, &str)> {
+    content
+        .lines()
+        .enumerate()
+        .filter_map(|(i, line)| {
+            let text = line.split('#').next().unwrap_or("").trim();
+            (!text.is_empty()).then_some((i + 1, text))
+        })
+        .collect()
+}
 
-        if Path::new(&file_path).exists() {
-            return Ok(file_path);
-        } else {
-            println!(
-                "{}",
-                "❌ File not found! Please check the path and try again.".red()
-            );
-            println!();
+/// How distance entries are written in the input and shown in reports.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+enum WeightFormat {
+    /// Plain integers
+    #[default]
+    Plain,
+    /// `HH:MM:SS` durations, stored internally as whole seconds. A bare
+    /// integer is read as seconds, so diagonals can stay `0`.
+    Duration,
+    /// Plain integers shown with a unit label (`--unit`)
+    Labeled(Unit),
+}
+
+impl WeightFormat {
+    fn render(self, value: Weight) -> String {
+        match self {
+            WeightFormat::Plain => value.to_string(),
+            WeightFormat::Duration => format_duration(value),
+            WeightFormat::Labeled(unit) => format!("{} {}", value, unit.label()),
+        }
+    }
+
+    /// Like `render`, but plain integers get thousands separators unless
+    /// `separators` is off.
+    fn render_grouped(self, value: Weight, separators: bool) -> String {
+        match self {
+            WeightFormat::Plain if separators => group_thousands(&value.to_string()),
+            WeightFormat::Labeled(unit) if separators => {
+                format!("{} {}", group_thousands(&value.to_string()), unit.label())
+            }
+            _ => self.render(value),
+        }
+    }
+
+    /// Renders a mean weight: two decimals for plain integers, the nearest
+    /// second for durations.
+    fn render_average(self, value: f64, separators: bool) -> String {
+        let decimals = || {
+            let text = format!("{:.2}", value);
+            if separators {
+                group_thousands(&text)
+            } else {
+                text
+            }
+        };
+        match self {
+            WeightFormat::Plain => decimals(),
+            WeightFormat::Duration => format_duration(value.round() as Weight),
+            WeightFormat::Labeled(unit) => format!("{} {}", decimals(), unit.label()),
         }
     }
 }
 
-fn create_progress_bar() -> ProgressBar {
-    let pb = ProgressBar::new(100);
+/// Unit label for `--unit`. Weights stay integers in whatever unit the
+/// input uses; only `--convert` rescales them (between km and mi).
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Unit {
+    Km,
+    Mi,
+    Min,
+    Units,
+}
+
+impl Unit {
+    fn parse(text: &str) -> Result<Self, String> {
+        match text.to_lowercase().as_str() {
+            "km" => Ok(Unit::Km),
+            "mi" => Ok(Unit::Mi),
+            "min" => Ok(Unit::Min),
+            "units" => Ok(Unit::Units),
+            _ => Err(format!("Unknown unit '{}' (expected km, mi, min or units)", text)),
+        }
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            Unit::Km => "km",
+            Unit::Mi => "mi",
+            Unit::Min => "min",
+            Unit::Units => "units",
+        }
+    }
+}
+
+/// `--convert`: rescales weights given in the other of km and mi into
+/// `to`, rounding to the nearest integer. Forbidden edges stay forbidden.
+fn convert_distance_units(distances: &mut [Vec<Weight>], to: Unit) {
+    let factor = match to {
+        Unit::Km => KM_PER_MILE,
+        Unit::Mi => 1.0 / KM_PER_MILE,
+        Unit::Min | Unit::Units => return,
+    };
+    for d in distances.iter_mut().flatten().filter(|d| **d < INF) {
+        *d = ((*d as f64) * factor).round() as Weight;
+    }
+}
+
+/// DP throughput for the progress bar, e.g. `1,250,000 states/s`; `None`
+/// until any time has passed.
+fn format_throughput(states: usize, elapsed: Duration) -> Option<String> {
+    let seconds = elapsed.as_secs_f64();
+    (seconds > 0.0).then(|| format!("{} states/s", group_thousands(&format!("{:.0}", states as f64 / seconds))))
+}
+
+/// Inserts `,` between groups of three digits in the integer part of a
+/// number, e.g. `-1234567.5` becomes `-1,234,567.5`. Always uses `,` and `.`
+/// regardless of locale.
+fn group_thousands(number: &str) -> String {
+    let (sign, digits) = match number.strip_prefix('-') {
+        Some(rest) => ("-", rest),
+        None => ("", number),
+    };
+    let (integer, fraction) = match digits.split_once('.') {
+        Some((integer, fraction)) => (integer, Some(fraction)),
+        None => (digits, None),
+    };
+
+    let mut grouped = String::from(sign);
+    for (i, digit) in integer.chars().enumerate() {
+        if i > 0 && (integer.len() - i) % 3 == 0 {
+            grouped.push(',');
+        }
+        grouped.push(digit);
+    }
+    if let Some(fraction) = fraction {
+        grouped.push('.');
+        grouped.push_str(fraction);
+    }
+    grouped
+}
+
+/// Parses `HH:MM:SS` (hours may have any number of digits) into seconds.
+fn parse_duration(text: &str) -> Option<Weight> {
+    let parts: Vec<&str> = text.split(':').collect();
+    let [hours, minutes, seconds] = parts.as_slice() else {
+        return None;
+    };
+    let hours: Weight = hours.parse().ok()?;
+    let minutes: Weight = minutes.parse().ok()?;
+    let seconds: Weight = seconds.parse().ok()?;
+    if hours < 0 || !(0..60).contains(&minutes) || !(0..60).contains(&seconds) {
+        return None;
+    }
+    hours.checked_mul(3_600)?.checked_add(minutes * 60 + seconds)
+}
+
+fn format_duration(total_seconds: Weight) -> String {
+    let sign = if total_seconds < 0 { "-" } else { "" };
+    let secs = total_seconds.unsigned_abs();
+    format!("{}{:02}:{:02}:{:02}", sign, secs / 3_600, secs % 3_600 / 60, secs % 60)
+}
+
+/// Parses one distance entry, where `INF` or `∞` marks a forbidden edge.
+fn parse_weight(token: &str, format: WeightFormat) -> Option<Weight> {
+    let token = token.trim().to_uppercase();
+    if token == "INF" || token == "∞" {
+        return Some(INF);
+    }
+    match format {
+        WeightFormat::Plain | WeightFormat::Labeled(_) => token.parse::<Weight>().ok(),
+        WeightFormat::Duration => token.parse::<Weight>().ok().or_else(|| parse_duration(&token)),
+    }
+}
+
+/// Settings that change how an instance file is read.
+#[derive(Clone, Copy, Debug, Default)]
+struct ParseOptions {
+    rounding: Option<Rounding>,
+    weight_format: WeightFormat,
+    /// Edge-list entries are one-way instead of being mirrored
+    directed: bool,
+    /// Reject instances with more cities before building their matrix
+    max_cities: Option<usize>,
+    /// CSV cell separator, `,` unless `--delimiter` says otherwise
+    delimiter: Option<char>,
+    /// Matrix values may wrap across lines: read `n²` values in order and
+    /// ignore where the lines break
+    reflow: bool,
+    /// What repeated edges between the same cities in an edge list become
+    parallel_edges: ParallelEdges,
+}
+
+impl ParseOptions {
+    fn check_city_count(&self, n: usize) -> Result<(), String> {
+        if n == 0 {
+            return Err("Number of cities must be greater than 0".to_string());
+        }
+        match self.max_cities {
+            Some(max) if n > max => Err(format!("Safe mode: {} cities, the limit is {}", n, max)),
+            _ => Ok(()),
+        }
+    }
+
+    fn csv_delimiter(&self) -> char {
+        self.delimiter.unwrap_or(',')
+    }
+}
+
+/// One-glance characterization of a distance matrix for `--matrix-stats`.
+struct MatrixStats {
+    n: usize,
+    finite_edges: usize,
+    forbidden_edges: usize,
+    symmetric: bool,
+    min_weight: Option<Weight>,
+    max_weight: Option<Weight>,
+    mean_weight: Option<f64>,
+}
+
+impl MatrixStats {
+    fn new(dist: &[Vec<Weight>]) -> Self {
+        let n = dist.len();
+        let finite: Vec<Weight> = (0..n)
+            .flat_map(|i| (0..n).filter(move |&j| j != i).map(move |j| dist[i][j]))
+            .filter(|&d| d < INF)
+            .collect();
+        let sum: WideWeight = finite.iter().map(|&d| WideWeight::from(d)).sum();
+
+        MatrixStats {
+            n,
+            finite_edges: finite.len(),
+            forbidden_edges: n * n.saturating_sub(1) - finite.len(),
+            symmetric: is_symmetric(dist),
+            min_weight: finite.iter().copied().min(),
+            max_weight: finite.iter().copied().max(),
+            mean_weight: (!finite.is_empty()).then(|| sum as f64 / finite.len() as f64),
+        }
+    }
+
+    /// Percentage of off-diagonal entries that are finite.
+    fn density(&self) -> f64 {
+        let off_diagonal = self.n * self.n.saturating_sub(1);
+        if off_diagonal == 0 {
+            return 100.0;
+        }
+        self.finite_edges as f64 / off_diagonal as f64 * 100.0
+    }
+}
+
+fn print_matrix_stats(stats: &MatrixStats, format: WeightFormat) {
+    let render = |weight: Option<Weight>| weight.map_or("-".to_string(), |w| format.render(w));
+
+    println!("{}", "📐 Matrix Statistics:".bright_green().bold());
+    println!("   Cities:          {}", stats.n);
+    println!("   Density:         {:.1}% finite off-diagonal entries", stats.density());
+    println!(
+        "   Symmetric:       {}",
+        if stats.symmetric { "yes".green() } else { "no".yellow() }
+    );
+    println!("   Forbidden edges: {}", stats.forbidden_edges);
+    println!(
+        "   Weights:         min {}, max {}, mean {}",
+        render(stats.min_weight),
+        render(stats.max_weight),
+        stats.mean_weight.map_or("-".to_string(), |mean| format!("{:.2}", mean))
+    );
+    println!();
+}
+
+/// Reads the leading city count shared by the plain formats.
+fn plain_city_count(lines: &[(usize, &str)], parse: &ParseOptions) -> Result<usize, String> {
+    if lines.is_empty() {
+        return Err("Empty input file".to_string());
+    }
+
+    // First line should contain number of cities
+    let n: usize = lines[0].1.parse()
+        .map_err(|_| "Invalid number of cities")?;
+    parse.check_city_count(n)?;
+    Ok(n)
+}
+
+/// `n`, then one row of `n` weights per city.
+fn parse_matrix(content: &str, parse: &ParseOptions) -> Result<Vec<Vec<Weight>>, String> {
+    let lines = content_lines(content);
+    let n = plain_city_count(&lines, parse)?;
+    if parse.reflow {
+        return parse_reflowed_matrix(&lines[1..], n, parse);
+    }
+    let rows = &lines[1..];
+    if rows.len() != n {
+        return Err(format!("Expected {} matrix rows, found {}", n, rows.len()));
+    }
+
+    let mut distances = vec![vec![0; n]; n];
+    for (i, &(_, line)) in rows.iter().enumerate() {
+        let row: Option<Vec<Weight>> = line
+            .split_whitespace()
+            .map(|token| parse_weight(token, parse.weight_format))
+            .collect();
+        
+        match row {
+            Some(values) => {
+                if values.len() != n {
+                    return Err(format!("Row {} has {} values, expected {}", i, values.len(), n));
+                }
+                distances[i] = values;
+            }
+            None => return Err(format!("Invalid number in row {}", i + 1)),
+        }
+    }
+
+    Ok(distances)
+}
+
+/// `--reflow`: the matrix body as one stream of `n²` values, separated by
+/// whitespace and/or commas, filled in row by row.
+fn parse_reflowed_matrix(lines: &[(usize, &str)], n: usize, parse: &ParseOptions) -> Result<Vec<Vec<Weight>>, String> {
+    let mut values = Vec::with_capacity(n * n);
+    for &(line_num, line) in lines {
+        for token in line.split(|c: char| c == ',' || c.is_whitespace()).filter(|token| !token.is_empty()) {
+            let value = parse_weight(token, parse.weight_format)
+                .ok_or_else(|| format!("Line {}: Invalid number '{}'", line_num, token))?;
+            values.push(value);
+        }
+    }
+    if values.len() != n * n {
+        return Err(format!("Expected {} × {} = {} values, found {}", n, n, n * n, values.len()));
+    }
+    Ok(values.chunks(n).map(<[Weight]>::to_vec).collect())
+}
+
+/// `n`, then one `from to weight` edge per line; missing edges are `INF`.
+fn parse_edge_list(content: &str, parse: &ParseOptions) -> Result<Vec<Vec<Weight>>, String> {
+    let lines = content_lines(content);
+    let n = plain_city_count(&lines, parse)?;
+
+    // Initialize with infinity
+    let mut distances: Vec<Vec<Weight>> = (0..n)
+        .map(|i| (0..n).map(|j| if i == j { 0 } else { INF }).collect())
+        .collect();
+
+    // Parse edges
+    let mut seen = HashMap::new();
+    for &(line_num, line) in &lines[1..] {
+        let parts: Vec<&str> = line.split_whitespace().collect();
+        if parts.len() != 3 {
+            return Err(format!("Line {}: Expected 3 values (from to weight)", line_num));
+        }
+
+        let from: usize = parts[0].parse()
+            .map_err(|_| format!("Line {}: Invalid 'from' city", line_num))?;
+        let to: usize = parts[1].parse()
+            .map_err(|_| format!("Line {}: Invalid 'to' city", line_num))?;
+        let weight = parse_weight(parts[2], parse.weight_format)
+            .ok_or_else(|| format!("Line {}: Invalid weight", line_num))?;
+
+        if from >= n || to >= n {
+            return Err(format!("Line {}: City index out of range", line_num));
+        }
+
+        add_edge(&mut distances, &mut seen, (from, to, weight), line_num, parse)
+            .map_err(|e| format!("Line {}: {}", line_num, e))?;
+    }
+
+    Ok(distances)
+}
+
+/// A square matrix with comma-separated rows and no count line, as
+/// spreadsheets export it.
+fn parse_csv(content: &str, parse: &ParseOptions) -> Result<Vec<Vec<Weight>>, String> {
+    let rows = content_lines(content);
+    if rows.is_empty() {
+        return Err("Empty input file".to_string());
+    }
+    let n = rows.len();
+    parse.check_city_count(n)?;
+
+    let delimiter = parse.csv_delimiter();
+    rows.iter()
+        .map(|&(line_num, line)| {
+            if n > 1 && !line.contains(delimiter) {
+                return Err(format!("Line {}: No '{}' between the values", line_num, delimiter));
+            }
+            let values: Vec<Weight> = line
+                .split(delimiter)
+                .map(|cell| {
+                    parse_csv_cell(cell, parse)
+                        .map_err(|e| format!("Line {}: {}", line_num, e))
+                })
+                .collect::<Result<_, _>>()?;
+            if values.len() != n {
+                return Err(format!("Line {}: {} values, expected {}", line_num, values.len(), n));
+            }
+            Ok(values)
+        })
+        .collect()
+}
+
+/// One CSV weight. With a delimiter other than `,` the cell may be a decimal
+/// written with a comma (`12,5`); decimals are rounded by `--rounding` and
+/// rejected without it.
+fn parse_csv_cell(cell: &str, parse: &ParseOptions) -> Result<Weight, String> {
+    if let Some(weight) = parse_weight(cell, parse.weight_format) {
+        return Ok(weight);
+    }
+    let cell = cell.trim();
+    let decimal = if parse.csv_delimiter() == ',' { cell.to_string() } else { cell.replacen(',', ".", 1) };
+    match decimal.parse::<f64>() {
+        Ok(value) if value.is_finite() => parse.rounding.unwrap_or(Rounding::Exact).apply(value),
+        _ => Err(format!("Invalid number '{}'", cell)),
+    }
+}
+
+/// `n`, then one `x y` coordinate pair per city; distances are Euclidean,
+/// rounded to the nearest integer unless `--rounding` says otherwise.
+fn parse_coordinates(content: &str, parse: &ParseOptions) -> Result<CoordinateDistances, String> {
+    let lines = content_lines(content);
+    let n = plain_city_count(&lines, parse)?;
+    let rows = &lines[1..];
+    if rows.len() != n {
+        return Err(format!("Expected {} coordinate lines, found {}", n, rows.len()));
+    }
+
+    let coords = rows
+        .iter()
+        .map(|&(line_num, line)| parse_coordinate_pair(line_num, line))
+        .collect::<Result<_, String>>()?;
+
+    Ok(CoordinateDistances {
+        coords,
+        rounding: parse.rounding.unwrap_or(Rounding::Nearest),
+        metric: Metric::Euclidean,
+    })
+}
+
+fn parse_coordinate_pair(line_num: usize, line: &str) -> Result<(f64, f64), String> {
+    let parts: Vec<&str> = line.split_whitespace().collect();
+    let [x, y] = parts.as_slice() else {
+        return Err(format!("Line {}: Expected 2 values (x y)", line_num));
+    };
+    let x: f64 = x.parse().map_err(|_| format!("Line {}: Invalid x coordinate", line_num))?;
+    let y: f64 = y.parse().map_err(|_| format!("Line {}: Invalid y coordinate", line_num))?;
+    Ok((x, y))
+}
+
+/// Reads a coordinate file line by line instead of loading it whole, for
+/// instances far too big for a distance matrix. Accepts the same layout as
+/// `parse_coordinates`, optionally after a `COORDS` declaration, and shows
+/// a byte-based progress bar while reading.
+fn stream_coordinates(path: &str, parse: &ParseOptions) -> Result<CoordinateDistances, String> {
+    let file = fs::File::open(path).map_err(|e| format!("Error reading file '{}': {}", path, e))?;
+    let size = file.metadata().map(|meta| meta.len()).unwrap_or(0);
+    let pb = ProgressBar::new(size);
     pb.set_style(
         ProgressStyle::default_bar()
-            .template(
-                "{spinner:.green} [{elapsed_precise}] [{bar:40.cyan/blue}] {pos}% ({eta}) {msg}",
-            )
+            .template("{spinner:.green} [{elapsed_precise}] [{bar:40.cyan/blue}] {bytes}/{total_bytes} {msg}")
             .unwrap()
             .progress_chars("█▉▊▋▌▍▎▏  "),
     );
-    pb
+    pb.set_message("Reading coordinates...");
+
+    let mut n = None;
+    let mut coords = Vec::new();
+    let mut read = 0u64;
+    for (i, line) in BufReader::new(file).lines().enumerate() {
+        let line = line.map_err(|e| format!("Error reading file '{}': {}", path, e))?;
+        read += line.len() as u64 + 1;
+        if i % 4096 == 0 {
+            pb.set_position(read);
+        }
+
+        let line_num = i + 1;
+        let text = line.split('#').next().unwrap_or("").trim();
+        let text = if line_num == 1 { text.strip_prefix('\u{feff}').unwrap_or(text) } else { text };
+        if text.is_empty() {
+            continue;
+        }
+        match n {
+            Some(n) => {
+                if coords.len() == n {
+                    return Err(format!("Line {}: More than {} coordinate lines", line_num, n));
+                }
+                coords.push(parse_coordinate_pair(line_num, text)?);
+            }
+            None if coords.is_empty() && InputFormat::parse(text) == Ok(InputFormat::Coordinates) => {}
+            None => {
+                let count: usize = text
+                    .parse()
+                    .map_err(|_| format!("Line {}: Expected the number of cities", line_num))?;
+                parse.check_city_count(count)?;
+                coords.reserve_exact(count);
+                n = Some(count);
+            }
+        }
+    }
+    pb.finish_and_clear();
+
+    let n = n.ok_or("Empty input file")?;
+    if coords.len() != n {
+        return Err(format!("Expected {} coordinate lines, found {}", n, coords.len()));
+    }
+    Ok(CoordinateDistances {
+        coords,
+        rounding: parse.rounding.unwrap_or(Rounding::Nearest),
+        metric: Metric::Euclidean,
+    })
 }
 
-fn main() -> Result<(), Box<dyn std::error::Error>> {
-    print_banner();
-    
-    println!("🎮 Welcome to the advanced TSP Solver!");
-    println!("This program solves the Traveling Salesman Problem using Dynamic Programming with Bitmask.\n");
-    
-    println!("📋 Instructions:");
-    println!("  • Matrix format: n (first line), then n×n distance matrix");
-    println!("  • Edge format: n (first line), then edges as 'from to weight'");
-    println!("  • Use 0 for diagonal elements (city to itself)");
-    println!("  • Use INF or ∞ for unreachable paths");
-    println!("  • Cities are numbered from 0 to n-1\n");
+/// `--parallel-edge`: how an edge list's repeated edges between the same
+/// two cities (several roads, say) combine into one matrix entry. Without
+/// `--directed`, `a b` and `b a` are the same pair.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+enum ParallelEdges {
+    /// The cheapest of them
+    #[default]
+    Min,
+    Max,
+    /// All of them added up (`INF` if any is `INF`)
+    Sum,
+    /// Reject the file
+    Error,
+}
 
-    let distances = if std::env::args().len() > 1 {
-        // Command line argument provided
-        let file_path = std::env::args().nth(1).unwrap();
-        println!("📂 Reading from file: {}", file_path.bright_blue());
-        
-        let content = fs::read_to_string(&file_path)
-            .map_err(|e| format!("Error reading file '{}': {}", file_path, e))?;
-        
-        parse_input(&content)
-            .map_err(|e| format!("Error parsing input: {}", e))?
-    } else {
-        // Interactive mode
-        if get_input_method()? {
-            let file_path = get_file_path()?;
-            println!("📂 Reading matrix file...");
-            
-            let content = fs::read_to_string(&file_path)
-                .map_err(|e| format!("Error reading file: {}", e))?;
-            
-            parse_input(&content)
-                .map_err(|e| format!("Error parsing input: {}", e))?
-        } else {
-            return Err("Manual input not implemented yet".into());
+impl ParallelEdges {
+    fn parse(text: &str) -> Result<Self, String> {
+        match text.to_lowercase().as_str() {
+            "min" => Ok(ParallelEdges::Min),
+            "max" => Ok(ParallelEdges::Max),
+            "sum" => Ok(ParallelEdges::Sum),
+            "error" => Ok(ParallelEdges::Error),
+            _ => Err(format!("Unknown parallel-edge policy '{}' (expected min, max, sum or error)", text)),
+        }
+    }
+
+    /// The entry for an edge weighing `old` that meets another weighing
+    /// `new`, or `None` under `Error`.
+    fn combine(self, old: Weight, new: Weight) -> Option<Weight> {
+        match self {
+            ParallelEdges::Min => Some(old.min(new)),
+            ParallelEdges::Max => Some(old.max(new)),
+            // Kept below INF, so an overflowing sum is reported as out of range
+            ParallelEdges::Sum if old < INF && new < INF => Some(old.saturating_add(new).min(INF - 1)),
+            ParallelEdges::Sum => Some(INF),
+            ParallelEdges::Error => None,
         }
+    }
+}
+
+/// Enters the edge `from → to` read on line `line` (and its mirror when
+/// undirected), combining it with an earlier edge between the same cities
+/// by `parse.parallel_edges`. `seen` remembers where each pair came from.
+fn add_edge(
+    distances: &mut [Vec<Weight>],
+    seen: &mut HashMap<(usize, usize), usize>,
+    (from, to, weight): (usize, usize, Weight),
+    line: usize,
+    parse: &ParseOptions,
+) -> Result<(), String> {
+    let pair = if parse.directed { (from, to) } else { (from.min(to), from.max(to)) };
+    let weight = match seen.get(&pair) {
+        Some(&first) => parse.parallel_edges.combine(distances[from][to], weight).ok_or_else(|| {
+            format!(
+                "City{} and City{} already have an edge (line {}); use --parallel-edge min, max or sum to combine them",
+                from, to, first
+            )
+        })?,
+        None => weight,
     };
+    seen.entry(pair).or_insert(line);
+    distances[from][to] = weight;
+    if !parse.directed {
+        distances[to][from] = weight;
+    }
+    Ok(())
+}
 
-    println!("✅ Matrix loaded successfully!\n");
-    print_matrix(&distances);
+/// How Euclidean distances computed from coordinates become integer weights.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Rounding {
+    /// TSPLIB `nint`: round half up (`EUC_2D`)
+    Nearest,
+    /// Round up (`CEIL_2D`, `ATT`)
+    Ceil,
+    /// Round down (`GEO`)
+    Floor,
+    /// Require the distance to already be an integer
+    Exact,
+}
 
-    let n = distances.len();
-    if n > 20 {
-        println!(
-            "{}",
-            format!("⚠️  Large matrix detected ({} cities). This will take exponential time!", n)
-                .yellow()
-                .bold()
-        );
-        let continue_anyway = Confirm::with_theme(&ColorfulTheme::default())
-            .with_prompt("Continue anyway? (Not recommended for n > 20)")
-            .default(false)
-            .interact()?;
+impl Rounding {
+    fn parse(text: &str) -> Result<Self, String> {
+        match text.to_lowercase().as_str() {
+            "nearest" => Ok(Rounding::Nearest),
+            "ceil" => Ok(Rounding::Ceil),
+            "floor" => Ok(Rounding::Floor),
+            "none" => Ok(Rounding::Exact),
+            _ => Err(format!(
+                "Unknown rounding '{}' (expected nearest, ceil, floor or none)",
+                text
+            )),
+        }
+    }
 
-        if !continue_anyway {
-            println!("🛑 Operation cancelled.");
-            return Ok(());
+    /// Rounds without validation; `Exact` falls back to the nearest integer.
+    fn round(self, distance: f64) -> Weight {
+        let rounded = match self {
+            Rounding::Nearest => (distance + 0.5).floor(),
+            Rounding::Ceil => distance.ceil(),
+            Rounding::Floor => distance.floor(),
+            Rounding::Exact => distance.round(),
+        };
+        rounded as Weight
+    }
+
+    fn apply(self, distance: f64) -> Result<Weight, String> {
+        if self == Rounding::Exact && (distance - distance.round()).abs() > 1e-9 {
+            return Err(format!(
+                "Distance {:.4} is not an integer (use --rounding to pick a rounding)",
+                distance
+            ));
         }
-    } else if n > 15 {
-        println!(
-            "{}",
-            format!("⚠️  Medium-large matrix ({} cities). This may take some time.", n)
-                .yellow()
-        );
+        Ok(self.round(distance))
     }
+}
 
-    let pb = create_progress_bar();
-    let start_time = Instant::now();
-    
-    let mut solver = TSPSolver::new(distances);
-    solver.set_progress_bar(pb);
-    
-    let (min_cost, optimal_path) = solver.solve();
-    let elapsed = start_time.elapsed();
+/// Source of pairwise distances. Heuristics can work through this instead of
+/// a materialized `n×n` matrix; the exact DP still needs the full matrix.
+trait DistanceProvider {
+    fn dist(&self, i: usize, j: usize) -> Weight;
+}
 
-    print_solution(min_cost, &optimal_path, elapsed, &solver);
-    
-    println!();
-    println!("🙏 Thank you for using TSP Solver!");
-    println!("💡 Tip: For better performance with large graphs, consider approximation algorithms.");
-    
-    Ok(())
-}
\ No newline at end of file
+impl DistanceProvider for [Vec<Weight>] {
+    fn dist(&self, i: usize, j: usize) -> Weight {
+        self[i][j]
+    }
+}
+
+impl DistanceProvider for Vec<Vec<Weight>> {
+    fn dist(&self, i: usize, j: usize) -> Weight {
+        self[i][j]
+    }
+}
+
+/// How a distance is computed from two coordinate pairs, as named by the
+/// TSPLIB `EDGE_WEIGHT_TYPE`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Metric {
+    /// `EUC_2D` / `CEIL_2D`, and plain coordinate input
+    Euclidean,
+    /// `ATT`: pseudo-Euclidean, `sqrt((dx² + dy²) / 10)`
+    Att,
+    /// `GEO`: coordinates are `DDD.MM` latitude/longitude, distances are
+    /// kilometres on the TSPLIB idealized sphere
+    Geo,
+}
+
+/// Decimal degrees of a TSPLIB `DDD.MM` coordinate: the integer part is
+/// degrees and the fraction minutes. The TSPLIB reference code truncates the
+/// degrees (the spec text says `nint`), so this does too.
+fn geo_degrees(coordinate: f64) -> f64 {
+    let degrees = coordinate.trunc();
+    let minutes = coordinate - degrees;
+    degrees + 5.0 * minutes / 3.0
+}
+
+fn geo_radians(coordinate: f64) -> f64 {
+    GEO_PI * geo_degrees(coordinate) / 180.0
+}
+
+/// Computes distances from coordinates on demand, so large coordinate
+/// instances never have to hold all `n²` entries.
+struct CoordinateDistances {
+    coords: Vec<(f64, f64)>,
+    rounding: Rounding,
+    metric: Metric,
+}
+
+impl CoordinateDistances {
+    /// Unrounded distance between two cities under `metric`.
+    fn raw_distance(&self, i: usize, j: usize) -> f64 {
+        let (a, b) = (self.coords[i], self.coords[j]);
+        match self.metric {
+            Metric::Euclidean => {
+                let (dx, dy) = (a.0 - b.0, a.1 - b.1);
+                (dx * dx + dy * dy).sqrt()
+            }
+            // TSPLIB rounds this with nint and adds 1 if that fell short,
+            // which is `Rounding::Ceil`
+            Metric::Att => {
+                let (dx, dy) = (a.0 - b.0, a.1 - b.1);
+                ((dx * dx + dy * dy) / 10.0).sqrt()
+            }
+            // TSPLIB computes `(int) (RRR * acos(...) + 1.0)`; the `+ 1.0` is
+            // part of the distance, so the default `Rounding::Floor` matches
+            Metric::Geo => {
+                let (latitude_a, longitude_a) = (geo_radians(a.0), geo_radians(a.1));
+                let (latitude_b, longitude_b) = (geo_radians(b.0), geo_radians(b.1));
+                let q1 = (longitude_a - longitude_b).cos();
+                let q2 = (latitude_a - latitude_b).cos();
+                let q3 = (latitude_a + latitude_b).cos();
+                GEO_EARTH_RADIUS * (0.5 * ((1.0 + q1) * q2 - (1.0 - q1) * q3)).clamp(-1.0, 1.0).acos() + 1.0
+            }
+        }
+    }
+
+    /// Fingerprint of the coordinates, metric and rounding, so a
+    /// `--load-matrix` cache is never used for a different instance (FNV-1a).
+    fn fingerprint(&self) -> u64 {
+        let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+        let mut feed = |value: u64| {
+            for byte in value.to_le_bytes() {
+                hash ^= u64::from(byte);
+                hash = hash.wrapping_mul(0x0100_0000_01b3);
+            }
+        };
+        feed(self.coords.len() as u64);
+        feed(self.metric as u64);
+        feed(self.rounding as u64);
+        for &(x, y) in &self.coords {
+            feed(x.to_bits());
+            feed(y.to_bits());
+        }
+        hash
+    }
+
+    /// Builds the full matrix, rejecting non-integer distances under
+    /// `Rounding::Exact`, which lazy lookups can't report.
+    fn materialize(&self) -> Result<Vec<Vec<Weight>>, String> {
+        let n = self.coords.len();
+        let mut distances = vec![vec![0; n]; n];
+        for (i, row) in distances.iter_mut().enumerate() {
+            for (j, cell) in row.iter_mut().enumerate() {
+                if i != j {
+                    *cell = self.rounding.apply(self.raw_distance(i, j))?;
+                }
+            }
+        }
+        Ok(distances)
+    }
+}
+
+impl DistanceProvider for CoordinateDistances {
+    fn dist(&self, i: usize, j: usize) -> Weight {
+        if i == j {
+            return 0;
+        }
+        self.rounding.round(self.raw_distance(i, j))
+    }
+}
+
+/// `fs::read_to_string` with a short, bounded retry for transient failures,
+/// as seen on network file systems. Errors that won't go away by waiting (a
+/// missing file, no permission, not UTF-8) are returned at once.
+fn read_file(path: &str) -> std::io::Result<String> {
+    let mut delay = READ_RETRY_DELAY;
+    let mut attempt = 1;
+    loop {
+        match fs::read_to_string(path) {
+            Err(e) if attempt < READ_ATTEMPTS && is_transient(&e) => {
+                std::thread::sleep(delay);
+                delay *= 2;
+                attempt += 1;
+            }
+            result => return result,
+        }
+    }
+}
+
+fn is_transient(error: &std::io::Error) -> bool {
+    use std::io::ErrorKind;
+    !matches!(
+        error.kind(),
+        ErrorKind::NotFound
+            | ErrorKind::PermissionDenied
+            | ErrorKind::InvalidData
+            | ErrorKind::InvalidInput
+            | ErrorKind::IsADirectory
+            | ErrorKind::Unsupported
+            | ErrorKind::OutOfMemory
+    )
+}
+
+/// Where `--cache-matrix` / `--load-matrix` keep a coordinate instance's
+/// computed matrix.
+#[derive(Clone, Debug)]
+enum MatrixCache {
+    Save(String),
+    Load(String),
+}
+
+/// Writes `distances` with the fingerprint of the coordinates they came from.
+fn write_matrix_cache(path: &str, fingerprint: u64, distances: &[Vec<Weight>]) -> Result<(), String> {
+    let mut out = format!("{}\nhash {:016x}\n{}\n", MATRIX_CACHE_HEADER, fingerprint, distances.len());
+    for row in distances {
+        out += &row.iter().map(|d| d.to_string()).collect::<Vec<_>>().join(" ");
+        out += "\n";
+    }
+    fs::write(path, out).map_err(|e| format!("Error writing matrix cache '{}': {}", path, e))
+}
+
+/// `--emit-matrix`: `distances` in the plain `n` + matrix format, columns
+/// aligned, so it reads back to the same matrix with `INF` kept as `INF`.
+/// City names become trailing `# name` comments. `--time-format` weights
+/// stay `HH:MM:SS` (negative ones as seconds), so read such a file back
+/// with `--time-format` too.
+fn render_matrix_file(distances: &[Vec<Weight>], format: WeightFormat, names: Option<&[String]>) -> String {
+    let cell = |d: Weight| match format {
+        _ if d >= INF => "INF".to_string(),
+        WeightFormat::Duration if d >= 0 => format_duration(d),
+        _ => d.to_string(),
+    };
+    let cells: Vec<Vec<String>> = distances.iter().map(|row| row.iter().map(|&d| cell(d)).collect()).collect();
+    let width = cells.iter().flatten().map(String::len).max().unwrap_or(1);
+
+    let mut out = format!("{}\n", distances.len());
+    for (city, row) in cells.iter().enumerate() {
+        let line: Vec<String> = row.iter().map(|cell| format!("{:>width$}", cell, width = width)).collect();
+        out += &line.join(" ");
+        if let Some(name) = names.and_then(|names| names.get(city)) {
+            out += &format!("  # {}", name);
+        }
+        out += "\n";
+    }
+    out
+}
+
+/// Reads a matrix saved by `--cache-matrix`, refusing it unless it was made
+/// from the same coordinates, metric and rounding.
+fn read_matrix_cache(path: &str, fingerprint: u64) -> Result<Vec<Vec<Weight>>, String> {
+    let content = read_file(path).map_err(|e| format!("Error reading matrix cache '{}': {}", path, e))?;
+    let mut lines = content.lines();
+    if lines.next() != Some(MATRIX_CACHE_HEADER) {
+        return Err(format!("'{}' is not a matrix cache", path));
+    }
+    if lines.next().and_then(|line| line.strip_prefix("hash ")) != Some(format!("{:016x}", fingerprint).as_str()) {
+        return Err(format!(
+            "Matrix cache '{}' was made from other coordinates, metric or rounding; rebuild it with --cache-matrix",
+            path
+        ));
+    }
+    let n: usize = lines
+        .next()
+        .and_then(|line| line.trim().parse().ok())
+        .ok_or_else(|| format!("Matrix cache '{}' is missing its city count", path))?;
+    let distances = lines
+        .take(n)
+        .map(|line| line.split_whitespace().map(str::parse).collect::<Result<Vec<Weight>, _>>())
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|_| format!("Matrix cache '{}' has an invalid entry", path))?;
+    if distances.len() != n || distances.iter().any(|row| row.len() != n) {
+        return Err(format!("Matrix cache '{}' is truncated", path));
+    }
+    Ok(distances)
+}
+
+/// `--emit-binary`: the magic, the version and `n` as little-endian `u32`,
+/// then the `n × n` entries row by row as little-endian `i32`, `INF` as
+/// `BINARY_INF`. Weights that don't fit in 32 bits can't be written.
+fn encode_binary_matrix(distances: &[Vec<Weight>]) -> Result<Vec<u8>, String> {
+    let n = distances.len();
+    let mut bytes = Vec::with_capacity(12 + 4 * n * n);
+    bytes.extend_from_slice(BINARY_MAGIC);
+    bytes.extend_from_slice(&BINARY_VERSION.to_le_bytes());
+    bytes.extend_from_slice(&(n as u32).to_le_bytes());
+    for (i, row) in distances.iter().enumerate() {
+        for (j, &d) in row.iter().enumerate() {
+            // a no-op conversion unless `Weight` is widened by the `wide` feature
+            #[allow(clippy::useless_conversion)]
+            let cell = match i32::try_from(d) {
+                _ if d >= INF => BINARY_INF,
+                Ok(cell) if cell != BINARY_INF => cell,
+                _ => return Err(format!("dist[{}][{}] = {} doesn't fit the 32-bit binary format", i, j, d)),
+            };
+            bytes.extend_from_slice(&cell.to_le_bytes());
+        }
+    }
+    Ok(bytes)
+}
+
+fn read_binary_matrix(path: &str) -> Result<Vec<Vec<Weight>>, String> {
+    let bytes = fs::read(path).map_err(|e| format!("Error reading binary matrix '{}': {}", path, e))?;
+    decode_binary_matrix(&bytes).map_err(|e| format!("Error reading binary matrix '{}': {}", path, e))
+}
+
+/// Reads a matrix written by `encode_binary_matrix`, checking the magic,
+/// the version and the length before trusting `n`.
+fn decode_binary_matrix(bytes: &[u8]) -> Result<Vec<Vec<Weight>>, String> {
+    let word = |at: usize| bytes.get(at..at + 4).map(|word| [word[0], word[1], word[2], word[3]]);
+    if bytes.get(..4) != Some(BINARY_MAGIC.as_slice()) {
+        return Err("not a binary instance (no TSPB magic)".to_string());
+    }
+    let version = word(4).map(u32::from_le_bytes).ok_or("the header is truncated")?;
+    if version != BINARY_VERSION {
+        return Err(format!("binary format version {} is not supported (this build reads {})", version, BINARY_VERSION));
+    }
+    let n = word(8).map(u32::from_le_bytes).ok_or("the header is truncated")? as usize;
+    let expected = n.checked_mul(n).and_then(|cells| cells.checked_mul(4)).and_then(|size| size.checked_add(12));
+    match expected {
+        Some(size) if size == bytes.len() => {}
+        Some(size) => return Err(format!("{} cities need {} bytes, the file has {}", n, size, bytes.len())),
+        None => return Err(format!("{} cities don't fit in memory", n)),
+    }
+    let distances = (0..n)
+        .map(|i| {
+            (0..n)
+                .map(|j| match word(12 + 4 * (i * n + j)).map(i32::from_le_bytes) {
+                    Some(BINARY_INF) => INF,
+                    Some(cell) => cell as Weight,
+                    None => unreachable!("the length was checked above"),
+                })
+                .collect()
+        })
+        .collect();
+    Ok(distances)
+}
+
+fn is_tsplib(content: &str) -> bool {
+    content.lines().any(|line| {
+        let line = line.trim().to_uppercase();
+        line.starts_with("NODE_COORD_SECTION") || line.starts_with("EDGE_WEIGHT_TYPE")
+    })
+}
+
+/// Parses a TSPLIB file with a `NODE_COORD_SECTION` into a distance matrix.
+/// `parse.rounding` overrides the convention implied by `EDGE_WEIGHT_TYPE`.
+fn parse_tsplib(content: &str, parse: &ParseOptions) -> Result<CoordinateDistances, String> {
+    let mut dimension: Option<usize> = None;
+    let mut default_rounding = None;
+    let mut metric = Metric::Euclidean;
+    let mut coords: Vec<(f64, f64)> = Vec::new();
+    let mut in_coords = false;
+
+    for (line_num, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        let upper = line.to_uppercase();
+        if upper == "EOF" {
+            break;
+        }
+        if upper.starts_with("NODE_COORD_SECTION") {
+            in_coords = true;
+            continue;
+        }
+
+        if in_coords {
+            let parts: Vec<&str> = line.split_whitespace().collect();
+            if parts.len() != 3 {
+                return Err(format!("Line {}: Expected 'id x y'", line_num + 1));
+            }
+            let x: f64 = parts[1].parse()
+                .map_err(|_| format!("Line {}: Invalid x coordinate", line_num + 1))?;
+            let y: f64 = parts[2].parse()
+                .map_err(|_| format!("Line {}: Invalid y coordinate", line_num + 1))?;
+            coords.push((x, y));
+            continue;
+        }
+
+        let Some((key, value)) = line.split_once(':') else {
+            return Err(format!("Line {}: Unexpected content '{}'", line_num + 1, line));
+        };
+        let value = value.trim();
+        match key.trim().to_uppercase().as_str() {
+            "DIMENSION" => {
+                dimension = Some(value.parse()
+                    .map_err(|_| format!("Line {}: Invalid DIMENSION", line_num + 1))?);
+            }
+            "EDGE_WEIGHT_TYPE" => {
+                (default_rounding, metric) = match value.to_uppercase().as_str() {
+                    "EUC_2D" => (Some(Rounding::Nearest), Metric::Euclidean),
+                    "CEIL_2D" => (Some(Rounding::Ceil), Metric::Euclidean),
+                    "ATT" => (Some(Rounding::Ceil), Metric::Att),
+                    "GEO" => (Some(Rounding::Floor), Metric::Geo),
+                    other => return Err(format!("Unsupported EDGE_WEIGHT_TYPE '{}'", other)),
+                };
+            }
+            // NAME, TYPE, COMMENT and friends don't affect the distances
+            _ => {}
+        }
+    }
+
+    let n = dimension.ok_or("Missing DIMENSION")?;
+    parse.check_city_count(n)?;
+    if coords.len() != n {
+        return Err(format!("DIMENSION is {} but {} coordinates were given", n, coords.len()));
+    }
+    let rounding = parse
+        .rounding
+        .or(default_rounding)
+        .ok_or("Missing EDGE_WEIGHT_TYPE")?;
+
+    Ok(CoordinateDistances { coords, rounding, metric })
+}
+
+/// The instance file layouts `InstanceLoader` understands.
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum InputFormat {
+    /// `n`, then an `n × n` matrix
+    Matrix,
+    /// `n`, then `from to weight` lines
+    EdgeList,
+    /// Comma-separated square matrix without a count line
+    Csv,
+    /// TSPLIB `NODE_COORD_SECTION` file
+    Tsplib,
+    /// `n`, then `x y` lines
+    Coordinates,
+}
+
+impl InputFormat {
+    fn parse(text: &str) -> Result<Self, String> {
+        match text.to_lowercase().as_str() {
+            "matrix" => Ok(InputFormat::Matrix),
+            "edges" | "edge-list" => Ok(InputFormat::EdgeList),
+            "csv" => Ok(InputFormat::Csv),
+            "tsplib" => Ok(InputFormat::Tsplib),
+            "coords" | "coordinates" => Ok(InputFormat::Coordinates),
+            other => Err(format!(
+                "Unknown format '{}' (expected matrix, edges, csv, tsplib or coords)",
+                other
+            )),
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            InputFormat::Matrix => "matrix",
+            InputFormat::EdgeList => "edge list",
+            InputFormat::Csv => "CSV",
+            InputFormat::Tsplib => "TSPLIB",
+            InputFormat::Coordinates => "coordinates",
+        }
+    }
+
+    /// Guesses the format from the content alone. The plain formats are told
+    /// apart by their shape: `n + 1` lines are a matrix, or coordinates when
+    /// every row is a pair (only possible for 3+ cities); anything else with
+    /// a count line is an edge list.
+    fn detect(content: &str) -> Self {
+        if is_tsplib(content) {
+            return InputFormat::Tsplib;
+        }
+        let lines = content_lines(content);
+        if lines.first().is_some_and(|&(_, line)| line.contains(',')) {
+            return InputFormat::Csv;
+        }
+        let n = lines.first().and_then(|&(_, line)| line.parse::<usize>().ok());
+        match n {
+            Some(n) if lines.len() == n + 1 => {
+                let pairs = lines[1..].iter().all(|&(_, line)| line.split_whitespace().count() == 2);
+                if n > 2 && pairs {
+                    InputFormat::Coordinates
+                } else {
+                    InputFormat::Matrix
+                }
+            }
+            _ => InputFormat::EdgeList,
+        }
+    }
+}
+
+/// A loaded instance and the format it was read as.
+struct ParsedInstance {
+    distances: Vec<Vec<Weight>>,
+    format: InputFormat,
+    /// City positions, for the coordinate formats
+    coords: Option<Vec<(f64, f64)>>,
+    /// How `coords` turn into distances
+    metric: Metric,
+    /// City names from a header row or index column
+    names: Option<Vec<String>>,
+}
+
+/// Removes a header row and/or index column from matrix or CSV content and
+/// returns the remaining data plus the city names they held (the header row
+/// wins when both are given). Comments are dropped; line numbers are kept.
+fn strip_headers(
+    content: &str,
+    csv: Option<char>,
+    header: bool,
+    index_col: bool,
+) -> Result<(String, Vec<String>), String> {
+    let split = |line: &str| -> Vec<String> {
+        if let Some(delimiter) = csv {
+            line.split(delimiter).map(|cell| cell.trim().to_string()).collect()
+        } else {
+            line.split_whitespace().map(str::to_string).collect()
+        }
+    };
+    let mut out = vec![String::new(); content.lines().count()];
+    let lines = content_lines(content);
+
+    // The matrix format keeps its count line in front of the header
+    let mut rows = if csv.is_some() { &lines[..] } else { lines.get(1..).unwrap_or(&[]) };
+    if let (None, Some(&(line_no, text))) = (csv, lines.first()) {
+        out[line_no - 1] = text.to_string();
+    }
+
+    let mut names = None;
+    if header {
+        let (&(_, text), data) = rows.split_first().ok_or("Missing header row")?;
+        let mut cells = split(text);
+        if index_col && cells.len() == data.len() + 1 {
+            cells.remove(0); // Corner cell above the index column
+        }
+        if cells.len() != data.len() {
+            return Err(format!("Header has {} names but there are {} rows", cells.len(), data.len()));
+        }
+        names = Some(cells);
+        rows = data;
+    }
+
+    let mut index_names = Vec::new();
+    for &(line_no, text) in rows {
+        let mut cells = split(text);
+        if index_col {
+            if cells.is_empty() {
+                return Err(format!("Line {}: Missing index column", line_no));
+            }
+            index_names.push(cells.remove(0));
+        }
+        out[line_no - 1] = match csv {
+            Some(delimiter) => cells.join(&delimiter.to_string()),
+            None => cells.join(" "),
+        };
+    }
+
+    // Without a header row the names come from the index column
+    Ok((out.join("\n"), names.unwrap_or(index_names)))
+}
+
+/// City names from trailing `# name` comments, when every matrix row has
+/// one; rows without a comment mean the comments are just annotations.
+fn row_comment_names(content: &str, n: usize) -> Option<Vec<String>> {
+    let raw: Vec<&str> = content.lines().collect();
+    let lines = content_lines(content);
+    let rows = lines.get(1..=n)?;
+    rows.iter()
+        .map(|&(line_no, _)| {
+            let (_, comment) = raw[line_no - 1].split_once('#')?;
+            let name = comment.trim();
+            (!name.is_empty()).then(|| name.to_string())
+        })
+        .collect()
+}
+
+/// Reads instance files in any supported format. The format comes from, in
+/// order: `set_format`, a declaration line (`MATRIX`, `EDGES`, `CSV`,
+/// `COORDS`) before the data, or detection from the content's shape.
+struct InstanceLoader {
+    parse: ParseOptions,
+    format: Option<InputFormat>,
+    /// Refuse to guess: the format must be forced, declared or TSPLIB
+    strict: bool,
+    /// The data starts with a row of city names
+    header: bool,
+    /// Every data row starts with its city's name
+    index_col: bool,
+    /// Save or reuse the matrix computed from coordinates
+    matrix_cache: Option<MatrixCache>,
+}
+
+impl InstanceLoader {
+    fn new(parse: ParseOptions) -> Self {
+        InstanceLoader {
+            parse,
+            format: None,
+            strict: false,
+            header: false,
+            index_col: false,
+            matrix_cache: None,
+        }
+    }
+
+    /// Skip detection and always read `format`.
+    fn set_format(&mut self, format: InputFormat) {
+        self.format = Some(format);
+    }
+
+    fn set_strict(&mut self, strict: bool) {
+        self.strict = strict;
+    }
+
+    /// Matrix and CSV input with a header row and/or an index column.
+    fn set_headers(&mut self, header: bool, index_col: bool) {
+        self.header = header;
+        self.index_col = index_col;
+    }
+
+    /// Coordinate input only: write the computed matrix to a cache file, or
+    /// read it back from one instead of computing it.
+    fn set_matrix_cache(&mut self, cache: MatrixCache) {
+        self.matrix_cache = Some(cache);
+    }
+
+    /// Splits off a format declaration on the first content line. The line
+    /// is blanked rather than removed so error line numbers still match.
+    fn take_declaration(content: &str) -> (Option<InputFormat>, std::borrow::Cow<'_, str>) {
+        let Some(&(line_no, first)) = content_lines(content).first() else {
+            return (None, content.into());
+        };
+        match InputFormat::parse(first) {
+            Ok(format) if format != InputFormat::Tsplib => {
+                let body: Vec<&str> = content
+                    .lines()
+                    .enumerate()
+                    .map(|(i, line)| if i + 1 == line_no { "" } else { line })
+                    .collect();
+                (Some(format), body.join("\n").into())
+            }
+            _ => (None, content.into()),
+        }
+    }
+
+    fn parse(&self, content: &str) -> Result<ParsedInstance, String> {
+        let (declared, content) = Self::take_declaration(content);
+        let content = content.as_ref();
+
+        if self.header || self.index_col {
+            // A header row throws off shape detection, so only the delimiter decides
+            let delimiter = self.parse.csv_delimiter();
+            let delimited = content_lines(content).iter().any(|&(_, line)| line.contains(delimiter));
+            let format =
+                self.format.or(declared).unwrap_or(if delimited { InputFormat::Csv } else { InputFormat::Matrix });
+            if !matches!(format, InputFormat::Matrix | InputFormat::Csv) {
+                return Err("A header row or index column only works with matrix or CSV input".to_string());
+            }
+            let csv = (format == InputFormat::Csv).then_some(delimiter);
+            let (content, names) = strip_headers(content, csv, self.header, self.index_col)?;
+            let mut instance = self.parse_as(&content, format)?;
+            instance.names = Some(names);
+            return Ok(instance);
+        }
+
+        let format = match self.format.or(declared) {
+            Some(format) => format,
+            None if is_tsplib(content) => InputFormat::Tsplib,
+            // Only CSV has a delimiter to configure, only a matrix reflows
+            None if self.parse.delimiter.is_some() => InputFormat::Csv,
+            None if self.parse.reflow => InputFormat::Matrix,
+            None if self.strict => {
+                return Err(
+                    "Strict format: declare the format on the first line (MATRIX, EDGES, CSV or COORDS) or pass --format"
+                        .to_string(),
+                );
+            }
+            None => InputFormat::detect(content),
+        };
+        let mut instance = self.parse_as(content, format)?;
+        if format == InputFormat::Matrix {
+            instance.names = row_comment_names(content, instance.distances.len());
+        }
+        Ok(instance)
+    }
+
+    fn parse_as(&self, content: &str, format: InputFormat) -> Result<ParsedInstance, String> {
+        if self.matrix_cache.is_some() && !matches!(format, InputFormat::Tsplib | InputFormat::Coordinates) {
+            return Err("A matrix cache only works with coordinate (TSPLIB or coords) input".to_string());
+        }
+        let coordinates = match format {
+            InputFormat::Matrix => return Self::plain(parse_matrix(content, &self.parse)?, format),
+            InputFormat::EdgeList => return Self::plain(parse_edge_list(content, &self.parse)?, format),
+            InputFormat::Csv => return Self::plain(parse_csv(content, &self.parse)?, format),
+            InputFormat::Tsplib => parse_tsplib(content, &self.parse)?,
+            InputFormat::Coordinates => parse_coordinates(content, &self.parse)?,
+        };
+        let distances = match &self.matrix_cache {
+            Some(MatrixCache::Load(path)) => read_matrix_cache(path, coordinates.fingerprint())?,
+            Some(MatrixCache::Save(path)) => {
+                let distances = coordinates.materialize()?;
+                write_matrix_cache(path, coordinates.fingerprint(), &distances)?;
+                distances
+            }
+            None => coordinates.materialize()?,
+        };
+        Ok(ParsedInstance {
+            distances,
+            format,
+            coords: Some(coordinates.coords),
+            metric: coordinates.metric,
+            names: None,
+        })
+    }
+
+    fn plain(distances: Vec<Vec<Weight>>, format: InputFormat) -> Result<ParsedInstance, String> {
+        Ok(ParsedInstance { distances, format, coords: None, metric: Metric::Euclidean, names: None })
+    }
+
+    /// Loads an instance from `path`, or from standard input for `-`.
+    fn load_file(&self, path: &str) -> Result<ParsedInstance, String> {
+        let content = if path == "-" {
+            std::io::read_to_string(std::io::stdin()).map_err(|e| format!("Error reading standard input: {}", e))?
+        } else {
+            read_file(path).map_err(|e| format!("Error reading file '{}': {}", path, e))?
+        };
+        // Editors on Windows like to start UTF-8 files with a byte order mark
+        let content = content.strip_prefix('\u{feff}').unwrap_or(&content);
+        self.parse(content)
+            .map_err(|e| format!("Error parsing input: {}", e))
+    }
+
+    /// Loads an instance given inline as base64 with `--b64`.
+    fn load_base64(&self, text: &str) -> Result<ParsedInstance, String> {
+        let bytes = decode_base64(text).map_err(|e| format!("Error decoding --b64: {}", e))?;
+        let content = String::from_utf8(bytes)
+            .map_err(|_| "Error decoding --b64: the decoded instance is not UTF-8 text".to_string())?;
+        let content = content.strip_prefix('\u{feff}').unwrap_or(&content);
+        self.parse(content)
+            .map_err(|e| format!("Error parsing input: {}", e))
+    }
+}
+
+/// Decodes standard (`+/`) or URL-safe (`-_`) base64. Trailing `=` padding
+/// is optional and whitespace is ignored, so wrapped strings work too.
+fn decode_base64(text: &str) -> Result<Vec<u8>, String> {
+    let mut bytes = Vec::with_capacity(text.len() / 4 * 3);
+    let (mut buffer, mut bits) = (0u32, 0);
+    for (i, c) in text.trim_end_matches(|c: char| c == '=' || c.is_whitespace()).chars().enumerate() {
+        let value = match c {
+            'A'..='Z' => c as u32 - 'A' as u32,
+            'a'..='z' => c as u32 - 'a' as u32 + 26,
+            '0'..='9' => c as u32 - '0' as u32 + 52,
+            '+' | '-' => 62,
+            '/' | '_' => 63,
+            c if c.is_whitespace() => continue,
+            _ => return Err(format!("invalid character '{}' at position {}", c, i + 1)),
+        };
+        buffer = (buffer << 6) | value;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            bytes.push((buffer >> bits) as u8);
+            buffer &= (1 << bits) - 1;
+        }
+    }
+    // A lone last character holds 6 bits, less than a byte
+    if bits == 6 {
+        return Err("the string is truncated (its length is one more than a multiple of 4)".to_string());
+    }
+    Ok(bytes)
+}
+
+fn format_path(path: &[usize]) -> String {
+    render_path(path, false)
+}
+
+/// `format_path` for the report, with the depot marked when `depot_marker`
+/// is set. Only the depot's first visit is marked, with 🏠 and the depot
+/// color, so a closed tour shows it once, at the start.
+fn render_path(path: &[usize], depot_marker: bool) -> String {
+    let depot = depot_stop(path, depot_marker);
+    path.iter()
+        .enumerate()
+        .map(|(stop, &i)| if Some(stop) == depot { depot_label(i).to_string() } else { format!("City{}", i) })
+        .collect::<Vec<_>>()
+        .join(" → ")
+}
+
+/// Position in `path` that `render_path` and the detailed route mark as the
+/// depot: its first visit, or none without `depot_marker`.
+fn depot_stop(path: &[usize], depot_marker: bool) -> Option<usize> {
+    path.iter().position(|&city| city == DEPOT).filter(|_| depot_marker)
+}
+
+fn depot_label(city: usize) -> ColoredString {
+    format!("🏠City{}", city).bright_yellow()
+}
+
+/// Parses a comma-separated list of city indices such as `0,3,1,2`.
+fn parse_city_list(text: &str) -> Result<Vec<usize>, String> {
+    text.split(',')
+        .map(|s| {
+            s.trim()
+                .parse::<usize>()
+                .map_err(|_| format!("Invalid city '{}'", s.trim()))
+        })
+        .collect()
+}
+
+/// `--leg-profile`: comma-separated whole multipliers of at least 1, one
+/// per leg in tour order.
+fn parse_leg_profile(text: &str) -> Result<Vec<Weight>, String> {
+    text.split(',')
+        .map(|s| {
+            s.trim()
+                .parse::<Weight>()
+                .ok()
+                .filter(|&multiplier| multiplier >= 1)
+                .ok_or_else(|| format!("Invalid leg multiplier '{}': expected a whole number of at least 1", s.trim()))
+        })
+        .collect()
+}
+
+/// The cities left after `--skip`, so results on the reduced matrix can be
+/// reported with the original indices.
+struct CitySubset {
+    /// `kept[i]` is the original index of reduced city `i`
+    kept: Vec<usize>,
+}
+
+impl CitySubset {
+    /// Drops the skipped cities from the matrix. The start city must stay.
+    fn reduce(dist: &[Vec<Weight>], skip: &[usize]) -> Result<(Vec<Vec<Weight>>, Self), String> {
+        let n = dist.len();
+        if let Some(&city) = skip.iter().find(|&&city| city >= n) {
+            return Err(format!("Cannot skip City{}: out of range (0 to {})", city, n - 1));
+        }
+        if skip.contains(&0) {
+            return Err("Cannot skip City0: it is the start city".to_string());
+        }
+
+        let kept: Vec<usize> = (0..n).filter(|city| !skip.contains(city)).collect();
+        let reduced = kept
+            .iter()
+            .map(|&i| kept.iter().map(|&j| dist[i][j]).collect())
+            .collect();
+        Ok((reduced, CitySubset { kept }))
+    }
+
+    fn to_original(&self, path: &[usize]) -> Vec<usize> {
+        path.iter().map(|&city| self.kept[city]).collect()
+    }
+
+    fn to_reduced(&self, path: &[usize]) -> Result<Vec<usize>, String> {
+        path.iter()
+            .map(|&city| {
+                self.kept
+                    .iter()
+                    .position(|&kept| kept == city)
+                    .ok_or_else(|| format!("City {} is skipped", city))
+            })
+            .collect()
+    }
+}
+
+/// Checks that `tour` visits every city exactly once and returns the closed
+/// path (start city repeated at the end). The closing city may be omitted.
+fn validate_tour(tour: &[usize], n: usize) -> Result<Vec<usize>, String> {
+    if tour.is_empty() {
+        return Err("Tour is empty".to_string());
+    }
+
+    let start = tour[0];
+    let mut cities = tour;
+    if cities.len() > 1 && cities[cities.len() - 1] == start {
+        cities = &cities[..cities.len() - 1];
+    }
+
+    let mut seen = vec![false; n];
+    for &city in cities {
+        if city >= n {
+            return Err(format!("City {} is out of range (0 to {})", city, n - 1));
+        }
+        if seen[city] {
+            return Err(format!("City {} is visited more than once", city));
+        }
+        seen[city] = true;
+    }
+
+    if let Some(missing) = seen.iter().position(|&visited| !visited) {
+        return Err(format!("City {} is missing from the tour", missing));
+    }
+
+    let mut path = cities.to_vec();
+    path.push(start);
+    Ok(path)
+}
+
+fn is_symmetric(dist: &[Vec<Weight>]) -> bool {
+    let n = dist.len();
+    (0..n).all(|i| (i + 1..n).all(|j| dist[i][j] == dist[j][i]))
+}
+
+/// True when every edge between distinct cities is finite.
+fn is_complete(dist: &[Vec<Weight>]) -> bool {
+    dist.iter()
+        .enumerate()
+        .all(|(i, row)| row.iter().enumerate().all(|(j, &d)| i == j || d < INF))
+}
+
+/// Rejects weights whose tour sums could reach the `±INF` sentinels. Every
+/// partial or complete tour has fewer than `n` legs, so keeping `n` times the
+/// largest finite weight below `INF` means a real cost is never mistaken for
+/// a forbidden or infeasible one, however negative the sums get.
+///
+/// Negative weights (profits) are otherwise fine: a tour visits each city
+/// once, so a negative cycle can't be repeated to drive the cost down.
+fn check_weight_range(dist: &[Vec<Weight>]) -> Result<(), String> {
+    let n = dist.len().max(1) as WideWeight;
+    let limit = (WideWeight::from(INF) - 1) / n;
+    for (i, row) in dist.iter().enumerate() {
+        for (j, &d) in row.iter().enumerate() {
+            if i != j && d < INF && WideWeight::from(d).abs() > limit {
+                return Err(format!(
+                    "Weight {} from City{} to City{} is out of range: with {} cities weights must stay within ±{}",
+                    d, i, j, n, limit
+                ));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Number of finite negative weights between distinct cities.
+fn count_negative_edges(dist: &[Vec<Weight>]) -> usize {
+    dist.iter()
+        .enumerate()
+        .map(|(i, row)| row.iter().enumerate().filter(|&(j, &d)| i != j && d < 0).count())
+        .sum()
+}
+
+/// An advisory noticed while loading or solving. Repeated in the "Warnings"
+/// section at the end of the run and written to `--stats-json`, so it isn't
+/// lost in the scrollback.
+#[derive(Clone, Serialize)]
+struct Warning {
+    /// Stable identifier, e.g. `nonzero-diagonal`
+    kind: &'static str,
+    message: String,
+}
+
+impl Warning {
+    fn new(kind: &'static str, message: String) -> Self {
+        Warning { kind, message }
+    }
+}
+
+/// The first `(i, j, k)` among finite edges with City i → City k longer than
+/// going via City j, or `None` when the matrix is metric. Takes `O(n³)`.
+fn triangle_violation(dist: &[Vec<Weight>]) -> Option<(usize, usize, usize)> {
+    let n = dist.len();
+    // Both legs are within check_weight_range, so their sum can't overflow
+    let finite = |d: Weight| d < INF;
+    (0..n)
+        .flat_map(|i| (0..n).flat_map(move |j| (0..n).map(move |k| (i, j, k))))
+        .find(|&(i, j, k)| {
+            i != j && j != k && i != k
+                && finite(dist[i][j])
+                && finite(dist[j][k])
+                && finite(dist[i][k])
+                && dist[i][k] > dist[i][j] + dist[j][k]
+        })
+}
+
+/// Advisories about the matrix itself: nonzero diagonal entries (never
+/// used), direction-dependent weights without `--directed`, and triangle
+/// inequality violations (checked up to `METRIC_CHECK_MAX_CITIES`).
+fn instance_warnings(dist: &[Vec<Weight>], directed: bool, format: WeightFormat) -> Vec<Warning> {
+    let n = dist.len();
+    let render = |d: Weight| if d >= INF { "INF".to_string() } else { format.render(d) };
+    let mut warnings = Vec::new();
+
+    let diagonal = (0..n).filter(|&i| dist[i][i] != 0).count();
+    if diagonal > 0 {
+        warnings.push(Warning::new(
+            "nonzero-diagonal",
+            format!("Nonzero diagonal entries: {}; a city's distance to itself is never used", diagonal),
+        ));
+    }
+
+    if !directed {
+        let pair = (0..n).flat_map(|i| (i + 1..n).map(move |j| (i, j))).find(|&(i, j)| dist[i][j] != dist[j][i]);
+        if let Some((i, j)) = pair {
+            warnings.push(Warning::new(
+                "asymmetric",
+                format!(
+                    "Weights depend on direction (City{} → City{} is {}, back is {}); pass --directed to also cost the tour in reverse",
+                    i,
+                    j,
+                    render(dist[i][j]),
+                    render(dist[j][i])
+                ),
+            ));
+        }
+    }
+
+    if n <= METRIC_CHECK_MAX_CITIES {
+        if let Some((i, j, k)) = triangle_violation(dist) {
+            warnings.push(Warning::new(
+                "non-metric",
+                format!(
+                    "Not metric: City{} → City{} ({}) is longer than going via City{} ({}); exact results are unaffected, but heuristics and bounds may be weaker",
+                    i,
+                    k,
+                    render(dist[i][k]),
+                    j,
+                    render(dist[i][j] + dist[j][k])
+                ),
+            ));
+        }
+    }
+    warnings
+}
+
+/// All-pairs shortest paths over the finite edges (Floyd–Warshall). Its
+/// distance matrix is the metric closure of the input graph.
+struct ShortestPaths {
+    dist: Vec<Vec<Weight>>,
+    /// `next[i][j]` is the first hop on a shortest path from `i` to `j`
+    next: Vec<Vec<Option<usize>>>,
+}
+
+impl ShortestPaths {
+    /// Fails on a negative cycle, where shortest paths have no minimum.
+    fn new(dist: &[Vec<Weight>]) -> Result<Self, String> {
+        let n = dist.len();
+        let mut closure = dist.to_vec();
+        let mut next = vec![vec![None; n]; n];
+        for (i, row) in next.iter_mut().enumerate() {
+            for (j, hop) in row.iter_mut().enumerate() {
+                if closure[i][j] < INF {
+                    *hop = Some(j);
+                }
+            }
+        }
+
+        for k in 0..n {
+            for i in 0..n {
+                if closure[i][k] >= INF {
+                    continue;
+                }
+                for j in 0..n {
+                    if closure[k][j] >= INF {
+                        continue;
+                    }
+                    let through_k = closure[i][k].saturating_add(closure[k][j]);
+                    if through_k < closure[i][j] {
+                        closure[i][j] = through_k;
+                        next[i][j] = next[i][k];
+                    }
+                }
+            }
+        }
+
+        if let Some(city) = (0..n).find(|&i| closure[i][i] < 0) {
+            return Err(format!(
+                "Negative cycle through City{}: shortest paths are unbounded, so --shortest-path-complete can't be used",
+                city
+            ));
+        }
+        Ok(ShortestPaths { dist: closure, next })
+    }
+
+    /// Cities on a shortest path from `from` to `to`, both ends included.
+    fn hops(&self, from: usize, to: usize) -> Vec<usize> {
+        let mut hops = vec![from];
+        let mut current = from;
+        while current != to {
+            match self.next[current][to] {
+                Some(hop) => {
+                    hops.push(hop);
+                    current = hop;
+                }
+                None => break,
+            }
+        }
+        hops
+    }
+}
+
+fn print_expanded_legs(closure: &ShortestPaths, path: &[usize], format: WeightFormat) {
+    let multi_hop: Vec<Vec<usize>> = path
+        .windows(2)
+        .map(|leg| closure.hops(leg[0], leg[1]))
+        .filter(|hops| hops.len() > 2)
+        .collect();
+    if multi_hop.is_empty() {
+        return;
+    }
+
+    println!("{}", "🧭 Multi-hop Legs (shortest-path closure):".bright_magenta().bold());
+    for hops in multi_hop {
+        let (from, to) = (hops[0], hops[hops.len() - 1]);
+        println!(
+            "   {} → {}: {} (distance: {})",
+            format!("City{}", from).bright_cyan(),
+            format!("City{}", to).bright_cyan(),
+            format_path(&hops),
+            format.render(closure.dist[from][to]).yellow()
+        );
+    }
+    println!();
+}
+
+/// Whether a DP result means no tour exists, in either optimization direction.
+fn is_infeasible(cost: Weight) -> bool {
+    cost >= INF || cost <= -INF
+}
+
+/// False when every off-diagonal entry is `INF`, usually a matrix that was
+/// never filled in.
+fn has_finite_edge(dist: &[Vec<Weight>]) -> bool {
+    dist.iter().enumerate().any(|(i, row)| row.iter().enumerate().any(|(j, &d)| i != j && d < INF))
+}
+
+/// Sums the legs of `path` in order, or returns `INF` if any leg is
+/// forbidden. Whole tours go through `tour_cost`, which also closes them.
+fn path_cost<D: DistanceProvider + ?Sized>(dist: &D, path: &[usize]) -> Weight {
+    let mut total: Weight = 0;
+    for leg in path.windows(2) {
+        let distance = dist.dist(leg[0], leg[1]);
+        if distance >= INF {
+            return INF;
+        }
+        total = total.saturating_add(distance);
+    }
+    total
+}
+
+/// Scores any tour through `dist`: its legs in order plus the edge back to
+/// its first city, which is only added when `path` doesn't already end
+/// there. `None` if any leg, the closing one included, is forbidden.
+fn tour_cost<D: DistanceProvider + ?Sized>(dist: &D, path: &[usize]) -> Option<Weight> {
+    let back = match (path.first(), path.last()) {
+        (Some(&first), Some(&last)) if first != last => dist.dist(last, first),
+        _ => 0,
+    };
+    let legs = path_cost(dist, path);
+    // path_cost saturates, so a sum that reaches INF is as forbidden as a leg
+    Some(legs.saturating_add(back)).filter(|&total| legs < INF && back < INF && total < INF)
+}
+
+/// `TSPSolver::solve_heuristic_from` without a solver, for `--start-tour`
+/// on instances too large for the DP's bitmasks.
+fn improve_tour(dist: &[Vec<Weight>], initial: Vec<usize>, maximize: bool) -> Result<(Weight, Vec<usize>), String> {
+    if initial.first() != Some(&0) {
+        return Err("The start tour must begin at City0".to_string());
+    }
+    let path = two_opt(dist, validate_tour(&initial, dist.len())?, maximize);
+    Ok((tour_cost(dist, &path).unwrap_or(INF), path))
+}
+
+/// `--restarts`: 2-opt from `first`, then from `restarts - 1` random tours
+/// drawn from `seed`. Returns the best tour, its cost and how many restarts
+/// ended on that same tour (either direction on symmetric instances), a
+/// rough confidence signal: a local optimum most restarts agree on is less
+/// likely to be beaten. The same seed draws the same tours, so more
+/// restarts never give a worse best.
+fn restart_two_opt(
+    dist: &[Vec<Weight>],
+    first: Vec<usize>,
+    restarts: usize,
+    maximize: bool,
+    seed: u64,
+) -> Result<(Weight, Vec<usize>, usize), String> {
+    let n = dist.len();
+    let symmetric = is_symmetric(dist);
+    let canonical = |path: &[usize]| {
+        let reversed: Vec<usize> = path.iter().rev().copied().collect();
+        if symmetric { path.to_vec().min(reversed) } else { path.to_vec() }
+    };
+    let mut rng = SplitMix64(seed);
+    let mut runs = vec![improve_tour(dist, first, maximize)?];
+    for _ in 1..restarts {
+        let mut tour: Vec<usize> = (0..n).collect();
+        for i in (2..n).rev() {
+            tour.swap(i, 1 + rng.below(i as u64) as usize);
+        }
+        tour.push(0);
+        runs.push(improve_tour(dist, tour, maximize)?);
+    }
+    let better = |a: Weight, b: Weight| if maximize { a > b } else { a < b };
+    let mut best = 0;
+    for (run, (cost, _)) in runs.iter().enumerate() {
+        if better(*cost, runs[best].0) {
+            best = run;
+        }
+    }
+    let target = canonical(&runs[best].1);
+    let agreeing = runs.iter().filter(|(_, path)| canonical(path) == target).count();
+    let (cost, path) = runs.swap_remove(best);
+    Ok((cost, path, agreeing))
+}
+
+/// `TSPSolver::held_karp_bound` without a solver.
+fn held_karp_bound(dist: &[Vec<Weight>], iterations: usize) -> Weight {
+    let n = dist.len();
+    let upper = two_opt(dist, (0..n).chain([0]).collect(), false);
+    held_karp_lower_bound(dist, n, iterations, tour_cost(dist, &upper))
+}
+
+/// Improves a closed tour with 2-opt moves (reversing the stretch between
+/// two positions) until no move helps, keeping City0 first and last.
+///
+/// Forbidden edges count as a penalty larger than any finite tour, so an
+/// infeasible start tour is first repaired as far as 2-opt can. On asymmetric
+/// instances a reversal also flips every leg inside the stretch, which the
+/// move gain accounts for.
+fn two_opt<D: DistanceProvider + ?Sized>(dist: &D, mut path: Vec<usize>, maximize: bool) -> Vec<usize> {
+    let n = path.len() - 1;
+    let penalty = (n as WideWeight + 1) * WideWeight::from(INF);
+    // Lower is better in both objectives
+    let key = |from: usize, to: usize| {
+        let d = dist.dist(from, to);
+        if d >= INF {
+            penalty
+        } else if maximize {
+            -WideWeight::from(d)
+        } else {
+            WideWeight::from(d)
+        }
+    };
+    let symmetric = (0..n).all(|i| (i + 1..n).all(|j| dist.dist(i, j) == dist.dist(j, i)));
+
+    let mut improved = true;
+    while improved {
+        improved = false;
+        for i in 1..n.saturating_sub(1) {
+            for j in i + 1..n {
+                let (a, b, c, d) = (path[i - 1], path[i], path[j], path[j + 1]);
+                let mut gain = key(a, b) + key(c, d) - key(a, c) - key(b, d);
+                if !symmetric {
+                    gain += (i..j)
+                        .map(|k| key(path[k], path[k + 1]) - key(path[k + 1], path[k]))
+                        .sum::<WideWeight>();
+                }
+                if gain > 0 {
+                    path[i..=j].reverse();
+                    improved = true;
+                }
+            }
+        }
+    }
+    path
+}
+
+/// Minimum 1-tree under node potentials `pi`: a spanning tree on cities
+/// `1..n` plus City0's two cheapest edges. Edge costs are
+/// `min(d(i,j), d(j,i)) + pi[i] + pi[j]`, so the bound also holds for
+/// asymmetric instances. Returns the cost and every city's degree, or `None`
+/// when forbidden edges leave no 1-tree (and so no tour).
+fn min_one_tree<D: DistanceProvider + ?Sized>(dist: &D, n: usize, pi: &[f64]) -> Option<(f64, Vec<usize>)> {
+    let weight = |i: usize, j: usize| {
+        let d = dist.dist(i, j).min(dist.dist(j, i));
+        (d < INF).then(|| d as f64 + pi[i] + pi[j])
+    };
+
+    // Prim's algorithm over cities 1..n, O(n²) without a heap
+    let mut degree = vec![0; n];
+    let mut in_tree = vec![false; n];
+    let mut best: Vec<(f64, usize)> = vec![(f64::INFINITY, 0); n];
+    let mut total = 0.0;
+    best[1] = (0.0, 1);
+    for _ in 1..n {
+        let next = (1..n)
+            .filter(|&c| !in_tree[c])
+            .min_by(|&a, &b| best[a].0.total_cmp(&best[b].0))?;
+        if best[next].0.is_infinite() {
+            return None;
+        }
+        in_tree[next] = true;
+        total += best[next].0;
+        let parent = best[next].1;
+        if parent != next {
+            degree[parent] += 1;
+            degree[next] += 1;
+        }
+        for c in (1..n).filter(|&c| !in_tree[c]) {
+            if let Some(w) = weight(next, c) {
+                if w < best[c].0 {
+                    best[c] = (w, next);
+                }
+            }
+        }
+    }
+
+    let mut start_edges: Vec<(f64, usize)> = (1..n).filter_map(|c| weight(0, c).map(|w| (w, c))).collect();
+    if start_edges.len() < 2 {
+        return None;
+    }
+    start_edges.sort_by(|a, b| a.0.total_cmp(&b.0));
+    for &(w, c) in &start_edges[..2] {
+        total += w;
+        degree[0] += 1;
+        degree[c] += 1;
+    }
+    Some((total, degree))
+}
+
+/// Held-Karp lower bound on the shortest tour: the best 1-tree bound found by
+/// `iterations` rounds of subgradient ascent on the node potentials. `upper`
+/// (a known tour cost) sizes the steps; without one they shrink from a
+/// fraction of the bound. Returns `INF` when no tour can exist.
+fn held_karp_lower_bound<D: DistanceProvider + ?Sized>(
+    dist: &D,
+    n: usize,
+    iterations: usize,
+    upper: Option<Weight>,
+) -> Weight {
+    if n < 3 {
+        // The only tour is 0 → 1 → 0
+        return if n == 2 { dist.dist(0, 1).saturating_add(dist.dist(1, 0)).min(INF) } else { 0 };
+    }
+
+    let mut pi = vec![0.0; n];
+    let mut best = f64::NEG_INFINITY;
+    let mut lambda = 2.0;
+    let mut stalled = 0;
+    for _ in 0..iterations.max(1) {
+        let Some((tree, degree)) = min_one_tree(dist, n, &pi) else {
+            return INF;
+        };
+        let bound = tree - 2.0 * pi.iter().sum::<f64>();
+        if bound > best + 1e-9 {
+            best = bound;
+            stalled = 0;
+        } else {
+            stalled += 1;
+            if stalled >= 10 {
+                lambda /= 2.0;
+                stalled = 0;
+            }
+        }
+
+        // Push cities with degree > 2 up and leaves down
+        let norm: f64 = degree.iter().map(|&d| (d as f64 - 2.0).powi(2)).sum();
+        if norm == 0.0 {
+            break; // The 1-tree is a tour, so the bound is exact
+        }
+        let target = upper.map_or(bound.abs() * 1.05 + 1.0, |upper| upper as f64);
+        let step = lambda * (target - bound).max(1e-6) / norm;
+        for (p, &d) in pi.iter_mut().zip(&degree) {
+            *p += step * (d as f64 - 2.0);
+        }
+    }
+
+    // Tour costs are integers, so the bound can be rounded up
+    (best - 1e-6).ceil().min(INF as f64) as Weight
+}
+
+/// Assignment-relaxation lower bound on the shortest tour: the cheapest way
+/// to give every city one successor and one predecessor, subtours allowed,
+/// found with the Hungarian algorithm in `O(n³)`. Returns `INF` when the
+/// forbidden edges leave no assignment (and so no tour).
+fn assignment_lower_bound<D: DistanceProvider + ?Sized>(dist: &D, n: usize) -> Weight {
+    if n < 2 {
+        return 0;
+    }
+    // Bigger than any assignment of finite edges, so one forbidden entry shows
+    let forbidden = (n as WideWeight + 1) * WideWeight::from(INF);
+    let cost = |i: usize, j: usize| {
+        let d = dist.dist(i, j);
+        if i == j || d >= INF { forbidden } else { WideWeight::from(d) }
+    };
+
+    // Potentials u (rows) and v (columns), 1-based with a virtual column 0
+    let (mut u, mut v): (Vec<WideWeight>, Vec<WideWeight>) = (vec![0; n + 1], vec![0; n + 1]);
+    let mut row_of = vec![0usize; n + 1];
+    let mut way = vec![0usize; n + 1];
+    for row in 1..=n {
+        row_of[0] = row;
+        let mut col = 0;
+        let mut min_to = vec![WideWeight::MAX; n + 1];
+        let mut used = vec![false; n + 1];
+        loop {
+            used[col] = true;
+            let (i, mut delta, mut next) = (row_of[col], WideWeight::MAX, 0);
+            for j in 1..=n {
+                if !used[j] {
+                    let reduced = cost(i - 1, j - 1) - u[i] - v[j];
+                    if reduced < min_to[j] {
+                        min_to[j] = reduced;
+                        way[j] = col;
+                    }
+                    if min_to[j] < delta {
+                        delta = min_to[j];
+                        next = j;
+                    }
+                }
+            }
+            for j in 0..=n {
+                if used[j] {
+                    u[row_of[j]] += delta;
+                    v[j] -= delta;
+                } else {
+                    min_to[j] -= delta;
+                }
+            }
+            col = next;
+            if row_of[col] == 0 {
+                break;
+            }
+        }
+        // Flip the augmenting path back to the start
+        while col != 0 {
+            let prev = way[col];
+            row_of[col] = row_of[prev];
+            col = prev;
+        }
+    }
+
+    let total: WideWeight = (1..=n).map(|j| cost(row_of[j] - 1, j - 1)).sum();
+    if total >= forbidden {
+        INF
+    } else {
+        total.clamp(WideWeight::from(-INF) + 1, WideWeight::from(INF) - 1) as Weight
+    }
+}
+
+/// `--assignment-bound`: the relaxation next to the best known tour cost.
+fn print_assignment_bound(bound: Weight, cost: Weight, format: WeightFormat) {
+    println!("{}", "📉 Assignment Bound:".bright_magenta().bold());
+    if bound >= INF {
+        println!("   {}", "❌ The forbidden edges leave no assignment, so no tour exists".red());
+    } else {
+        println!("   Bound: {} (every city gets one successor, subtours allowed)", format.render(bound).green());
+        if !is_infeasible(cost) {
+            println!("   Tour:  {}", format.render(cost).yellow());
+            if bound > 0 {
+                println!("   Gap:   {:.2}%", (cost - bound) as f64 / bound as f64 * 100.0);
+            }
+        }
+    }
+    println!();
+}
+
+#[allow(clippy::too_many_arguments)]
+fn print_solution(
+    cost: Weight,
+    path: &[usize],
+    elapsed: std::time::Duration,
+    solver: &TSPSolver,
+    dist: &[Vec<Weight>],
+    format: WeightFormat,
+    separators: bool,
+    depot_highlight: bool,
+) {
+    let width = report_width();
+    println!();
+    println!(
+        "{}",
+        center_text("✨ SOLUTION FOUND! ✨", width)
+            .bright_green()
+            .bold()
+    );
+    println!(
+        "{}",
+        center_text("═".repeat(width).as_str(), width).bright_cyan()
+    );
+
+    if is_infeasible(cost) {
+        println!(
+            "{}",
+            center_text("❌ No valid tour found!", width).red().bold()
+        );
+        println!(
+            "{}",
+            center_text("The graph might not be connected.", width).yellow()
+        );
+    } else {
+        println!(
+            "{}",
+            center_text(
+                &format!(
+                    "🎯 {} Cost: {}",
+                    if solver.maximize { "Maximum" } else { "Minimum" },
+                    format.render_grouped(cost, separators)
+                ),
+                width
+            )
+                .bright_yellow()
+                .bold()
+        );
+        if path.len() > 1 {
+            // Travel only: --optional costs also hold skip penalties
+            let average = tour_cost(dist, path).unwrap_or(INF) as f64 / (path.len() - 1) as f64;
+            println!(
+                "{}",
+                center_text(
+                    &format!("📏 Average Leg: {}", format.render_average(average, separators)),
+                    width
+                )
+                    .white()
+            );
+        }
+        println!(
+            "{}",
+            center_text(&format!("🗺️  Optimal Path: {}", render_path(path, depot_highlight)), width).bright_white()
+        );
+        println!(
+            "{}",
+            center_text(&format!("⏱️  Computation Time: {:.3?}", elapsed), width).white()
+        );
+        println!(
+            "{}",
+            center_text(&format!("🏙️  Cities Visited: {}", path.len() - 1), width).white()
+        );
+        println!(
+            "{}",
+            center_text(&format!("🔢 DP States Computed: {}", solver.computed_states), width).dimmed()
+        );
+    }
+
+    println!(
+        "{}",
+        center_text("═".repeat(width).as_str(), width).bright_cyan()
+    );
+}
+
+/// Legs shown by the detailed route, `None` marking the ellipsis: every leg
+/// of short tours, the first and last `limit` of longer ones under
+/// `--route-limit`, and none otherwise.
+fn detailed_route_legs(legs: usize, limit: Option<usize>) -> Vec<Option<usize>> {
+    match limit {
+        _ if legs < 12 => (0..legs).map(Some).collect(),
+        Some(k) if 2 * k < legs => (0..k).map(Some).chain([None]).chain((legs - k..legs).map(Some)).collect(),
+        Some(_) => (0..legs).map(Some).collect(),
+        None => Vec::new(),
+    }
+}
+
+/// Detailed route for small instances, each step with the runner-up from
+/// `TSPSolver::runner_ups` when there is one; longer tours are shortened by
+/// `detailed_route_legs`, the ellipsis line totalling the legs left out.
+/// The depot is marked as in `render_path`, at its first visit only.
+fn render_detailed_route(
+    path: &[usize],
+    runner_ups: &[Option<(usize, Weight)>],
+    dist: &[Vec<Weight>],
+    format: WeightFormat,
+    separators: bool,
+    route_limit: Option<usize>,
+    depot_marker: bool,
+) -> Vec<String> {
+    let depot = depot_stop(path, depot_marker);
+    let city = |stop: usize| match depot {
+        Some(depot) if stop == depot => depot_label(path[stop]),
+        _ => format!("City{}", path[stop]).bright_cyan(),
+    };
+    let legs = detailed_route_legs(path.len().saturating_sub(1), route_limit);
+    let mut lines = Vec::new();
+    for (k, &leg) in legs.iter().enumerate() {
+        let Some(i) = leg else {
+            let before = legs[..k].last().copied().flatten();
+            let skipped = before.map_or(0, |i| i + 1)..legs.get(k + 1).copied().flatten().unwrap_or(path.len() - 1);
+            let total = path_cost(dist, &path[skipped.start..=skipped.end]);
+            let line = format!("   ⋯  {} more legs, {} in total", skipped.len(), format.render_grouped(total, separators));
+            lines.push(line.dimmed().to_string());
+            continue;
+        };
+        let from = path[i];
+        let to = path[i + 1];
+        let distance = dist[from][to];
+        let runner_up = match runner_ups.get(i).copied().flatten() {
+            Some((city, 0)) => format!("  runner-up City{}, a tie", city),
+            Some((city, delta)) => format!("  runner-up City{}, {} worse", city, format.render_grouped(delta, separators)),
+            None => String::new(),
+        };
+        lines.push(format!(
+            "   Step {}: {} → {} (distance: {}){}",
+            format!("{:2}", i + 1).bright_blue(),
+            city(i),
+            city(i + 1),
+            format.render_grouped(distance, separators).yellow(),
+            runner_up.dimmed()
+        ));
+    }
+    lines
+}
+
+#[allow(clippy::too_many_arguments)]
+fn print_detailed_route(
+    cost: Weight,
+    path: &[usize],
+    runner_ups: &[Option<(usize, Weight)>],
+    dist: &[Vec<Weight>],
+    format: WeightFormat,
+    separators: bool,
+    route_limit: Option<usize>,
+    depot_highlight: bool,
+) {
+    if is_infeasible(cost) {
+        return;
+    }
+    let lines = render_detailed_route(path, runner_ups, dist, format, separators, route_limit, depot_highlight);
+    if !lines.is_empty() {
+        println!();
+        println!("{}", "📍 Detailed Route:".bright_magenta().bold());
+        for line in lines {
+            println!("{}", line);
+        }
+        println!();
+    }
+}
+
+/// Footer naming the objective and recurrence, so a saved report says what
+/// was solved.
+fn print_recurrence_footer(solver: &TSPSolver, format: WeightFormat) {
+    let (objective, recurrence) = solver.recurrence(format);
+    println!("{}", format!("   Objective:  {}", objective).dimmed());
+    println!("{}", format!("   Recurrence: {}", recurrence).dimmed());
+    println!();
+}
+
+/// `--mod` and `--count-tours`: the optimum reduced modulo `modulus`, as
+/// judge problems ask for, and how many optimal tours there are.
+fn print_counting_report(cost: Weight, modulus: Option<u64>, tours: Option<u128>) {
+    println!("{}", "🧮 Counting:".bright_magenta().bold());
+    if is_infeasible(cost) {
+        println!("   {}", "No valid tour, so nothing to count".dimmed());
+        println!();
+        return;
+    }
+    if let Some(m) = modulus {
+        let reduced = i128::from(cost).rem_euclid(i128::from(m));
+        println!("   Cost mod {}: {}", m, reduced.to_string().bright_yellow().bold());
+    }
+    match (tours, modulus) {
+        (Some(tours), Some(m)) => println!("   Optimal tours mod {}: {}", m, tours.to_string().yellow()),
+        (Some(tours), None) => println!("   Optimal tours: {}", tours.to_string().yellow()),
+        _ => {}
+    }
+    if tours.is_some() {
+        println!("   {}", "ℹ️  Each direction of a tour counts separately".dimmed());
+    }
+    println!();
+}
+
+/// Whether the optimum found is the only one, from
+/// `TSPSolver::distinct_optimal_tours`.
+fn print_uniqueness(tours: u128, reverse_is_same: bool) {
+    let line = if tours <= 1 {
+        "🔂 Optimal tour is unique".to_string()
+    } else {
+        format!("🔂 Multiple optimal tours exist ({} found)", tours)
+    };
+    let note = if reverse_is_same { " (a tour and its reverse count once)" } else { "" };
+    println!("{}{}\n", line.bright_white(), note.dimmed());
+}
+
+/// `--optional`: which optional cities the tour visits and which it skips,
+/// splitting `cost` into travel and skip penalties. Cities are original
+/// indices.
+fn print_optional_stops(cost: Weight, path: &[usize], optional: &[usize], penalty: Weight, format: WeightFormat) {
+    println!("{}", "🎁 Optional Stops:".bright_magenta().bold());
+    if is_infeasible(cost) {
+        println!("   {}", "No valid tour visits every required city".dimmed());
+        println!();
+        return;
+    }
+    let (visited, skipped): (Vec<usize>, Vec<usize>) = optional.iter().partition(|city| path.contains(city));
+    let list = |cities: &[usize]| match cities {
+        [] => "none".to_string(),
+        _ => cities.iter().map(|city| format!("City{}", city)).collect::<Vec<_>>().join(", "),
+    };
+    println!("   Visited: {}", list(&visited).bright_cyan());
+    println!("   Skipped: {}", list(&skipped).bright_cyan());
+    let penalties = penalty * skipped.len() as Weight;
+    println!(
+        "   Travel {} + penalties {} ({} × {}) = {}",
+        format.render(cost - penalties).yellow(),
+        format.render(penalties).yellow(),
+        skipped.len(),
+        format.render(penalty),
+        format.render(cost).bright_yellow().bold()
+    );
+    println!();
+}
+
+/// The warnings collected during the run, if any.
+fn print_warnings(warnings: &[Warning]) {
+    if warnings.is_empty() {
+        return;
+    }
+    println!("{}", format!("⚠️  Warnings ({}):", warnings.len()).yellow().bold());
+    for warning in warnings {
+        println!("   • {}", warning.message.yellow());
+    }
+}
+
+/// Report for `--cost-only` runs, which have no path to show.
+fn print_cost_only(
+    cost: Weight,
+    elapsed: std::time::Duration,
+    solver: &TSPSolver,
+    format: WeightFormat,
+    separators: bool,
+) {
+    let width = report_width();
+    println!();
+    println!(
+        "{}",
+        center_text("✨ OPTIMAL COST FOUND! ✨", width)
+            .bright_green()
+            .bold()
+    );
+    println!(
+        "{}",
+        center_text("═".repeat(width).as_str(), width).bright_cyan()
+    );
+    if is_infeasible(cost) {
+        println!(
+            "{}",
+            center_text("❌ No valid tour found!", width).red().bold()
+        );
+    } else {
+        println!(
+            "{}",
+            center_text(
+                &format!(
+                    "🎯 {} Cost: {}",
+                    if solver.maximize { "Maximum" } else { "Minimum" },
+                    format.render_grouped(cost, separators)
+                ),
+                width
+            )
+                .bright_yellow()
+                .bold()
+        );
+    }
+    println!(
+        "{}",
+        center_text(&format!("⏱️  Computation Time: {:.3?}", elapsed), width).white()
+    );
+    println!(
+        "{}",
+        center_text(&format!("🔢 DP States Computed: {}", solver.computed_states), width).dimmed()
+    );
+    println!(
+        "{}",
+        center_text("═".repeat(width).as_str(), width).bright_cyan()
+    );
+    print_recurrence_footer(solver, format);
+}
+
+fn print_farewell() {
+    println!();
+    println!("🙏 Thank you for using TSP Solver!");
+    println!("💡 Tip: For better performance with large graphs, consider approximation algorithms.");
+}
+
+/// Compass bearing from `from` to `to` in degrees clockwise from north,
+/// taking +y as north and +x as east.
+fn compass_bearing(from: (f64, f64), to: (f64, f64)) -> f64 {
+    let (dx, dy) = (to.0 - from.0, to.1 - from.1);
+    dx.atan2(dy).to_degrees().rem_euclid(360.0)
+}
+
+/// The nearest of the eight compass points for a bearing.
+fn compass_point(bearing: f64) -> &'static str {
+    const POINTS: [&str; 8] = ["N", "NE", "E", "SE", "S", "SW", "W", "NW"];
+    POINTS[((bearing / 45.0).round() as usize) % 8]
+}
+
+/// The tour as directions: one compass heading and leg length per leg.
+fn print_bearings(coords: &[(f64, f64)], dist: &[Vec<Weight>], path: &[usize], format: WeightFormat) {
+    println!("{}", "🧭 Directions:".bright_magenta().bold());
+    println!("   Start at {} ({}, {})", "City0".bright_cyan(), coords[path[0]].0, coords[path[0]].1);
+    for leg in path.windows(2) {
+        let (from, to) = (leg[0], leg[1]);
+        let bearing = compass_bearing(coords[from], coords[to]);
+        println!(
+            "   Head {:<2} ({:>5.1}°) {} units to {}",
+            compass_point(bearing),
+            bearing,
+            format.render(dist[from][to]).yellow(),
+            format!("City{}", to).bright_cyan()
+        );
+    }
+    println!();
+}
+
+/// Clarke–Wright style savings: for each leg between two customers, how much
+/// driving it directly saves over returning to City0 in between. One
+/// `(from, to, direct, via City0, saving)` per leg; the saving is `None` when
+/// the detour through City0 is forbidden.
+fn leg_savings(dist: &[Vec<Weight>], path: &[usize]) -> Vec<(usize, usize, WideWeight, WideWeight, Option<WideWeight>)> {
+    path.windows(2)
+        .filter(|leg| leg[0] != 0 && leg[1] != 0)
+        .map(|leg| {
+            let (from, to) = (leg[0], leg[1]);
+            let direct = WideWeight::from(dist[from][to]);
+            let back = WideWeight::from(dist[from][0]);
+            let out = WideWeight::from(dist[0][to]);
+            let via_depot = if back >= WideWeight::from(INF) || out >= WideWeight::from(INF) {
+                WideWeight::from(INF)
+            } else {
+                back + out
+            };
+            (from, to, direct, via_depot, (via_depot < WideWeight::from(INF)).then_some(via_depot - direct))
+        })
+        .collect()
+}
+
+fn print_savings(dist: &[Vec<Weight>], path: &[usize], format: WeightFormat) {
+    let render = |cost: WideWeight| {
+        if cost >= WideWeight::from(INF) {
+            "∞".to_string()
+        } else {
+            format.render(cost as Weight)
+        }
+    };
+
+    println!("{}", "💰 Savings vs. Returning to City0:".bright_magenta().bold());
+    let mut total: WideWeight = 0;
+    for (from, to, direct, via_depot, saving) in leg_savings(dist, path) {
+        let saving = match saving {
+            Some(saving) => {
+                total += saving;
+                let text = format!("{}{}", if saving < 0 { "-" } else { "" }, render(saving.abs()));
+                if saving < 0 { text.red() } else { text.green() }
+            }
+            None => "∞".bright_green(),
+        };
+        println!(
+            "   {} → {}: direct {}, via City0 {}, saves {}",
+            format!("City{}", from).bright_cyan(),
+            format!("City{}", to).bright_cyan(),
+            render(direct).yellow(),
+            render(via_depot),
+            saving
+        );
+    }
+    println!(
+        "   Total savings over separate out-and-back trips: {}",
+        format!("{}{}", if total < 0 { "-" } else { "" }, render(total.abs())).bold()
+    );
+    println!();
+}
+
+/// For asymmetric instances: the cost of driving the same cycle the other way.
+fn print_direction_report(dist: &[Vec<Weight>], path: &[usize], format: WeightFormat) {
+    println!("{}", "🔁 Direction Check:".bright_magenta().bold());
+    for line in render_direction_report(dist, path, format) {
+        println!("{}", line);
+    }
+    println!();
+}
+
+/// The lines of `print_direction_report` below its heading.
+fn render_direction_report(dist: &[Vec<Weight>], path: &[usize], format: WeightFormat) -> Vec<String> {
+    let forward = tour_cost(dist, path).unwrap_or(INF);
+    let reversed: Vec<usize> = path.iter().rev().copied().collect();
+    let backward = tour_cost(dist, &reversed).unwrap_or(INF);
+
+    let mut lines = vec![format!("   Forward:  {} ({})", format.render(forward).yellow(), format_path(path))];
+    if backward >= INF {
+        lines.push(format!("   Reverse:  {} ({})", "∞".red(), format_path(&reversed)));
+        lines.push(format!("   {}", "✅ Only the forward direction is feasible".green()));
+    } else {
+        lines.push(format!("   Reverse:  {} ({})", format.render(backward).yellow(), format_path(&reversed)));
+        if backward == forward {
+            lines.push(format!("   {}", "↔️  Both directions cost the same".green()));
+        } else {
+            let diff = backward - forward;
+            lines.push(format!(
+                "   {} (reverse costs {}{})",
+                "✅ The optimum runs in the forward direction".green(),
+                if diff < 0 { "-" } else { "+" },
+                format.render(diff.abs())
+            ));
+        }
+    }
+    lines
+}
+
+fn print_heuristic_result(
+    start_cost: Weight,
+    cost: Weight,
+    path: &[usize],
+    elapsed: Duration,
+    bound: Option<Weight>,
+    format: WeightFormat,
+    depot_highlight: bool,
+) {
+    let render = |cost: Weight| if cost >= INF { "∞".to_string() } else { format.render(cost) };
+
+    println!("{}", "🧗 2-opt from the Start Tour:".bright_magenta().bold());
+    println!("   Start cost: {}", render(start_cost).yellow());
+    if cost >= INF {
+        println!("   {}", "❌ 2-opt could not remove every forbidden edge".red());
+    } else {
+        println!("   Final cost: {}", render(cost).bright_yellow().bold());
+    }
+    println!("   Tour:       {}", render_path(path, depot_highlight));
+    println!("   Time:       {:.3?}", elapsed);
+    match bound {
+        Some(bound) if bound >= INF => {
+            println!("   {}", "❌ Lower bound: the forbidden edges leave no tour at all".red());
+        }
+        Some(bound) => {
+            println!("   Lower bound: {} (Held-Karp 1-tree)", render(bound).green());
+            if cost < INF && bound > 0 {
+                println!(
+                    "   Gap:         at most {:.2}%",
+                    (cost - bound) as f64 / bound as f64 * 100.0
+                );
+            }
+        }
+        None => {}
+    }
+    println!("   {}", "ℹ️  A local optimum; it is not guaranteed to be the best tour".dimmed());
+    println!();
+}
+
+/// `--restarts`: how many of the 2-opt runs ended on the reported tour.
+fn print_restart_agreement(agreeing: usize, restarts: usize) {
+    println!("{}", "🔁 Restarts:".bright_magenta().bold());
+    println!(
+        "   {} of {} restarts reached this tour ({:.0}%)",
+        agreeing.to_string().bright_yellow(),
+        restarts,
+        agreeing as f64 / restarts as f64 * 100.0
+    );
+    println!("   {}", "ℹ️  More agreement suggests a stable solution, not a proven optimum".dimmed());
+    println!();
+}
+
+/// Edges of closed tour `a` that closed tour `b` doesn't use, in tour
+/// order. Undirected edges are compared with their ends sorted.
+fn edges_missing_from(a: &[usize], b: &[usize], directed: bool) -> Vec<(usize, usize)> {
+    let edge = |leg: &[usize]| if directed || leg[0] < leg[1] { (leg[0], leg[1]) } else { (leg[1], leg[0]) };
+    let in_b: HashSet<(usize, usize)> = b.windows(2).map(edge).collect();
+    a.windows(2).map(edge).filter(|e| !in_b.contains(e)).collect()
+}
+
+/// Shows which edges a tour (`label`, e.g. "your tour") has that the optimal
+/// tour doesn't, and the reverse. `subset` maps the solver's cities back under `--skip`.
+fn print_tour_diff(
+    label: &str,
+    (cost, path): (Weight, &[usize]),
+    (best, best_path): (Weight, &[usize]),
+    dist: &[Vec<Weight>],
+    subset: Option<&CitySubset>,
+    format: WeightFormat,
+) {
+    let directed = !is_symmetric(dist);
+    let ours = edges_missing_from(path, best_path, directed);
+    let theirs = edges_missing_from(best_path, path, directed);
+    if ours.is_empty() {
+        return;
+    }
+    let city = |city: usize| format!("City{}", subset.map_or(city, |subset| subset.kept[city]));
+    let arrow = if directed { "→" } else { "–" };
+    let render = |cost: Weight| if cost >= INF { "∞".to_string() } else { format.render(cost) };
+
+    println!(
+        "{}",
+        format!("🔀 Differences from the Optimal Tour ({} vs. {}):", render(cost), render(best))
+            .bright_magenta()
+            .bold()
+    );
+    for &(from, to) in &ours {
+        let text = format!("   - {} {} {} ({})", city(from), arrow, city(to), render(dist[from][to]));
+        println!("{}  only in {}", text.red(), label);
+    }
+    for &(from, to) in &theirs {
+        let text = format!("   + {} {} {} ({})", city(from), arrow, city(to), render(dist[from][to]));
+        println!("{}  only in the optimal tour", text.green());
+    }
+    let shared = path.len() - 1 - ours.len();
+    println!("   {}", format!("{} of {} edges shared", shared, path.len() - 1).dimmed());
+    println!();
+}
+
+fn print_evaluation(cost: Weight, path: &[usize], optimum: Option<Weight>, format: WeightFormat, depot_highlight: bool) {
+    let width = report_width();
+    println!();
+    println!(
+        "{}",
+        center_text("📝 TOUR EVALUATION 📝", width)
+            .bright_green()
+            .bold()
+    );
+    println!(
+        "{}",
+        center_text("═".repeat(width).as_str(), width).bright_cyan()
+    );
+    println!(
+        "{}",
+        center_text(&format!("🗺️  Tour: {}", render_path(path, depot_highlight)), width).bright_white()
+    );
+
+    if cost >= INF {
+        println!(
+            "{}",
+            center_text("❌ This tour uses an unreachable (INF) edge!", width).red().bold()
+        );
+    } else {
+        println!(
+            "{}",
+            center_text(&format!("💰 Tour Cost: {}", format.render(cost)), width)
+                .bright_yellow()
+                .bold()
+        );
+    }
+
+    match optimum {
+        Some(best) if is_infeasible(best) => {
+            println!(
+                "{}",
+                center_text("❌ The instance has no valid tour at all.", width).yellow()
+            );
+        }
+        Some(best) => {
+            println!(
+                "{}",
+                center_text(&format!("🎯 Exact Optimum: {}", format.render(best)), width).white()
+            );
+            if cost == best {
+                println!(
+                    "{}",
+                    center_text("✅ This tour is optimal!", width).bright_green()
+                );
+            } else if cost < INF {
+                let gap = cost - best;
+                let percent = if best != 0 {
+                    gap as f64 / best.abs() as f64 * 100.0
+                } else {
+                    0.0
+                };
+                println!(
+                    "{}",
+                    center_text(
+                        &format!(
+                            "📈 Gap to Optimum: {}{} ({:+.2}%)",
+                            if gap < 0 { "-" } else { "+" },
+                            format.render(gap.abs()),
+                            percent
+                        ),
+                        width
+                    )
+                        .yellow()
+                );
+            }
+        }
+        None => {
+            println!(
+                "{}",
+                center_text(
+                    &format!("ℹ️  Exact optimum skipped (more than {} cities)", EVALUATE_EXACT_LIMIT),
+                    width
+                )
+                .dimmed()
+            );
+        }
+    }
+
+    println!(
+        "{}",
+        center_text("═".repeat(width).as_str(), width).bright_cyan()
+    );
+}
+
+/// Telemetry written by `--stats-json` for experiment tracking.
+#[derive(Serialize)]
+struct RunStats {
+    n: usize,
+    algorithm: &'static str,
+    objective: &'static str,
+    memo_backend: &'static str,
+    /// `None` when no valid tour exists
+    cost: Option<Weight>,
+    /// Relative gap to the optimum; always 0 for the exact DP
+    optimality_gap: Option<f64>,
+    tour: Vec<usize>,
+    computed_states: usize,
+    states_stored: usize,
+    cache_hits: usize,
+    timings_ms: PhaseTimings,
+    memory_estimate_bytes: usize,
+    /// Random seed, for algorithms that use one
+    seed: Option<u64>,
+    warnings: Vec<Warning>,
+}
+
+#[derive(Serialize)]
+struct PhaseTimings {
+    load: f64,
+    dp: f64,
+    reconstruct: f64,
+    total: f64,
+}
+
+impl RunStats {
+    fn collect(
+        solver: &TSPSolver,
+        cost: Weight,
+        path: &[usize],
+        load_time: Duration,
+        total_time: Duration,
+        warnings: &[Warning],
+    ) -> Self {
+        let feasible = !is_infeasible(cost);
+        RunStats {
+            n: solver.n,
+            algorithm: "held-karp-bitmask-dp",
+            objective: if solver.maximize { "maximize" } else { "minimize" },
+            memo_backend: solver.memo_backend.name(),
+            cost: feasible.then_some(cost),
+            optimality_gap: feasible.then_some(0.0),
+            tour: if feasible { path.to_vec() } else { Vec::new() },
+            computed_states: solver.computed_states,
+            states_stored: solver.dp.len(),
+            cache_hits: solver.cache_hits.get(),
+            timings_ms: PhaseTimings {
+                load: load_time.as_secs_f64() * 1000.0,
+                dp: solver.dp_time.as_secs_f64() * 1000.0,
+                reconstruct: solver.reconstruct_time.as_secs_f64() * 1000.0,
+                total: total_time.as_secs_f64() * 1000.0,
+            },
+            memory_estimate_bytes: solver.dp.memory_bytes(),
+            seed: None,
+            warnings: warnings.to_vec(),
+        }
+    }
+
+    fn to_json(&self) -> Result<String, String> {
+        serde_json::to_string_pretty(self).map_err(|e| format!("Error serializing stats: {}", e))
+    }
+
+    fn write(&self, path: &str) -> Result<(), String> {
+        fs::write(path, self.to_json()? + "\n")
+            .map_err(|e| format!("Error writing stats file '{}': {}", path, e))
+    }
+}
+
+/// Renders a closed path as space-separated 0-based indices, the plain
+/// permutation format other solvers (OR-Tools, Concorde) accept.
+fn format_permutation(path: &[usize], append_return: bool) -> String {
+    let cities = if append_return { path } else { &path[..path.len() - 1] };
+    cities
+        .iter()
+        .map(|city| city.to_string())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Renders the tour for spreadsheets as one CSV row per leg, with the
+/// running total so far; coordinate instances also get each leg's end
+/// points. Durations are written as `HH:MM:SS`, which spreadsheets read
+/// as times.
+fn render_legs_csv(dist: &[Vec<Weight>], tour: &[usize], coords: Option<&[(f64, f64)]>, format: WeightFormat) -> String {
+    let render = |value: Weight| match format {
+        WeightFormat::Duration if value >= 0 => format_duration(value),
+        _ => value.to_string(),
+    };
+    let mut csv = String::from("step,from,to,distance,cumulative");
+    if coords.is_some() {
+        csv.push_str(",from_x,from_y,to_x,to_y");
+    }
+    csv.push('\n');
+    let mut cumulative = 0;
+    for (step, leg) in tour.windows(2).enumerate() {
+        let (from, to) = (leg[0], leg[1]);
+        cumulative += dist[from][to];
+        csv.push_str(&format!("{},{},{},{},{}", step + 1, from, to, render(dist[from][to]), render(cumulative)));
+        if let Some(coords) = coords {
+            let ((from_x, from_y), (to_x, to_y)) = (coords[from], coords[to]);
+            csv.push_str(&format!(",{},{},{},{}", from_x, from_y, to_x, to_y));
+        }
+        csv.push('\n');
+    }
+    csv
+}
+
+/// Draws the cities as labelled points and the tour as a closed polyline.
+#[cfg(feature = "png")]
+fn render_png(path: &str, coords: &[(f64, f64)], tour: &[usize], title: &str) -> Result<(), String> {
+    use plotters::prelude::*;
+
+    let error = |e: &dyn std::fmt::Display| format!("Error drawing '{}': {}", path, e);
+    let bounds = |axis: fn(&(f64, f64)) -> f64| {
+        let (min, max) = coords
+            .iter()
+            .map(axis)
+            .fold((f64::INFINITY, f64::NEG_INFINITY), |(lo, hi), v| (lo.min(v), hi.max(v)));
+        // Room for the labels, and a usable range when every city is aligned
+        let pad = ((max - min) * 0.05).max(1.0);
+        (min - pad)..(max + pad)
+    };
+
+    let root = BitMapBackend::new(path, (800, 800)).into_drawing_area();
+    root.fill(&WHITE).map_err(|e| error(&e))?;
+    let mut chart = ChartBuilder::on(&root)
+        .caption(title, ("sans-serif", 24))
+        .margin(20)
+        .build_cartesian_2d(bounds(|c| c.0), bounds(|c| c.1))
+        .map_err(|e| error(&e))?;
+
+    chart
+        .draw_series(LineSeries::new(tour.iter().map(|&city| coords[city]), &BLUE))
+        .map_err(|e| error(&e))?;
+    chart
+        .draw_series(coords.iter().enumerate().map(|(city, &point)| {
+            EmptyElement::at(point)
+                + Circle::new((0, 0), 4, RED.filled())
+                + Text::new(city.to_string(), (6, -16), ("sans-serif", 14))
+        }))
+        .map_err(|e| error(&e))?;
+    root.present().map_err(|e| error(&e))
+}
+
+#[cfg(not(feature = "png"))]
+fn render_png(_path: &str, _coords: &[(f64, f64)], _tour: &[usize], _title: &str) -> Result<(), String> {
+    Err("This build has no PNG support; rebuild with `--features png`".to_string())
+}
+
+/// The state the DP just filled, for the `--tui` dashboard.
+#[cfg(feature = "tui")]
+#[derive(Clone, Copy)]
+struct DashboardState {
+    n: usize,
+    computed: usize,
+    total: usize,
+    mask: usize,
+    pos: usize,
+    value: Weight,
+    next: Option<usize>,
+}
+
+/// Where `TSPSolver` sends DP progress under `--tui`.
+#[cfg(feature = "tui")]
+trait DashboardSink {
+    fn update(&mut self, state: DashboardState);
+    fn finish(&mut self);
+}
+
+/// `--tui`: the matrix beside a progress gauge and the latest DP state,
+/// redrawn at most every `DASHBOARD_REDRAW` so drawing doesn't slow the DP.
+#[cfg(feature = "tui")]
+struct Dashboard<B: ratatui::backend::Backend> {
+    terminal: ratatui::Terminal<B>,
+    matrix: String,
+    started: Instant,
+    last_draw: Option<Instant>,
+    state: Option<DashboardState>,
+    /// Leave the alternate screen on `finish`; test backends have none
+    restore: bool,
+}
+
+#[cfg(feature = "tui")]
+impl Dashboard<ratatui::backend::CrosstermBackend<std::io::Stdout>> {
+    fn start(dist: &[Vec<Weight>]) -> Result<Self, String> {
+        let terminal = ratatui::try_init().map_err(|e| format!("Couldn't start the TUI: {}", e))?;
+        Ok(Dashboard::new(terminal, dist, true))
+    }
+}
+
+#[cfg(feature = "tui")]
+impl<B: ratatui::backend::Backend> Dashboard<B> {
+    fn new(terminal: ratatui::Terminal<B>, dist: &[Vec<Weight>], restore: bool) -> Self {
+        let matrix = render_matrix_file(dist, WeightFormat::Plain, None);
+        let matrix = matrix.lines().skip(1).collect::<Vec<_>>().join("\n");
+        Dashboard { terminal, matrix, started: Instant::now(), last_draw: None, state: None, restore }
+    }
+
+    fn draw(&mut self) -> std::io::Result<()> {
+        use ratatui::layout::{Constraint, Layout};
+        use ratatui::widgets::{Block, Gauge, Paragraph};
+
+        let ratio = self.state.map_or(0.0, |state| (state.computed as f64 / state.total.max(1) as f64).min(1.0));
+        let mut lines = vec![match self.state {
+            Some(state) => format!("States: {} of {}", state.computed, state.total),
+            None => "States: none filled yet".to_string(),
+        }];
+        if let Some(rate) = self.state.and_then(|state| format_throughput(state.computed, self.started.elapsed())) {
+            lines.push(format!("Speed:  {}", rate));
+        }
+        if let Some(state) = self.state {
+            let value = if is_infeasible(state.value) { "∞".to_string() } else { state.value.to_string() };
+            lines.push(format!("Mask:   {:0width$b}", state.mask, width = state.n));
+            lines.push(format!("At:     City{}", state.pos));
+            lines.push(match state.next {
+                Some(next) => format!("Best:   {} to finish, via City{}", value, next),
+                None => format!("Best:   {} to finish", value),
+            });
+        }
+        let matrix = self.matrix.as_str();
+        self.terminal.draw(|frame| {
+            let [left, right] = Layout::horizontal([Constraint::Percentage(50); 2]).areas(frame.area());
+            let [gauge, latest] = Layout::vertical([Constraint::Length(3), Constraint::Min(0)]).areas(right);
+            frame.render_widget(Paragraph::new(matrix).block(Block::bordered().title(" Distance Matrix ")), left);
+            frame.render_widget(Gauge::default().block(Block::bordered().title(" DP Progress ")).ratio(ratio), gauge);
+            frame.render_widget(Paragraph::new(lines.join("\n")).block(Block::bordered().title(" Latest State ")), latest);
+        })?;
+        Ok(())
+    }
+}
+
+#[cfg(feature = "tui")]
+impl<B: ratatui::backend::Backend> DashboardSink for Dashboard<B> {
+    fn update(&mut self, state: DashboardState) {
+        self.state = Some(state);
+        if self.last_draw.is_none_or(|drawn| drawn.elapsed() >= DASHBOARD_REDRAW) {
+            // A failed redraw shouldn't stop the solve; the next one may work
+            let _ = self.draw();
+            self.last_draw = Some(Instant::now());
+        }
+    }
+
+    fn finish(&mut self) {
+        let _ = self.draw();
+        if self.restore {
+            ratatui::restore();
+        }
+    }
+}
+
+#[cfg(feature = "tui")]
+fn attach_dashboard(solver: &mut TSPSolver) -> Result<(), String> {
+    let dashboard = Dashboard::start(&solver.dist)?;
+    solver.set_dashboard(Box::new(dashboard));
+    Ok(())
+}
+
+#[cfg(not(feature = "tui"))]
+fn attach_dashboard(_solver: &mut TSPSolver) -> Result<(), String> {
+    Err("This build has no TUI; rebuild with `--features tui`".to_string())
+}
+
+/// The tour as GeoJSON for mapping tools: a closed `LineString` through the
+/// cities plus a `Point` per city carrying its index (and name). TSPLIB `GEO`
+/// coordinates are latitude/longitude in `DDD.MM`, so they are converted to
+/// `[longitude, latitude]` in decimal degrees; other coordinates are written
+/// as `[x, y]`, i.e. x is taken as the longitude.
+fn render_geojson(coords: &[(f64, f64)], metric: Metric, tour: &[usize], names: Option<&[String]>) -> String {
+    let position = |city: usize| {
+        let (x, y) = coords[city];
+        match metric {
+            // Six decimals are about 10 cm, and hide the float noise
+            Metric::Geo => {
+                let degrees = |coordinate: f64| (geo_degrees(coordinate) * 1e6).round() / 1e6;
+                serde_json::json!([degrees(y), degrees(x)])
+            }
+            _ => serde_json::json!([x, y]),
+        }
+    };
+    let mut features = vec![serde_json::json!({
+        "type": "Feature",
+        "geometry": {
+            "type": "LineString",
+            "coordinates": tour.iter().map(|&city| position(city)).collect::<Vec<_>>(),
+        },
+        "properties": { "kind": "tour" },
+    })];
+    for city in 0..coords.len() {
+        let mut properties = serde_json::json!({ "kind": "city", "index": city });
+        if let Some(name) = names.and_then(|names| names.get(city)) {
+            properties["name"] = serde_json::json!(name);
+        }
+        features.push(serde_json::json!({
+            "type": "Feature",
+            "geometry": { "type": "Point", "coordinates": position(city) },
+            "properties": properties,
+        }));
+    }
+    let collection = serde_json::json!({ "type": "FeatureCollection", "features": features });
+    serde_json::to_string_pretty(&collection).expect("GeoJSON values always serialize") + "\n"
+}
+
+/// Copy-pasteable Markdown write-up of a run: the matrix, the tour as a
+/// numbered list of legs and a small stats table.
+fn render_markdown(
+    dist: &[Vec<Weight>],
+    cost: Weight,
+    path: &[usize],
+    elapsed: Duration,
+    solver: &TSPSolver,
+    format: WeightFormat,
+) -> String {
+    let cell = |d: Weight| if d >= INF { "∞".to_string() } else { format.render(d) };
+    let mut md = String::from("# TSP Solution\n\n## Distance Matrix\n\n| |");
+    for i in 0..dist.len() {
+        md += &format!(" City{} |", i);
+    }
+    md += "\n|---|";
+    md += &"---:|".repeat(dist.len());
+    md += "\n";
+    for (i, row) in dist.iter().enumerate() {
+        md += &format!("| **City{}** |", i);
+        for &d in row {
+            md += &format!(" {} |", cell(d));
+        }
+        md += "\n";
+    }
+
+    md += "\n## Optimal Tour\n\n";
+    if is_infeasible(cost) {
+        md += "No valid tour exists.\n";
+    } else {
+        for (step, leg) in path.windows(2).enumerate() {
+            md += &format!(
+                "{}. City{} → City{} ({})\n",
+                step + 1,
+                leg[0],
+                leg[1],
+                cell(dist[leg[0]][leg[1]])
+            );
+        }
+    }
+
+    let objective = if solver.maximize { "Maximum cost" } else { "Minimum cost" };
+    md += "\n## Statistics\n\n| Metric | Value |\n|---|---|\n";
+    md += &format!("| Cities | {} |\n", dist.len());
+    md += &format!(
+        "| {} | {} |\n",
+        objective,
+        if is_infeasible(cost) { "-".to_string() } else { format.render(cost) }
+    );
+    md += &format!("| Computation time | {:.3?} |\n", elapsed);
+    md += &format!("| DP states computed | {} |\n", solver.computed_states);
+    md += &format!("| Memo backend | {} |\n", solver.memo_backend.name());
+    md
+}
+
+/// Reads an instance stored as a topology file (edge list without weights)
+/// plus a weights file with the matching values.
+fn load_split_files(topology: &str, weights: &str, parse: &ParseOptions) -> Result<Vec<Vec<Weight>>, String> {
+    let read = |path: &str| {
+        read_file(path).map_err(|e| format!("Error reading file '{}': {}", path, e))
+    };
+    parse_split_input(&read(topology)?, &read(weights)?, parse)
+        .map_err(|e| format!("Error parsing input: {}", e))
+}
+
+/// Combines a topology (`n`, then one `from to` edge per line) with a weights
+/// list (one value per line, in the same order) into a distance matrix.
+fn parse_split_input(topology: &str, weights: &str, parse: &ParseOptions) -> Result<Vec<Vec<Weight>>, String> {
+    let edges = content_lines(topology);
+    let values = content_lines(weights);
+    let Some(&(_, first)) = edges.first() else {
+        return Err("Empty topology file".to_string());
+    };
+
+    let n: usize = first.parse().map_err(|_| "Invalid number of cities in topology")?;
+    parse.check_city_count(n)?;
+
+    let edges = &edges[1..];
+    if edges.len() != values.len() {
+        let detail = if edges.len() > values.len() {
+            format!("topology line {} has no weight", edges[values.len()].0)
+        } else {
+            format!("weights line {} has no edge", values[edges.len()].0)
+        };
+        return Err(format!(
+            "Topology has {} edges but weights has {} values: {}",
+            edges.len(),
+            values.len(),
+            detail
+        ));
+    }
+
+    let mut distances: Vec<Vec<Weight>> = (0..n)
+        .map(|i| (0..n).map(|j| if i == j { 0 } else { INF }).collect())
+        .collect();
+
+    let mut seen = HashMap::new();
+    for (&(edge_line, edge), &(weight_line, value)) in edges.iter().zip(&values) {
+        let parts: Vec<&str> = edge.split_whitespace().collect();
+        let [from, to] = parts.as_slice() else {
+            return Err(format!("Topology line {}: Expected 2 values (from to)", edge_line));
+        };
+        let from: usize = from
+            .parse()
+            .map_err(|_| format!("Topology line {}: Invalid 'from' city", edge_line))?;
+        let to: usize = to
+            .parse()
+            .map_err(|_| format!("Topology line {}: Invalid 'to' city", edge_line))?;
+        if from >= n || to >= n {
+            return Err(format!("Topology line {}: City index out of range", edge_line));
+        }
+        let weight = parse_weight(value, parse.weight_format)
+            .ok_or_else(|| format!("Weights line {}: Invalid weight", weight_line))?;
+
+        add_edge(&mut distances, &mut seen, (from, to, weight), edge_line, parse)
+            .map_err(|e| format!("Topology line {}: {}", edge_line, e))?;
+    }
+
+    Ok(distances)
+}
+
+/// How many different weights the legs of `path` have.
+fn distinct_leg_weights(dist: &[Vec<Weight>], path: &[usize]) -> usize {
+    path.windows(2).map(|leg| dist[leg[0]][leg[1]]).collect::<HashSet<_>>().len()
+}
+
+fn print_k_best(tours: &[(Weight, Vec<usize>)], requested: usize, format: WeightFormat) {
+    println!("{}", format!("🏅 {} Best Tours:", requested).bright_magenta().bold());
+    for (rank, (cost, path)) in tours.iter().enumerate() {
+        println!(
+            "   #{} {} {}",
+            format!("{:<3}", rank + 1).bright_blue(),
+            format!("cost {:>8}", format.render(*cost)).yellow(),
+            format_path(path)
+        );
+    }
+    if tours.len() < requested {
+        println!(
+            "   {}",
+            format!("ℹ️  Only {} distinct tours found", tours.len()).dimmed()
+        );
+    }
+    println!();
+}
+
+/// The closed tour `path` started from `start` instead, or `None` if the
+/// tour doesn't visit it.
+fn rotate_tour(path: &[usize], start: usize) -> Option<Vec<usize>> {
+    let mut cycle = path[..path.len() - 1].to_vec();
+    let offset = cycle.iter().position(|&city| city == start)?;
+    cycle.rotate_left(offset);
+    cycle.push(start);
+    Some(cycle)
+}
+
+/// `--all-starts`: the optimal tour from every start city. A closed tour
+/// costs the same wherever it starts, directed or not, so the one DP
+/// optimum rotated to each start is that start's optimum; only the order
+/// the cities are listed in changes. One `(start, cost, tour)` per city on
+/// `path`, by start.
+fn all_start_tours(path: &[usize], dist: &[Vec<Weight>]) -> Vec<(usize, Weight, Vec<usize>)> {
+    let mut starts: Vec<usize> = path[..path.len() - 1].to_vec();
+    starts.sort_unstable();
+    starts
+        .into_iter()
+        .filter_map(|start| rotate_tour(path, start).map(|tour| (start, tour_cost(dist, &tour).unwrap_or(INF), tour)))
+        .collect()
+}
+
+/// The `all_start_tours` table; the best start(s) get a star.
+fn print_all_starts(path: &[usize], dist: &[Vec<Weight>], maximize: bool, format: WeightFormat) {
+    println!("{}", "🔄 Optimal Tour from Every Start:".bright_magenta().bold());
+    let tours = all_start_tours(path, dist);
+    let costs = tours.iter().map(|&(_, cost, _)| cost);
+    let best = if maximize { costs.max() } else { costs.min() };
+    for (start, cost, tour) in &tours {
+        let marker = if Some(*cost) == best { "★".bright_yellow() } else { " ".normal() };
+        println!(
+            "   {} {} {} {}",
+            marker,
+            format!("City{:<3}", start).bright_cyan(),
+            format!("cost {:>8}", format.render(*cost)).yellow(),
+            format_path(tour)
+        );
+    }
+    println!(
+        "   {}",
+        "ℹ️  A closed tour costs the same from any start, so one DP covers them all".dimmed()
+    );
+    println!();
+}
+
+/// After an interactive solve, lets the user pick a leg of the tour and see
+/// which next cities the DP compared at that point and what each would have
+/// cost. `subset` maps the solver's cities back to the input's under `--skip`.
+fn explain_legs(
+    solver: &TSPSolver,
+    path: &[usize],
+    subset: Option<&CitySubset>,
+    format: WeightFormat,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let legs = path.len() - 1;
+
+    loop {
+        let answer: String = Input::with_theme(&ColorfulTheme::default())
+            .with_prompt(format!("Explain a leg (1-{}, Enter to finish)", legs))
+            .allow_empty(true)
+            .validate_with(|text: &String| -> Result<(), String> {
+                match text.trim() {
+                    "" => Ok(()),
+                    leg => match leg.parse::<usize>() {
+                        Ok(leg) if (1..=legs).contains(&leg) => Ok(()),
+                        _ => Err(format!("Enter a number from 1 to {}", legs)),
+                    },
+                }
+            })
+            .interact_text()?;
+        let Ok(leg) = answer.trim().parse::<usize>() else {
+            return Ok(());
+        };
+
+        println!();
+        for line in render_leg_explanation(solver, path, leg, subset, format) {
+            println!("{}", line);
+        }
+        println!();
+    }
+}
+
+/// What `explain_legs` shows for leg `leg` (1-based) of `path`: its heading,
+/// then every next city the DP compared there.
+fn render_leg_explanation(
+    solver: &TSPSolver,
+    path: &[usize],
+    leg: usize,
+    subset: Option<&CitySubset>,
+    format: WeightFormat,
+) -> Vec<String> {
+    let label = |city: usize| format!("City{}", subset.map_or(city, |subset| subset.kept[city]));
+    let (from, to) = (path[leg - 1], path[leg]);
+    let mut lines = vec![
+        format!("🔎 Leg {}: {} → {}, distance {}", leg, label(from), label(to), format.render(solver.dist[from][to]))
+            .bright_magenta()
+            .bold()
+            .to_string(),
+        format!("   Next cities the DP compared at {} (edge + best rest of the tour):", label(from)),
+    ];
+    for (city, option) in solver.leg_alternatives(path, leg - 1) {
+        let marker = if city == to { " ✅ chosen".green() } else { "".normal() };
+        lines.push(match option {
+            Some((edge, rest)) => format!(
+                "   → {}: {} + {} = {}{}",
+                label(city).bright_cyan(),
+                format.render(edge),
+                format.render(rest),
+                format.render(edge + rest).yellow(),
+                marker
+            ),
+            None => format!("   → {}: {}", label(city).bright_cyan(), "no valid tour this way".dimmed()),
+        });
+    }
+    lines
+}
+
+fn print_fixed_position(
+    city: usize,
+    stop: usize,
+    (cost, path): (Weight, &[usize]),
+    optimum: Weight,
+    format: WeightFormat,
+) {
+    println!(
+        "{}",
+        format!("📌 Best Tour with City{} as Stop {}:", city, stop).bright_magenta().bold()
+    );
+    if is_infeasible(cost) {
+        println!("   {}", "❌ No valid tour satisfies this constraint".red());
+    } else {
+        let gap = cost - optimum;
+        println!(
+            "   Cost: {} ({}{} vs. optimum)",
+            format.render(cost).yellow(),
+            if gap < 0 { "-" } else { "+" },
+            format.render(gap.abs())
+        );
+        println!("   Path: {}", format_path(path));
+    }
+    println!();
+}
+
+/// Parses `--at-position` values of the form `city:stop`.
+fn parse_fixed_position(text: &str) -> Result<(usize, usize), String> {
+    let (city, stop) = text
+        .split_once(':')
+        .ok_or("--at-position expects city:stop, e.g. 3:2")?;
+    let city = city
+        .trim()
+        .parse()
+        .map_err(|_| format!("Invalid city '{}'", city.trim()))?;
+    let stop = stop
+        .trim()
+        .parse()
+        .map_err(|_| format!("Invalid stop '{}'", stop.trim()))?;
+    Ok((city, stop))
+}
+
+/// Parses a `--forbid` edge of the form `from:to`.
+fn parse_forbidden_edge(text: &str) -> Result<(usize, usize), String> {
+    let (from, to) = text
+        .split_once(':')
+        .ok_or("--forbid expects an edge from:to, e.g. 1:3")?;
+    let city = |token: &str| {
+        token
+            .trim()
+            .parse::<usize>()
+            .map_err(|_| format!("Invalid city '{}'", token.trim()))
+    };
+    Ok((city(from)?, city(to)?))
+}
+
+/// Sets the `--forbid` edges to `INF`, in both directions unless `directed`.
+fn forbid_edges(distances: &mut [Vec<Weight>], edges: &[(usize, usize)], directed: bool) -> Result<(), String> {
+    let n = distances.len();
+    for &(from, to) in edges {
+        if from >= n || to >= n {
+            return Err(format!("Cannot forbid {}:{}: city index out of range (0 to {})", from, to, n - 1));
+        }
+        if from == to {
+            return Err(format!("Cannot forbid {}:{}: not an edge", from, to));
+        }
+        distances[from][to] = INF;
+        if !directed {
+            distances[to][from] = INF;
+        }
+    }
+    Ok(())
+}
+
+/// A DP state in the `--state-graph` export.
+#[derive(Clone, Copy)]
+struct StateNode {
+    mask: usize,
+    pos: usize,
+    value: Weight,
+    /// The move the DP chose, `(next city, edge cost)`, or `None` when no
+    /// valid tour goes on from here. Full-mask states move back to City0.
+    next: Option<(usize, Weight)>,
+}
+
+/// Writes the reachable DP states and their chosen transitions for
+/// `--dump-dp`: every stored state as `mask pos value`, in table order (the
+/// mask in binary, read right to left like `--trace-states`). `INF` marks a
+/// state with no valid completion.
+fn write_dp_dump(path: &str, solver: &TSPSolver) -> Result<(), String> {
+    let error = |e: std::io::Error| format!("Error writing DP dump '{}': {}", path, e);
+    let mut out = BufWriter::new(fs::File::create(path).map_err(error)?);
+    writeln!(out, "# mask pos value ({} states)", solver.dp.len()).map_err(error)?;
+    for (mask, pos, value) in solver.dp.entries() {
+        let value = if is_infeasible(value) { "INF".to_string() } else { value.to_string() };
+        writeln!(out, "{:0width$b} {} {}", mask, pos, value, width = solver.n).map_err(error)?;
+    }
+    out.flush().map_err(error)
+}
+
+/// `--grade-log`: every reachable DP state with the city the DP chose there
+/// and the state's value, one `size mask pos choice value` line each,
+/// sorted by subset size, then mask, then position. Nothing depends on the
+/// memo backend, hashing or timing, so two correct solvers agree byte for
+/// byte and a plain `diff` against a reference log finds the first wrong
+/// decision. Ties go to the lowest city, as in the reconstruction;
+/// `choice` is `-` where no tour goes on.
+fn render_grade_log(solver: &TSPSolver, cost: Weight) -> String {
+    let mut nodes = solver.state_graph();
+    nodes.sort_by_key(|node| (node.mask.count_ones(), node.mask, node.pos));
+    let value = |value: Weight| if is_infeasible(value) { "INF".to_string() } else { value.to_string() };
+    let mut log = format!(
+        "# grade log: {} cities, {}, optimum {}\n# size mask pos choice value\n",
+        solver.n,
+        if solver.maximize { "maximize" } else { "minimize" },
+        value(cost)
+    );
+    for node in nodes {
+        let choice = node.next.map_or("-".to_string(), |(city, _)| city.to_string());
+        log.push_str(&format!(
+            "{} {:0width$b} {} {} {}\n",
+            node.mask.count_ones(),
+            node.mask,
+            node.pos,
+            choice,
+            value(node.value),
+            width = solver.n
+        ));
+    }
+    log
+}
+
+/// `--state-graph`: Graphviz DOT for `.dot`/`.gv` files, otherwise an edge
+/// list with one `from_mask from_pos to_mask to_pos edge value` line per
+/// transition. The optimal path is highlighted in the DOT output.
+fn write_state_graph(
+    path: &str,
+    nodes: &[StateNode],
+    n: usize,
+    format: WeightFormat,
+) -> Result<(), String> {
+    let full = (1 << n) - 1;
+    let name = |mask: usize, pos: usize| format!("{:0width$b}/{}", mask, pos, width = n);
+    let value = |value: Weight| if is_infeasible(value) { "∞".to_string() } else { format.render(value) };
+    let dot = Path::new(path)
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("dot") || ext.eq_ignore_ascii_case("gv"));
+
+    let mut out = String::new();
+    if dot {
+        let chosen: HashMap<(usize, usize), usize> = nodes
+            .iter()
+            .filter_map(|node| node.next.map(|(city, _)| ((node.mask, node.pos), city)))
+            .collect();
+        let mut optimal = HashSet::new();
+        let mut state = (1, 0);
+        while let Some(&city) = chosen.get(&state) {
+            optimal.insert(state);
+            if state.0 == full {
+                break;
+            }
+            state = (state.0 | (1 << city), city);
+        }
+
+        out.push_str("digraph tsp_dp {\n    rankdir=LR;\n    node [shape=box, fontname=\"monospace\"];\n");
+        out.push_str("    end [shape=doublecircle, label=\"City0\"];\n");
+        for &StateNode { mask, pos, value: state_value, next } in nodes {
+            let style = if optimal.contains(&(mask, pos)) { ", color=red, penwidth=2" } else { "" };
+            out.push_str(&format!(
+                "    \"{}\" [label=\"{:0width$b} @City{}\\n{}\"{}];\n",
+                name(mask, pos),
+                mask,
+                pos,
+                value(state_value),
+                style,
+                width = n
+            ));
+            if let Some((city, edge)) = next {
+                let target = if mask == full { "end".to_string() } else { format!("\"{}\"", name(mask | (1 << city), city)) };
+                out.push_str(&format!(
+                    "    \"{}\" -> {} [label=\"{}\"{}];\n",
+                    name(mask, pos),
+                    target,
+                    format.render(edge),
+                    style
+                ));
+            }
+        }
+        out.push_str("}\n");
+    } else {
+        out.push_str("# from_mask from_pos to_mask to_pos edge value (masks read right to left: the last bit is City0)\n");
+        for &StateNode { mask, pos, value: state_value, next } in nodes {
+            if let Some((city, edge)) = next {
+                let to_mask = if mask == full { 1 } else { mask | (1 << city) };
+                out.push_str(&format!(
+                    "{:0width$b} {} {:0width$b} {} {} {}\n",
+                    mask,
+                    pos,
+                    to_mask,
+                    city,
+                    format.render(edge),
+                    value(state_value),
+                    width = n
+                ));
+            }
+        }
+    }
+    fs::write(path, out).map_err(|e| format!("Error writing state graph '{}': {}", path, e))
+}
+
+fn load_service_times(path: &str, n: usize, format: WeightFormat) -> Result<Vec<Weight>, String> {
+    let content = read_file(path)
+        .map_err(|e| format!("Error reading service times file: {}", e))?;
+    parse_service_times(&content, n, format)
+}
+
+/// One service time per line, City0 first, in the matrix's weight format.
+fn parse_service_times(content: &str, n: usize, format: WeightFormat) -> Result<Vec<Weight>, String> {
+    let mut service = Vec::with_capacity(n);
+    for (line_no, line) in content_lines(content) {
+        match parse_weight(line, format) {
+            Some(time) if (0..INF).contains(&time) => service.push(time),
+            _ => return Err(format!("Line {}: invalid service time '{}'", line_no, line)),
+        }
+    }
+    if service.len() != n {
+        return Err(format!("Expected {} service times, one per city, found {}", n, service.len()));
+    }
+    Ok(service)
+}
+
+/// `--service-times`: every leg into city `j` costs `service[j]` more, so a
+/// tour, entering each city once (City0 on the way back), pays the total
+/// service time on top of its travel. Done before `--max-leg`, which then
+/// bounds a leg together with the service at its end. Returns the total.
+fn add_service_times(distances: &mut [Vec<Weight>], service: &[Weight]) -> Result<Weight, String> {
+    for (i, row) in distances.iter_mut().enumerate() {
+        for (j, d) in row.iter_mut().enumerate() {
+            if j == i || *d >= INF {
+                continue;
+            }
+            *d += service[j];
+            if *d >= INF {
+                return Err(format!("City{} → City{} plus its service time is out of range", i, j));
+            }
+        }
+    }
+    Ok(service.iter().sum())
+}
+
+/// `--max-leg`: forbids every edge longer than `max`, including the closing
+/// edges back to City0, so the DP and its reconstruction never use them.
+/// Returns how many edges were removed.
+fn cap_leg_length(distances: &mut [Vec<Weight>], max: Weight) -> usize {
+    let mut removed = 0;
+    for row in distances.iter_mut() {
+        for d in row.iter_mut().filter(|d| **d < INF && **d > max) {
+            *d = INF;
+            removed += 1;
+        }
+    }
+    removed
+}
+
+fn print_layer_report(layers: &[(usize, usize, usize)]) {
+    const BAR_WIDTH: usize = 30;
+    let widest = layers.iter().map(|&(_, _, finite)| finite).max().unwrap_or(0).max(1);
+
+    println!("{}", "📶 DP States per Layer:".bright_magenta().bold());
+    println!("   {:>7}  {:>10}  {:>10}", "Visited", "States", "Finite");
+    for &(visited, stored, finite) in layers {
+        println!(
+            "   {:>7}  {:>10}  {:>10}  {}",
+            visited,
+            stored,
+            finite,
+            "█".repeat(finite * BAR_WIDTH / widest).bright_cyan()
+        );
+    }
+    let total: usize = layers.iter().map(|&(_, _, finite)| finite).sum();
+    println!("   {}", format!("{} finite states in total", total).dimmed());
+    println!();
+}
+
+/// `--show-ties`: the stops where another next city would have been just as
+/// good, so the optimal tour isn't unique.
+fn print_ties(path: &[usize], ties: &[(usize, Vec<usize>)]) {
+    let label = |city: usize| format!("City{}", city);
+    println!("{}", "⚖️  Ties in Reconstruction:".bright_magenta().bold());
+    if ties.is_empty() {
+        println!("   None: every step had a single best next city, so this optimal tour is unique");
+        println!();
+        return;
+    }
+    for (stop, cities) in ties {
+        let others: Vec<String> = cities[1..].iter().map(|&city| label(city)).collect();
+        println!(
+            "   After stop {} ({}): {} was taken; {} would tie",
+            stop,
+            label(path[*stop]).bright_cyan(),
+            label(cities[0]).bright_cyan(),
+            others.join(" or ").yellow()
+        );
+    }
+    println!(
+        "   {}",
+        "ℹ️  The chosen tour is unchanged; on symmetric instances its reverse always ties at stop 0".dimmed()
+    );
+    println!();
+}
+
+/// `--leg-profile`: each leg of `path` with its matrix weight, the
+/// multiplier for its position and what it cost.
+fn print_leg_profile(path: &[usize], dist: &[Vec<Weight>], profile: &[Weight], format: WeightFormat) {
+    println!("{}", "🚦 Leg Profile:".bright_magenta().bold());
+    for (leg, step) in path.windows(2).enumerate() {
+        let multiplier = profile[leg.min(profile.len() - 1)];
+        let edge = dist[step[0]][step[1]];
+        println!(
+            "   Leg {}: {} → {}  {} × {} = {}",
+            format!("{:2}", leg + 1).bright_blue(),
+            format!("City{}", step[0]).bright_cyan(),
+            format!("City{}", step[1]).bright_cyan(),
+            format.render(edge),
+            multiplier,
+            format.render(edge * multiplier).yellow()
+        );
+    }
+    println!(
+        "   {}",
+        "ℹ️  Simplified time-dependent model: a leg's multiplier depends only on how many stops came before it, not on clock time".dimmed()
+    );
+    println!();
+}
+
+/// `--timing-histogram`: where the fill spent its time, by layer.
+fn print_timing_histogram(layers: &[(usize, Duration)]) {
+    const BAR_WIDTH: usize = 30;
+    let total: Duration = layers.iter().map(|&(_, time)| time).sum();
+    let slowest = layers.iter().map(|&(_, time)| time).max().unwrap_or_default().max(Duration::from_nanos(1));
+
+    println!("{}", "⏲️  DP Time per Layer:".bright_magenta().bold());
+    println!("   {:>7}  {:>12}  {:>6}", "Visited", "Time", "Share");
+    for &(visited, time) in layers {
+        let share = if total.is_zero() { 0.0 } else { time.as_secs_f64() / total.as_secs_f64() * 100.0 };
+        let bar = (time.as_secs_f64() / slowest.as_secs_f64() * BAR_WIDTH as f64).round() as usize;
+        println!(
+            "   {:>7}  {:>12}  {:>5.1}%  {}",
+            visited,
+            format!("{:.3?}", time),
+            share,
+            "█".repeat(bar).bright_cyan()
+        );
+    }
+    println!("   {}", format!("{:.3?} in total, including the timing overhead", total).dimmed());
+    println!();
+}
+
+/// Wall-time statistics over the `--repeat` runs.
+fn print_repeat_stats(samples: &[Duration]) {
+    let mut sorted = samples.to_vec();
+    sorted.sort();
+    let count = sorted.len();
+    let median = if count % 2 == 1 {
+        sorted[count / 2]
+    } else {
+        (sorted[count / 2 - 1] + sorted[count / 2]) / 2
+    };
+    let secs: Vec<f64> = sorted.iter().map(Duration::as_secs_f64).collect();
+    let mean = secs.iter().sum::<f64>() / count as f64;
+    let variance = secs.iter().map(|s| (s - mean).powi(2)).sum::<f64>() / count as f64;
+
+    println!("{}", format!("⏱️  Timing over {} runs:", count).bright_magenta().bold());
+    println!("   Min:    {:.3?}", sorted[0]);
+    println!("   Median: {:.3?}", median);
+    println!("   Mean:   {:.3?}", Duration::from_secs_f64(mean));
+    println!("   Stddev: {:.3?}", Duration::from_secs_f64(variance.sqrt()));
+    println!();
+}
+
+/// A `--validate-against` gold answer: the known optimal cost, and
+/// optionally the tour.
+struct ExpectedAnswer {
+    cost: Weight,
+    tour: Option<Vec<usize>>,
+}
+
+/// Reads a gold file: the optimal cost on the first line (`INF` when there
+/// is no valid tour), then optionally the tour as comma- or space-separated
+/// cities. `#` starts a comment.
+fn load_expected(path: &str, format: WeightFormat) -> Result<ExpectedAnswer, String> {
+    let content = read_file(path).map_err(|e| format!("Error reading expected answer '{}': {}", path, e))?;
+    let lines = content_lines(&content);
+    let Some(&(line_no, cost)) = lines.first() else {
+        return Err(format!("Expected answer '{}' is empty", path));
+    };
+    let cost = parse_weight(cost, format)
+        .ok_or_else(|| format!("{}:{}: Invalid expected cost '{}'", path, line_no, cost))?;
+    let tour = match lines.get(1) {
+        Some(&(line_no, tour)) => Some(
+            tour.split(|c: char| c == ',' || c.is_whitespace())
+                .filter(|city| !city.is_empty())
+                .map(|city| city.parse::<usize>().map_err(|_| format!("{}:{}: Invalid city '{}'", path, line_no, city)))
+                .collect::<Result<Vec<_>, _>>()?,
+        ),
+        None => None,
+    };
+    if let Some(&(line_no, _)) = lines.get(2) {
+        return Err(format!("{}:{}: Expected only a cost line and a tour line", path, line_no));
+    }
+    Ok(ExpectedAnswer { cost, tour })
+}
+
+/// `tour` as a closed path starting at City0, or `None` if it isn't a tour
+/// of `n` cities.
+fn normalize_tour(tour: &[usize], n: usize) -> Option<Vec<usize>> {
+    let mut path = validate_tour(tour, n).ok()?;
+    path.pop();
+    let start = path.iter().position(|&city| city == 0)?;
+    path.rotate_left(start);
+    path.push(0);
+    Some(path)
+}
+
+/// Compares a result with the gold answer. Tours match up to the starting
+/// city, and on symmetric instances also reversed. Prints the outcome, the
+/// differences on a mismatch, and returns whether both agree.
+fn check_expected(
+    expected: &ExpectedAnswer,
+    cost: Weight,
+    path: Option<&[usize]>,
+    dist: &[Vec<Weight>],
+    format: WeightFormat,
+) -> bool {
+    let render = |cost: Weight| if is_infeasible(cost) { "no tour".to_string() } else { format.render(cost) };
+    let cost_matches = expected.cost == cost || (is_infeasible(expected.cost) && is_infeasible(cost));
+    let tour_matches = match (&expected.tour, path) {
+        (Some(tour), Some(path)) if !is_infeasible(cost) => {
+            let expected_path = normalize_tour(tour, dist.len());
+            let mut reversed = expected_path.clone();
+            if let Some(reversed) = reversed.as_mut() {
+                reversed.reverse();
+            }
+            expected_path.as_deref() == Some(path) || (is_symmetric(dist) && reversed.as_deref() == Some(path))
+        }
+        _ => true,
+    };
+
+    println!("{}", "📋 Expected Answer:".bright_magenta().bold());
+    if cost_matches && tour_matches {
+        let what = if expected.tour.is_some() && path.is_some() { "cost and tour" } else { "cost" };
+        println!("   {}", format!("✅ Matches the expected {} ({})", what, render(cost)).green());
+        println!();
+        return true;
+    }
+    if !cost_matches {
+        println!("   {}", format!("- cost {}", render(expected.cost)).red());
+        println!("   {}", format!("+ cost {}", render(cost)).green());
+    }
+    if let (false, Some(tour), Some(path)) = (tour_matches, &expected.tour, path) {
+        let tour = normalize_tour(tour, dist.len()).unwrap_or_else(|| tour.clone());
+        println!("   {}", format!("- tour {}", format_path(&tour)).red());
+        println!("   {}", format!("+ tour {}", format_path(path)).green());
+    }
+    println!();
+    false
+}
+
+/// Reads `--precedence` pairs, one `a b` per line meaning City a must be
+/// visited before City b. `#` starts a comment.
+fn load_precedence(path: &str, n: usize) -> Result<Vec<(usize, usize)>, String> {
+    let content = read_file(path)
+        .map_err(|e| format!("Error reading precedence file: {}", e))?;
+
+    let mut pairs = Vec::new();
+    for (line_no, line) in content_lines(&content) {
+        let parts: Vec<&str> = line.split_whitespace().collect();
+        let [before, after] = parts.as_slice() else {
+            return Err(format!("Line {}: expected two cities 'a b'", line_no));
+        };
+        let city = |token: &str| -> Result<usize, String> {
+            match token.parse::<usize>() {
+                Ok(city) if city < n => Ok(city),
+                _ => Err(format!("Line {}: invalid city '{}' (0 to {})", line_no, token, n - 1)),
+            }
+        };
+        let (before, after) = (city(before)?, city(after)?);
+        if after == 0 {
+            return Err(format!("Line {}: City0 is the start and cannot come after City{}", line_no, before));
+        }
+        if before == after {
+            return Err(format!("Line {}: City{} cannot come before itself", line_no, before));
+        }
+        pairs.push((before, after));
+    }
+    Ok(pairs)
+}
+
+/// `--groups`: the cities partitioned into groups, of which the tour visits
+/// exactly one city each.
+struct CityGroups {
+    /// Group of every city, numbered from 0 in order of first appearance,
+    /// so City0's group is group 0
+    of: Vec<usize>,
+    /// The group ids as written in the file, by group number
+    ids: Vec<usize>,
+}
+
+impl CityGroups {
+    fn len(&self) -> usize {
+        self.ids.len()
+    }
+
+    fn members(&self, group: usize) -> Vec<usize> {
+        (0..self.of.len()).filter(|&city| self.of[city] == group).collect()
+    }
+}
+
+/// Reads `--groups`, one `city group` per line (`#` starts a comment).
+/// Every city needs exactly one line; group ids are any non-negative
+/// integers.
+fn load_groups(path: &str, n: usize) -> Result<CityGroups, String> {
+    let content = read_file(path)
+        .map_err(|e| format!("Error reading groups file: {}", e))?;
+    parse_groups(&content, n)
+}
+
+fn parse_groups(content: &str, n: usize) -> Result<CityGroups, String> {
+    let mut id_of = vec![None; n];
+    for (line_no, line) in content_lines(content) {
+        let parts: Vec<&str> = line.split_whitespace().collect();
+        let [city, group] = parts.as_slice() else {
+            return Err(format!("Line {}: expected a city and its group 'city group'", line_no));
+        };
+        let city = match city.parse::<usize>() {
+            Ok(city) if city < n => city,
+            _ => return Err(format!("Line {}: invalid city '{}' (0 to {})", line_no, city, n - 1)),
+        };
+        let group = group
+            .parse::<usize>()
+            .map_err(|_| format!("Line {}: invalid group id '{}'", line_no, group))?;
+        if id_of[city].replace(group).is_some() {
+            return Err(format!("Line {}: City{} already has a group", line_no, city));
+        }
+    }
+    if let Some(missing) = id_of.iter().position(Option::is_none) {
+        return Err(format!("City{} has no group", missing));
+    }
+
+    let mut ids = Vec::new();
+    let of = id_of
+        .into_iter()
+        .flatten()
+        .map(|id| match ids.iter().position(|&known| known == id) {
+            Some(group) => group,
+            None => {
+                ids.push(id);
+                ids.len() - 1
+            }
+        })
+        .collect();
+    Ok(CityGroups { of, ids })
+}
+
+/// `--groups`: a small generalized TSP, solved with Held-Karp over groups
+/// instead of cities. A state is the set of groups visited so far plus the
+/// city the tour stands in, so each group is entered exactly once, through
+/// whichever member makes the whole tour best. City0 starts the tour and
+/// stands for its own group. Returns `INF` (`-INF` when maximizing) if no
+/// tour exists.
+fn solve_groups(dist: &[Vec<Weight>], groups: &CityGroups, maximize: bool) -> Result<(Weight, Vec<usize>), String> {
+    let (n, g) = (dist.len(), groups.len());
+    if g == 1 {
+        return Ok((0, vec![0]));
+    }
+    let states = 1usize
+        .checked_shl(g as u32)
+        .and_then(|masks| masks.checked_mul(n))
+        .filter(|&states| states <= GROUPS_MAX_STATES)
+        .ok_or_else(|| format!("--groups: {} groups over {} cities need more than {} DP states", g, n, GROUPS_MAX_STATES))?;
+    let worst = if maximize { -INF } else { INF };
+    let improves = |candidate: Weight, best: Weight| if maximize { candidate > best } else { candidate < best };
+
+    // best[mask * n + city]: best path from City0 through the groups in
+    // mask, ending at city; parent is the city before it
+    let mut best = vec![worst; states];
+    let mut parent = vec![0u32; states];
+    best[n] = 0;
+    let full = (1 << g) - 1;
+    for mask in (1..=full).step_by(2) {
+        for city in 0..n {
+            let cost = best[mask * n + city];
+            if cost == worst {
+                continue;
+            }
+            for (next, &leg) in dist[city].iter().enumerate() {
+                let bit = 1 << groups.of[next];
+                if mask & bit != 0 || leg >= INF {
+                    continue;
+                }
+                let state = (mask | bit) * n + next;
+                if improves(cost + leg, best[state]) {
+                    best[state] = cost + leg;
+                    parent[state] = city as u32;
+                }
+            }
+        }
+    }
+
+    let mut answer = (worst, 0);
+    for city in 0..n {
+        let cost = best[full * n + city];
+        if cost != worst && dist[city][0] < INF && improves(cost + dist[city][0], answer.0) {
+            answer = (cost + dist[city][0], city);
+        }
+    }
+    let (cost, mut city) = answer;
+    if cost == worst {
+        return Ok((cost, vec![0]));
+    }
+    let mut path = vec![0];
+    let mut mask = full;
+    while city != 0 {
+        path.push(city);
+        let previous = parent[mask * n + city] as usize;
+        mask &= !(1 << groups.of[city]);
+        city = previous;
+    }
+    path.push(0);
+    path.reverse();
+    Ok((cost, path))
+}
+
+/// `--groups` report: the tour and the city it chose in every group. The
+/// detailed route follows it.
+fn print_group_tour(
+    cost: Weight,
+    path: &[usize],
+    groups: &CityGroups,
+    format: WeightFormat,
+    separators: bool,
+    depot_highlight: bool,
+) {
+    println!("{}", "🧩 Group Tour:".bright_magenta().bold());
+    if is_infeasible(cost) {
+        println!("   {}", "❌ No tour visits one city of every group".red());
+        println!();
+        return;
+    }
+    println!("   Cost: {}", format.render_grouped(cost, separators).bright_yellow().bold());
+    println!("   Tour: {}", render_path(path, depot_highlight));
+    println!("   Representatives:");
+    for (group, &id) in groups.ids.iter().enumerate() {
+        let members = groups.members(group);
+        let chosen = members.iter().find(|city| path.contains(city)).copied().unwrap_or(0);
+        let others: Vec<String> = members.iter().filter(|&&city| city != chosen).map(|city| format!("City{}", city)).collect();
+        let alternatives = if others.is_empty() { "the only member".to_string() } else { format!("over {}", others.join(", ")) };
+        println!("      group {}: {}  {}", id, format!("City{}", chosen).bright_cyan(), alternatives.dimmed());
+    }
+}
+
+/// True when the precedence pairs contain a cycle, so no order satisfies
+/// them all.
+fn has_precedence_cycle(pairs: &[(usize, usize)], n: usize) -> bool {
+    // Repeatedly place every city whose predecessors are all placed
+    let mut predecessors = vec![0usize; n];
+    for &(before, after) in pairs {
+        predecessors[after] |= 1 << before;
+    }
+    let mut placed = 0usize;
+    loop {
+        let ready = (0..n)
+            .filter(|&c| placed & (1 << c) == 0 && predecessors[c] & !placed == 0)
+            .fold(0, |mask, c| mask | (1 << c));
+        if ready == 0 {
+            return placed.count_ones() as usize != n;
+        }
+        placed |= ready;
+    }
+}
+
+/// Parses an interactive edit command of the form `i j newval`.
+fn parse_edit(text: &str, n: usize, format: WeightFormat) -> Result<(usize, usize, Weight), String> {
+    let parts: Vec<&str> = text.split_whitespace().collect();
+    let [i, j, value] = parts.as_slice() else {
+        return Err("Expected three values: i j newval".to_string());
+    };
+
+    let i: usize = i.parse().map_err(|_| format!("Invalid row index '{}'", i))?;
+    let j: usize = j.parse().map_err(|_| format!("Invalid column index '{}'", j))?;
+    if i >= n || j >= n {
+        return Err(format!("City index out of range (0 to {})", n - 1));
+    }
+    if i == j {
+        return Err("Diagonal entries must stay 0".to_string());
+    }
+
+    Ok((i, j, parse_entry(value, format)?))
+}
+
+/// Applies one `i j newval` edit, mirrored into `dist[j][i]` when
+/// `symmetric`.
+fn apply_edit(distances: &mut [Vec<Weight>], command: &str, format: WeightFormat, symmetric: bool) -> Result<(), String> {
+    let (i, j, value) = parse_edit(command, distances.len(), format)?;
+    distances[i][j] = value;
+    if symmetric {
+        distances[j][i] = value;
+    }
+    Ok(())
+}
+
+/// One matrix entry typed at a prompt; `INF` marks a forbidden edge.
+fn parse_entry(text: &str, format: WeightFormat) -> Result<Weight, String> {
+    let value = parse_weight(text, format).ok_or_else(|| format!("Invalid value '{}'", text.trim()))?;
+    if value > INF || value <= -INF {
+        return Err("Value is too large; use INF for a forbidden edge".to_string());
+    }
+    Ok(value)
+}
+
+/// Fills an `n × n` matrix one cell at a time, row by row: the diagonal is
+/// 0, and unless `directed` only the cells above it are asked for and
+/// mirrored below. `ask(i, j)` supplies `dist[i][j]`; `row_done` sees the
+/// partly filled matrix after every row that asked for something.
+fn fill_matrix_grid<E>(
+    n: usize,
+    directed: bool,
+    mut ask: impl FnMut(usize, usize) -> Result<Weight, E>,
+    mut row_done: impl FnMut(&[Vec<Option<Weight>>]),
+) -> Result<Vec<Vec<Weight>>, E> {
+    let mut grid: Vec<Vec<Option<Weight>>> = (0..n).map(|i| (0..n).map(|j| (i == j).then_some(0)).collect()).collect();
+    for i in 0..n {
+        let columns: Vec<usize> = (0..n).filter(|&j| if directed { j != i } else { j > i }).collect();
+        for &j in &columns {
+            let value = ask(i, j)?;
+            grid[i][j] = Some(value);
+            if !directed {
+                grid[j][i] = Some(value);
+            }
+        }
+        if !columns.is_empty() {
+            row_done(&grid);
+        }
+    }
+    Ok(grid.into_iter().map(|row| row.into_iter().map(|cell| cell.unwrap_or(0)).collect()).collect())
+}
+
+/// `--interactive-matrix`, or "Enter matrix manually": asks for the number
+/// of cities and then every cell as `dist[i][j] = ?`, reprinting the matrix
+/// after each row.
+fn enter_matrix_grid(parse: &ParseOptions, depot_highlight: bool) -> Result<Vec<Vec<Weight>>, Box<dyn std::error::Error>> {
+    let n: usize = Input::with_theme(&ColorfulTheme::default())
+        .with_prompt("Number of cities")
+        .validate_with(|n: &usize| parse.check_city_count(*n))
+        .interact_text()?;
+    let mirrored = if parse.directed { "" } else { ", and dist[j][i] mirrors dist[i][j]" };
+    println!(
+        "{}",
+        format!("ℹ️  Type each distance, or INF for no edge; the diagonal is 0{}\n", mirrored).dimmed()
+    );
+
+    let matrix = fill_matrix_grid(
+        n,
+        parse.directed,
+        |i, j| -> Result<Weight, Box<dyn std::error::Error>> {
+            let text: String = Input::with_theme(&ColorfulTheme::default())
+                .with_prompt(format!("dist[{}][{}] = ?", i, j))
+                .validate_with(|text: &String| parse_entry(text, parse.weight_format).map(|_| ()))
+                .interact_text()?;
+            Ok(parse_entry(&text, parse.weight_format)?)
+        },
+        |grid| {
+            println!();
+            print_matrix_cells(grid, depot_highlight);
+        },
+    )?;
+    Ok(matrix)
+}
+
+/// Lets the user patch individual entries after loading. Edits to a
+/// symmetric matrix are mirrored so it stays undirected, unless `--directed`.
+fn edit_matrix(
+    distances: &mut [Vec<Weight>],
+    format: WeightFormat,
+    directed: bool,
+    depot_highlight: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let n = distances.len();
+    let symmetric = !directed && is_symmetric(distances);
+
+    loop {
+        let edit = Confirm::with_theme(&ColorfulTheme::default())
+            .with_prompt("Edit an entry?")
+            .default(false)
+            .interact()?;
+        if !edit {
+            return Ok(());
+        }
+
+        let command: String = Input::with_theme(&ColorfulTheme::default())
+            .with_prompt("Enter 'i j newval' (newval may be INF)")
+            .validate_with(|text: &String| parse_edit(text, n, format).map(|_| ()))
+            .interact_text()?;
+        apply_edit(distances, &command, format, symmetric)?;
+        println!();
+        print_matrix(distances, depot_highlight);
+    }
+}
+
+fn get_input_method() -> Result<bool, Box<dyn std::error::Error>> {
+    let options = vec![
+        "📁 Load from file",
+        "⌨️  Enter matrix manually",
+    ];
+
+    let selection = Select::with_theme(&ColorfulTheme::default())
+        .with_prompt("How would you like to input the distance matrix?")
+        .items(&options)
+        .default(0)
+        .interact()?;
+
+    Ok(selection == 0)
+}
+
+fn get_file_path() -> Result<String, Box<dyn std::error::Error>> {
+    loop {
+        let file_path: String = Input::with_theme(&ColorfulTheme::default())
+            .with_prompt("Enter the path to your matrix file")
+            .interact_text()?;
+
+        if Path::new(&file_path).exists() {
+            return Ok(file_path);
+        } else {
+            println!(
+                "{}",
+                "❌ File not found! Please check the path and try again.".red()
+            );
+            println!();
+        }
+    }
+}
+
+fn create_progress_bar() -> ProgressBar {
+    let pb = ProgressBar::new(100);
+    pb.set_style(
+        ProgressStyle::default_bar()
+            .template(
+                "{spinner:.green} [{elapsed_precise}] [{bar:40.cyan/blue}] {pos}% ({eta}) {prefix:.dim} {msg}",
+            )
+            .unwrap()
+            .progress_chars("█▉▊▋▌▍▎▏  "),
+    );
+    pb
+}
+
+/// Hard caps for `--safe`, checked before anything big is read or allocated.
+#[derive(Clone, Copy, Debug)]
+struct SafeLimits {
+    max_cities: usize,
+    /// Largest allowed absolute weight; `INF` entries are always allowed
+    max_weight: Weight,
+    max_file_bytes: u64,
+}
+
+impl Default for SafeLimits {
+    fn default() -> Self {
+        SafeLimits {
+            max_cities: 18,
+            max_weight: 1_000_000,
+            max_file_bytes: 1 << 20,
+        }
+    }
+}
+
+impl SafeLimits {
+    fn check_file(&self, path: &str) -> Result<(), String> {
+        let size = fs::metadata(path)
+            .map_err(|e| format!("Error reading file '{}': {}", path, e))?
+            .len();
+        if size > self.max_file_bytes {
+            return Err(format!(
+                "Safe mode: '{}' is {} bytes, the limit is {}",
+                path, size, self.max_file_bytes
+            ));
+        }
+        Ok(())
+    }
+
+    fn check_matrix(&self, dist: &[Vec<Weight>]) -> Result<(), String> {
+        if dist.len() > self.max_cities {
+            return Err(format!(
+                "Safe mode: {} cities, the limit is {}",
+                dist.len(),
+                self.max_cities
+            ));
+        }
+        for (i, row) in dist.iter().enumerate() {
+            for (j, &d) in row.iter().enumerate() {
+                if d < INF && d.unsigned_abs() > self.max_weight.unsigned_abs() {
+                    return Err(format!(
+                        "Safe mode: weight {} from City{} to City{} is outside ±{}",
+                        d, i, j, self.max_weight
+                    ));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Outcome of one instance in `--dir` mode.
+struct BatchResult {
+    file: String,
+    cities: usize,
+    /// `None` when no valid tour exists
+    solution: Option<(Weight, Vec<usize>)>,
+    elapsed: Duration,
+    computed_states: usize,
+}
+
+/// Loads and solves one instance for `--dir`, with the same parse and solver
+/// settings as a single run but without any terminal output.
+fn solve_batch_instance(path: &Path, options: &CliOptions) -> Result<BatchResult, String> {
+    let path_text = path.to_string_lossy();
+    if let Some(limits) = &options.safe {
+        limits.check_file(&path_text)?;
+    }
+    let mut distances = options.instance_loader().load_file(&path_text)?.distances;
+    if distances.len() > 1 && !has_finite_edge(&distances) {
+        return Err(NO_FINITE_EDGES.to_string());
+    }
+    if let Some(limits) = &options.safe {
+        limits.check_matrix(&distances)?;
+    }
+    if let (true, Some(unit)) = (options.convert, options.unit) {
+        convert_distance_units(&mut distances, unit);
+    }
+    forbid_edges(&mut distances, &options.forbid, options.directed)?;
+    if let Some(max) = options.max_leg {
+        cap_leg_length(&mut distances, max);
+    }
+    check_weight_range(&distances)?;
+    if distances.len() > BATCH_MAX_CITIES {
+        return Err(format!(
+            "{} cities, batch mode solves at most {}",
+            distances.len(),
+            BATCH_MAX_CITIES
+        ));
+    }
+
+    let cities = distances.len();
+    let start_time = Instant::now();
+    let mut solver = TSPSolver::new(distances)?;
+    solver.set_maximize(options.maximize);
+    if let Some(backend) = options.memo {
+        solver.set_memo_backend(backend);
+    }
+    solver.set_announce(false);
+    let (cost, tour) = solver.solve();
+    solver.check_reconstruction(cost, &tour)?;
+
+    Ok(BatchResult {
+        file: path.file_name().map_or(path_text.to_string(), |name| name.to_string_lossy().to_string()),
+        cities,
+        solution: (!is_infeasible(cost)).then_some((cost, tour)),
+        elapsed: start_time.elapsed(),
+        computed_states: solver.computed_states,
+    })
+}
+
+/// Quotes a CSV field when it contains a separator, quote or line break.
+fn csv_field(text: &str) -> String {
+    if text.contains([',', '"', '\n']) {
+        format!("\"{}\"", text.replace('"', "\"\""))
+    } else {
+        text.to_string()
+    }
+}
+
+/// Where 2-opt starts for `--start-tour` and `--stream`.
+#[derive(Clone, Debug)]
+enum StartTour {
+    Given(Vec<usize>),
+    /// `--start-tour nn`: built with `nearest_neighbor_tour`
+    NearestNeighbor,
+}
+
+impl StartTour {
+    fn parse(text: &str) -> Result<Self, String> {
+        if text.eq_ignore_ascii_case("nn") {
+            Ok(StartTour::NearestNeighbor)
+        } else {
+            parse_city_list(text).map(StartTour::Given)
+        }
+    }
+}
+
+/// Nearest-neighbor construction from City0 (farthest neighbor when
+/// maximizing), over finite edges as long as there are any. Equally near
+/// cities go to the lowest index, so the tour is reproducible; with `ties`
+/// one of them is drawn at random instead, for diverse restarts.
+fn nearest_neighbor_tour<D: DistanceProvider + ?Sized>(
+    dist: &D,
+    n: usize,
+    maximize: bool,
+    mut ties: Option<&mut SplitMix64>,
+) -> Vec<usize> {
+    let mut visited = vec![false; n];
+    let mut tour = vec![0];
+    visited[0] = true;
+    for _ in 1..n {
+        let current = tour[tour.len() - 1];
+        // Lower is better; forbidden edges only when nothing else is left
+        let key = |city: usize| {
+            let d = dist.dist(current, city);
+            match (d >= INF, maximize) {
+                (true, _) => (1, 0),
+                (false, true) => (0, -WideWeight::from(d)),
+                (false, false) => (0, WideWeight::from(d)),
+            }
+        };
+        let unvisited = (0..n).filter(|&city| !visited[city]);
+        let best = unvisited.clone().map(key).min().expect("an unvisited city remains");
+        let nearest: Vec<usize> = unvisited.filter(|&city| key(city) == best).collect();
+        let next = match ties.as_deref_mut() {
+            Some(rng) => nearest[rng.below(nearest.len() as u64) as usize],
+            None => nearest[0],
+        };
+        visited[next] = true;
+        tour.push(next);
+    }
+    tour.push(0);
+    tour
+}
+
+/// SplitMix64: a tiny seeded generator, enough for reproducible test data.
+struct SplitMix64(u64);
+
+impl SplitMix64 {
+    fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// Uniform in `0..bound` (up to a negligible modulo bias).
+    fn below(&mut self, bound: u64) -> u64 {
+        self.next_u64() % bound
+    }
+}
+
+/// Exhaustive optimum over every tour from City0, for cross-checking.
+fn brute_force_optimum(dist: &[Vec<Weight>], maximize: bool) -> Weight {
+    brute_force_by(dist.len(), maximize, &|path| tour_cost(dist, path).unwrap_or(INF))
+}
+
+/// Like `brute_force_optimum`, with the tour cost computed by `cost`.
+fn brute_force_by(n: usize, maximize: bool, cost: &dyn Fn(&[usize]) -> Weight) -> Weight {
+    fn extend(n: usize, cost_of: &dyn Fn(&[usize]) -> Weight, path: &mut Vec<usize>, maximize: bool, best: &mut Option<Weight>) {
+        if path.len() == n {
+            path.push(0);
+            let cost = cost_of(path);
+            path.pop();
+            let better = best.is_none_or(|best| if maximize { cost > best } else { cost < best });
+            if !is_infeasible(cost) && better {
+                *best = Some(cost);
+            }
+            return;
+        }
+        for city in 1..n {
+            if !path.contains(&city) {
+                path.push(city);
+                extend(n, cost_of, path, maximize, best);
+                path.pop();
+            }
+        }
+    }
+
+    let mut best = None;
+    extend(n, cost, &mut vec![0], maximize, &mut best);
+    match best {
+        Some(cost) => cost,
+        None if n <= 1 => 0,
+        None if maximize => -INF,
+        None => INF,
+    }
+}
+
+/// Checks one random instance for `--selftest`, returning what went wrong.
+fn selftest_instance(dist: &[Vec<Weight>], maximize: bool) -> Vec<String> {
+    let n = dist.len();
+    let solver_for = |dist: &[Vec<Weight>]| {
+        let mut solver = TSPSolver::new(dist.to_vec()).expect("self-test instances are small");
+        solver.set_maximize(maximize);
+        solver.set_announce(false);
+        solver
+    };
+    let better = |a: Weight, b: Weight| if maximize { a > b } else { a < b };
+    let mut failures = Vec::new();
+
+    let mut solver = solver_for(dist);
+    let (cost, path) = solver.solve();
+    let again = solver_for(dist).solve();
+    if again != (cost, path.clone()) {
+        failures.push(format!("DP not reproducible: {} then {}", cost, again.0));
+    }
+    if let Err(e) = solver.verify_path(cost, &path) {
+        failures.push(format!("DP tour invalid: {}", e));
+    }
+    let exhaustive = brute_force_optimum(dist, maximize);
+    if exhaustive != cost && !(is_infeasible(exhaustive) && is_infeasible(cost)) {
+        failures.push(format!("DP cost {} but exhaustive search finds {}", cost, exhaustive));
+    }
+    if solver_for(dist).solve_cost() != cost {
+        failures.push("--cost-only disagrees with the full solve".to_string());
+    }
+    if let Some((best, _)) = solver.solve_k_best(1).first() {
+        if *best != cost {
+            failures.push(format!("k-best #1 costs {} instead of {}", best, cost));
+        }
+    }
+    if is_infeasible(cost) || n < 2 {
+        return failures;
+    }
+
+    let nearest = nearest_neighbor_tour(dist, n, maximize, None);
+    if nearest != nearest_neighbor_tour(dist, n, maximize, None) {
+        failures.push("Nearest-neighbor tour not reproducible".to_string());
+    }
+    if validate_tour(&nearest, n).is_err() || nearest.first() != Some(&0) {
+        failures.push(format!("Nearest neighbor built an invalid tour {}", format_path(&nearest)));
+    }
+
+    match solver.solve_heuristic_from((0..n).collect()) {
+        Ok((heuristic, tour)) => {
+            if validate_tour(&tour, n).is_err() || tour.first() != Some(&0) {
+                failures.push(format!("2-opt returned an invalid tour {}", format_path(&tour)));
+            } else if tour_cost(dist, &tour).unwrap_or(INF) != heuristic {
+                failures.push(format!("2-opt reports {} for a tour costing {}", heuristic, tour_cost(dist, &tour).unwrap_or(INF)));
+            }
+            if !is_infeasible(heuristic) && better(heuristic, cost) {
+                failures.push(format!("2-opt found {}, beating the optimum {}", heuristic, cost));
+            }
+        }
+        Err(e) => failures.push(format!("2-opt failed: {}", e)),
+    }
+    if !maximize {
+        let held_karp = solver.held_karp_bound(DEFAULT_BOUND_ITERATIONS);
+        if held_karp > cost {
+            failures.push(format!("Held-Karp bound {} exceeds the optimum {}", held_karp, cost));
+        }
+        let assignment = assignment_lower_bound(dist, n);
+        if assignment > cost {
+            failures.push(format!("Assignment bound {} exceeds the optimum {}", assignment, cost));
+        }
+    }
+    failures
+}
+
+/// `--selftest`: solves seeded random instances with the DP, exhaustive
+/// search, nearest neighbor, 2-opt and the lower bounds, and checks they
+/// agree. Not listed in the README; it is a confidence check for people
+/// changing the solver.
+fn run_selftest(seed: u64) -> Result<(), Box<dyn std::error::Error>> {
+    println!("{}", format!("🧪 Self-test: {} random instances, seed {}", SELFTEST_RUNS, seed).bright_cyan());
+    let failed = run_selftest_instances(seed, SELFTEST_RUNS);
+    if failed == 0 {
+        println!("{}", format!("✅ All {} instances passed", SELFTEST_RUNS).green());
+        Ok(())
+    } else {
+        Err(format!("{} of {} self-test instances failed", failed, SELFTEST_RUNS).into())
+    }
+}
+
+/// Checks `runs` random instances drawn from `seed`, printing what went
+/// wrong, and returns how many failed.
+fn run_selftest_instances(seed: u64, runs: usize) -> usize {
+    let mut rng = SplitMix64(seed);
+    let mut failed = 0;
+    for run in 1..=runs {
+        let n = 1 + rng.below(SELFTEST_MAX_CITIES as u64) as usize;
+        let symmetric = rng.below(2) == 0;
+        let maximize = rng.below(4) == 0;
+        let mut weight = || if rng.below(10) == 0 { INF } else { 1 + rng.below(100) as Weight };
+        let mut dist = vec![vec![0; n]; n];
+        for (i, j) in (0..n).flat_map(|i| (i + 1..n).map(move |j| (i, j))) {
+            dist[i][j] = weight();
+            dist[j][i] = if symmetric { dist[i][j] } else { weight() };
+        }
+
+        let failures = selftest_instance(&dist, maximize);
+        if !failures.is_empty() {
+            failed += 1;
+            let kind = if symmetric { "symmetric" } else { "asymmetric" };
+            let objective = if maximize { ", maximize" } else { "" };
+            println!("{}", format!("❌ Instance {} ({} cities, {}{}):", run, n, kind, objective).red());
+            for failure in failures {
+                println!("   {}", failure);
+            }
+        }
+    }
+    failed
+}
+
+/// `--stream` mode: streams a large coordinate file and improves a tour
+/// (`--start-tour`, or the cities in file order) with 2-opt on distances
+/// computed on the fly, so no n×n matrix is ever built. The Held-Karp bound
+/// needs `O(n²)` work per iteration and is left out.
+fn run_streamed(path: &str, options: &CliOptions) -> Result<(), Box<dyn std::error::Error>> {
+    if let Some(limits) = &options.safe {
+        limits.check_file(path)?;
+    }
+    let load_start = Instant::now();
+    let coords = stream_coordinates(path, &options.parse_options())
+        .map_err(|e| format!("Error parsing input: {}", e))?;
+    let n = coords.coords.len();
+    println!("✅ Streamed {} cities in {:.3?}\n", n, load_start.elapsed());
+
+    let tour = match &options.start_tour {
+        Some(StartTour::Given(tour)) => tour.clone(),
+        Some(StartTour::NearestNeighbor) => options.nearest_neighbor(&coords, n),
+        None => (0..n).collect(),
+    };
+    let tour = validate_tour(&tour, n).map_err(|e| format!("Invalid start tour: {}", e))?;
+    if tour[0] != 0 {
+        return Err("Invalid start tour: it must start at City0".into());
+    }
+    let start_cost = tour_cost(&coords, &tour).unwrap_or(INF);
+
+    let start_time = Instant::now();
+    let path = two_opt(&coords, tour, options.maximize);
+    let elapsed = start_time.elapsed();
+    print_heuristic_result(
+        start_cost,
+        tour_cost(&coords, &path).unwrap_or(INF),
+        &path,
+        elapsed,
+        None,
+        options.weight_format,
+        options.depot_highlight,
+    );
+    print_farewell();
+    Ok(())
+}
+
+/// `--dir` mode: solves every instance file in `dir` (not recursively) on
+/// `options.jobs` threads. Writes `results.csv` plus one `<file>.solution.txt`
+/// per instance to the output directory and keeps going past failures.
+fn run_directory(dir: &str, options: &CliOptions) -> Result<(), Box<dyn std::error::Error>> {
+    let mut files: Vec<_> = fs::read_dir(dir)
+        .map_err(|e| format!("Error reading directory '{}': {}", dir, e))?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| {
+            path.is_file()
+                && path
+                    .extension()
+                    .and_then(|ext| ext.to_str())
+                    .is_some_and(|ext| BATCH_EXTENSIONS.contains(&ext.to_lowercase().as_str()))
+        })
+        .collect();
+    files.sort();
+    if files.is_empty() {
+        return Err(format!("No instance files ({}) in '{}'", BATCH_EXTENSIONS.join(", "), dir).into());
+    }
+
+    let out_dir = options
+        .batch_out
+        .clone()
+        .unwrap_or_else(|| Path::new(dir).join("results").to_string_lossy().to_string());
+    fs::create_dir_all(&out_dir).map_err(|e| format!("Error creating '{}': {}", out_dir, e))?;
+
+    println!(
+        "📂 Solving {} instances from {} on {} thread(s)\n",
+        files.len(),
+        dir.bright_blue(),
+        options.jobs
+    );
+
+    // Workers pull the next file index until the list runs out
+    let next = std::sync::atomic::AtomicUsize::new(0);
+    let results: std::sync::Mutex<Vec<Option<Result<BatchResult, String>>>> =
+        std::sync::Mutex::new((0..files.len()).map(|_| None).collect());
+    std::thread::scope(|scope| {
+        for _ in 0..options.jobs.min(files.len()) {
+            scope.spawn(|| loop {
+                let index = next.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+                let Some(path) = files.get(index) else {
+                    break;
+                };
+                let result = solve_batch_instance(path, options);
+                let name = path.display();
+                match &result {
+                    Ok(BatchResult { solution: Some((cost, _)), elapsed, .. }) => println!(
+                        "   ✅ {}: cost {} ({:.3?})",
+                        name,
+                        options.weight_format.render(*cost),
+                        elapsed
+                    ),
+                    Ok(_) => println!("   ❌ {}: no valid tour", name),
+                    Err(e) => println!("   {} {}: {}", "⚠️ ".yellow(), name, e.red()),
+                }
+                results.lock().unwrap()[index] = Some(result);
+            });
+        }
+    });
+
+    let results = results.into_inner().unwrap();
+    let mut csv = String::from("file,cities,status,cost,time_ms,dp_states,error\n");
+    let mut failures = Vec::new();
+    for (path, result) in files.iter().zip(results) {
+        let file = path.file_name().map_or(path.to_string_lossy(), |name| name.to_string_lossy());
+        match result.expect("every file is solved by some worker") {
+            Ok(result) => {
+                let (status, cost) = match &result.solution {
+                    Some((cost, _)) => ("ok", cost.to_string()),
+                    None => ("infeasible", String::new()),
+                };
+                csv += &format!(
+                    "{},{},{},{},{:.3},{},\n",
+                    csv_field(&result.file),
+                    result.cities,
+                    status,
+                    cost,
+                    result.elapsed.as_secs_f64() * 1000.0,
+                    result.computed_states
+                );
+
+                let mut report = format!("instance: {}\ncities: {}\n", result.file, result.cities);
+                match &result.solution {
+                    Some((cost, tour)) => {
+                        report += &format!("cost: {}\ntour: {}\n", cost, format_permutation(tour, true));
+                    }
+                    None => report += "cost: none (no valid tour)\n",
+                }
+                report += &format!("time_ms: {:.3}\n", result.elapsed.as_secs_f64() * 1000.0);
+                let report_path = Path::new(&out_dir).join(format!("{}.solution.txt", result.file));
+                fs::write(&report_path, report)
+                    .map_err(|e| format!("Error writing '{}': {}", report_path.display(), e))?;
+            }
+            Err(e) => {
+                csv += &format!("{},,error,,,,{}\n", csv_field(&file), csv_field(&e));
+                failures.push((file.to_string(), e));
+            }
+        }
+    }
+    let csv_path = Path::new(&out_dir).join("results.csv");
+    fs::write(&csv_path, csv).map_err(|e| format!("Error writing '{}': {}", csv_path.display(), e))?;
+
+    println!();
+    println!("📄 Results written to {}", csv_path.display().to_string().bright_blue());
+    if failures.is_empty() {
+        println!("{}", format!("✅ All {} instances processed", files.len()).green());
+        Ok(())
+    } else {
+        println!("{}", format!("❌ {} of {} instances failed:", failures.len(), files.len()).red().bold());
+        for (file, error) in &failures {
+            println!("   {}: {}", file, error);
+        }
+        Err(format!("{} of {} instances failed", failures.len(), files.len()).into())
+    }
+}
+
+/// Options given on the command line. The first non-flag argument is the
+/// input file path.
+struct CliOptions {
+    file_path: Option<String>,
+    evaluate: Option<Vec<usize>>,
+    maximize: bool,
+    log_path: Option<String>,
+    rounding: Option<Rounding>,
+    parallel_edges: ParallelEdges,
+    checkpoint: Option<String>,
+    checkpoint_every: usize,
+    resume: Option<String>,
+    quiet: bool,
+    /// Colored output; off with `--no-color` or `--headless`
+    color: bool,
+    /// Progress bars; off with `--no-progress` or `--headless`
+    progress: bool,
+    /// Print only the result as JSON on stdout (`--json` or `--headless`)
+    json: bool,
+    /// `--headless`: never prompt, and read stdin when no file is given
+    headless: bool,
+    memo: Option<MemoBackend>,
+    stats_json: Option<String>,
+    permutation_export: Option<String>,
+    append_return: bool,
+    legs_csv: Option<String>,
+    /// `--route-limit`: legs shown at each end of a long detailed route
+    route_limit: Option<usize>,
+    /// `--prefer-uniform`: break cost ties by fewest distinct leg weights
+    prefer_uniform: bool,
+    /// `--cycle-notation`: also print the tour as `(0 3 1 2 | back to 0, +5)`
+    cycle_notation: bool,
+    /// `--tui`: a live dashboard while the DP runs
+    tui: bool,
+    k_best: Option<usize>,
+    weight_format: WeightFormat,
+    shortest_path_complete: bool,
+    directed: bool,
+    matrix_stats: bool,
+    check: bool,
+    skip: Option<Vec<usize>>,
+    repeat: usize,
+    at_position: Option<(usize, usize)>,
+    precedence: Option<String>,
+    /// `--groups` file: visit one city of each group
+    groups: Option<String>,
+    /// `--service-times` file: a time per city, charged on entering it
+    service_times: Option<String>,
+    topology: Option<String>,
+    weights: Option<String>,
+    layer_report: bool,
+    safe: Option<SafeLimits>,
+    /// Thousands separators in the solution report
+    separators: bool,
+    /// 🏠 marker for the depot; off with `--no-depot-highlight`
+    depot_highlight: bool,
+    cost_only: bool,
+    trace_states: bool,
+    /// Read the file as this format instead of detecting it
+    input_format: Option<InputFormat>,
+    strict_format: bool,
+    has_header: bool,
+    has_index_col: bool,
+    /// CSV cell separator from `--delimiter`
+    delimiter: Option<char>,
+    self_check: bool,
+    bearings: bool,
+    /// Stream a large coordinate file into the 2-opt heuristic
+    stream: bool,
+    /// Report the optimum (and `--count-tours`) modulo this value
+    modulus: Option<u64>,
+    count_tours: bool,
+    /// `--state-graph` destination; DOT for `.dot`/`.gv`, else an edge list
+    state_graph: Option<String>,
+    /// `--grade-log` destination for the canonical decision log
+    grade_log: Option<String>,
+    assignment_bound: bool,
+    selftest: bool,
+    timing_histogram: bool,
+    show_ties: bool,
+    /// Seed for randomized modes
+    seed: Option<u64>,
+    /// Markdown report destination, `-` for stdout
+    markdown: Option<String>,
+    png: Option<String>,
+    geojson: Option<String>,
+    matrix_cache: Option<MatrixCache>,
+    /// Gold file with the known optimal cost (and tour) to compare against
+    validate_against: Option<String>,
+    reflow: bool,
+    /// `--dump-dp` destination for the filled table
+    dump_dp: Option<String>,
+    all_starts: bool,
+    leg_profile: Vec<Weight>,
+    emit_matrix: Option<String>,
+    interactive_matrix: bool,
+    /// `--b64`: the instance text inline, base64-encoded
+    b64: Option<String>,
+    /// `--load-binary` instance file in the `--emit-binary` format
+    load_binary: Option<String>,
+    emit_binary: Option<String>,
+    forbid: Vec<(usize, usize)>,
+    /// Longest allowed single leg
+    max_leg: Option<Weight>,
+    /// Cities the tour may leave out, each at `skip_penalty`
+    optional: Vec<usize>,
+    skip_penalty: Option<Weight>,
+    unit: Option<Unit>,
+    /// Input is in the other of km/mi and gets converted into `unit`
+    convert: bool,
+    start_tour: Option<StartTour>,
+    /// Seed for random tie-breaks in the nearest-neighbor start tour
+    nn_random_ties: Option<u64>,
+    /// `--restarts`: 2-opt runs for `--start-tour`, the first from its tour
+    restarts: usize,
+    /// Refuse `--start-tour` on a matrix breaking the triangle inequality
+    require_metric: bool,
+    /// Subgradient iterations for the Held-Karp bound
+    bound_iterations: usize,
+    savings: bool,
+    /// Directory of instances to solve in batch
+    dir: Option<String>,
+    /// Worker threads for `--dir`
+    jobs: usize,
+    /// Where `--dir` writes its results, `<dir>/results` by default
+    batch_out: Option<String>,
+}
+
+impl CliOptions {
+    /// The `--start-tour nn` tour, announced with its tie-break rule.
+    fn nearest_neighbor<D: DistanceProvider + ?Sized>(&self, dist: &D, n: usize) -> Vec<usize> {
+        let mut rng = self.nn_random_ties.map(SplitMix64);
+        let tour = nearest_neighbor_tour(dist, n, self.maximize, rng.as_mut());
+        let ties = match self.nn_random_ties {
+            Some(seed) => format!("random ties, seed {}", seed),
+            None => "ties to the lowest index".to_string(),
+        };
+        println!("{}", format!("🧭 Nearest-neighbor start tour ({})\n", ties).dimmed());
+        tour
+    }
+
+    fn parse_options(&self) -> ParseOptions {
+        ParseOptions {
+            rounding: self.rounding,
+            weight_format: self.weight_format,
+            directed: self.directed,
+            max_cities: self.safe.map(|limits| limits.max_cities),
+            delimiter: self.delimiter,
+            reflow: self.reflow,
+            parallel_edges: self.parallel_edges,
+        }
+    }
+
+    /// Whether the command line names the instance (a file, `--topology`,
+    /// `--b64` or `--load-binary`) rather than leaving it to be entered.
+    fn has_instance_source(&self) -> bool {
+        self.file_path.is_some() || self.topology.is_some() || self.b64.is_some() || self.load_binary.is_some()
+    }
+
+    fn instance_loader(&self) -> InstanceLoader {
+        let mut loader = InstanceLoader::new(self.parse_options());
+        if let Some(format) = self.input_format {
+            loader.set_format(format);
+        }
+        loader.set_strict(self.strict_format);
+        loader.set_headers(self.has_header, self.has_index_col);
+        if let Some(cache) = &self.matrix_cache {
+            loader.set_matrix_cache(cache.clone());
+        }
+        loader
+    }
+}
+
+/// A single CSV separator character; `\t` or `tab` selects a tab.
+fn parse_delimiter(text: &str) -> Result<char, String> {
+    let text = if text == "\\t" || text.eq_ignore_ascii_case("tab") { "\t" } else { text };
+    let mut chars = text.chars();
+    match (chars.next(), chars.next()) {
+        (Some(c), None) if !c.is_alphanumeric() && !matches!(c, '.' | '-' | '#' | ' ') => Ok(c),
+        _ => Err(format!("Invalid delimiter '{}' (expected one character such as ';' or ',')", text)),
+    }
+}
+
+fn flag_value(args: &mut impl Iterator<Item = String>, flag: &str) -> Result<String, String> {
+    args.next()
+        .ok_or_else(|| format!("Option '{}' requires a value", flag))
+}
+
+impl Default for CliOptions {
+    fn default() -> Self {
+        CliOptions {
+            file_path: None,
+            evaluate: None,
+            maximize: false,
+            log_path: None,
+            rounding: None,
+            parallel_edges: ParallelEdges::Min,
+            checkpoint: None,
+            checkpoint_every: DEFAULT_CHECKPOINT_EVERY,
+            resume: None,
+            quiet: false,
+            color: true,
+            progress: true,
+            json: false,
+            headless: false,
+            memo: None,
+            stats_json: None,
+            permutation_export: None,
+            legs_csv: None,
+            route_limit: None,
+            prefer_uniform: false,
+            cycle_notation: false,
+            tui: false,
+            append_return: false,
+            k_best: None,
+            weight_format: WeightFormat::Plain,
+            shortest_path_complete: false,
+            directed: false,
+            matrix_stats: false,
+            check: false,
+            skip: None,
+            repeat: 1,
+            at_position: None,
+            precedence: None,
+            groups: None,
+            service_times: None,
+            topology: None,
+            weights: None,
+            layer_report: false,
+            safe: None,
+            separators: true,
+            depot_highlight: true,
+            cost_only: false,
+            trace_states: false,
+            input_format: None,
+            strict_format: false,
+            has_header: false,
+            has_index_col: false,
+            delimiter: None,
+            self_check: false,
+            bearings: false,
+            stream: false,
+            modulus: None,
+            count_tours: false,
+            state_graph: None,
+            grade_log: None,
+            assignment_bound: false,
+            selftest: false,
+            timing_histogram: false,
+            show_ties: false,
+            seed: None,
+            markdown: None,
+            png: None,
+            geojson: None,
+            matrix_cache: None,
+            validate_against: None,
+            reflow: false,
+            dump_dp: None,
+            all_starts: false,
+            leg_profile: Vec::new(),
+            emit_matrix: None,
+            b64: None,
+            load_binary: None,
+            emit_binary: None,
+            interactive_matrix: false,
+            forbid: Vec::new(),
+            max_leg: None,
+            optional: Vec::new(),
+            skip_penalty: None,
+            unit: None,
+            convert: false,
+            start_tour: None,
+            nn_random_ties: None,
+            restarts: 1,
+            require_metric: false,
+            bound_iterations: DEFAULT_BOUND_ITERATIONS,
+            savings: false,
+            dir: None,
+            jobs: 1,
+            batch_out: None,
+        }
+    }
+}
+
+fn parse_args(mut args: impl Iterator<Item = String>) -> Result<CliOptions, String> {
+    let mut options = CliOptions::default();
+    // Read after the loop, once --time-format is known
+    let mut max_leg = None;
+    let mut skip_penalty = None;
+    // --headless switches these off (or JSON on) unless given explicitly
+    let mut color = None;
+    let mut progress = None;
+    let mut json = None;
+
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--evaluate" => {
+                let value = flag_value(&mut args, "--evaluate")?;
+                options.evaluate = Some(parse_city_list(&value)?);
+            }
+            "--maximize" => options.maximize = true,
+            "--quiet" => options.quiet = true,
+            "--headless" => options.headless = true,
+            "--color" => color = Some(true),
+            "--no-color" => color = Some(false),
+            "--progress" => progress = Some(true),
+            "--no-progress" => progress = Some(false),
+            "--json" => json = Some(true),
+            "--no-json" => json = Some(false),
+            "--time-format" => options.weight_format = WeightFormat::Duration,
+            "--shortest-path-complete" => options.shortest_path_complete = true,
+            "--directed" => options.directed = true,
+            "--matrix-stats" => options.matrix_stats = true,
+            "--check" => options.check = true,
+            "--skip" => options.skip = Some(parse_city_list(&flag_value(&mut args, "--skip")?)?),
+            "--log" => options.log_path = Some(flag_value(&mut args, "--log")?),
+            "--rounding" => {
+                options.rounding = Some(Rounding::parse(&flag_value(&mut args, "--rounding")?)?);
+            }
+            "--parallel-edge" => {
+                options.parallel_edges = ParallelEdges::parse(&flag_value(&mut args, "--parallel-edge")?)?;
+            }
+            "--checkpoint" => options.checkpoint = Some(flag_value(&mut args, "--checkpoint")?),
+            "--checkpoint-every" => {
+                options.checkpoint_every = flag_value(&mut args, "--checkpoint-every")?
+                    .parse()
+                    .map_err(|_| "--checkpoint-every expects a number of states")?;
+            }
+            "--memo" => options.memo = Some(MemoBackend::parse(&flag_value(&mut args, "--memo")?)?),
+            "--start-tour" => options.start_tour = Some(StartTour::parse(&flag_value(&mut args, "--start-tour")?)?),
+            "--nn-random-ties" => {
+                options.nn_random_ties = Some(
+                    flag_value(&mut args, "--nn-random-ties")?
+                        .parse()
+                        .map_err(|_| "--nn-random-ties expects a seed (a non-negative integer)")?,
+                );
+            }
+            "--restarts" => {
+                options.restarts = flag_value(&mut args, "--restarts")?
+                    .parse()
+                    .ok()
+                    .filter(|&runs| runs > 0)
+                    .ok_or("--restarts expects a positive number of runs")?;
+            }
+            "--savings" => options.savings = true,
+            "--bearings" => options.bearings = true,
+            "--stream" => options.stream = true,
+            "--count-tours" => options.count_tours = true,
+            "--assignment-bound" => options.assignment_bound = true,
+            "--require-metric" => options.require_metric = true,
+            "--selftest" => options.selftest = true,
+            "--seed" => {
+                options.seed = Some(
+                    flag_value(&mut args, "--seed")?
+                        .parse()
+                        .map_err(|_| "--seed expects a non-negative integer")?,
+                );
+            }
+            "--state-graph" => options.state_graph = Some(flag_value(&mut args, "--state-graph")?),
+            "--grade-log" => options.grade_log = Some(flag_value(&mut args, "--grade-log")?),
+            "--mod" => {
+                options.modulus = Some(
+                    flag_value(&mut args, "--mod")?
+                        .parse()
+                        .ok()
+                        .filter(|&m| m > 0)
+                        .ok_or("--mod expects a positive integer")?,
+                );
+            }
+            "--dir" => options.dir = Some(flag_value(&mut args, "--dir")?),
+            "--batch-out" => options.batch_out = Some(flag_value(&mut args, "--batch-out")?),
+            "--jobs" => {
+                options.jobs = flag_value(&mut args, "--jobs")?
+                    .parse()
+                    .ok()
+                    .filter(|&jobs| jobs > 0)
+                    .ok_or("--jobs expects a positive number of threads")?;
+            }
+            "--bound-iterations" => {
+                options.bound_iterations = flag_value(&mut args, "--bound-iterations")?
+                    .parse()
+                    .map_err(|_| "--bound-iterations expects a number of iterations")?;
+            }
+            "--forbid" => options.forbid.push(parse_forbidden_edge(&flag_value(&mut args, "--forbid")?)?),
+            "--max-leg" => max_leg = Some(flag_value(&mut args, "--max-leg")?),
+            "--optional" => options.optional = parse_city_list(&flag_value(&mut args, "--optional")?)?,
+            "--skip-penalty" => skip_penalty = Some(flag_value(&mut args, "--skip-penalty")?),
+            "--unit" => options.unit = Some(Unit::parse(&flag_value(&mut args, "--unit")?)?),
+            "--convert" => options.convert = true,
+            "--png" => options.png = Some(flag_value(&mut args, "--png")?),
+            "--geojson" => options.geojson = Some(flag_value(&mut args, "--geojson")?),
+            "--validate-against" => options.validate_against = Some(flag_value(&mut args, "--validate-against")?),
+            "--reflow" => options.reflow = true,
+            "--dump-dp" => options.dump_dp = Some(flag_value(&mut args, "--dump-dp")?),
+            "--all-starts" => options.all_starts = true,
+            "--interactive-matrix" => options.interactive_matrix = true,
+            "--b64" => options.b64 = Some(flag_value(&mut args, "--b64")?),
+            "--load-binary" => options.load_binary = Some(flag_value(&mut args, "--load-binary")?),
+            "--emit-binary" => options.emit_binary = Some(flag_value(&mut args, "--emit-binary")?),
+            "--emit-matrix" => options.emit_matrix = Some(flag_value(&mut args, "--emit-matrix")?),
+            "--leg-profile" => options.leg_profile = parse_leg_profile(&flag_value(&mut args, "--leg-profile")?)?,
+            "--cache-matrix" | "--load-matrix" => {
+                if options.matrix_cache.is_some() {
+                    return Err("Give only one of --cache-matrix and --load-matrix".to_string());
+                }
+                let path = flag_value(&mut args, &arg)?;
+                options.matrix_cache = Some(if arg == "--cache-matrix" {
+                    MatrixCache::Save(path)
+                } else {
+                    MatrixCache::Load(path)
+                });
+            }
+            "--markdown" => options.markdown = Some(flag_value(&mut args, "--markdown")?),
+            "--stats-json" => options.stats_json = Some(flag_value(&mut args, "--stats-json")?),
+            "--permutation-export" => {
+                options.permutation_export = Some(flag_value(&mut args, "--permutation-export")?);
+            }
+            "--append-return" => options.append_return = true,
+            "--legs-csv" => options.legs_csv = Some(flag_value(&mut args, "--legs-csv")?),
+            "--prefer-uniform" => options.prefer_uniform = true,
+            "--cycle-notation" => options.cycle_notation = true,
+            "--tui" => options.tui = true,
+            "--route-limit" => {
+                options.route_limit = Some(
+                    flag_value(&mut args, "--route-limit")?
+                        .parse()
+                        .map_err(|_| "--route-limit expects a number of legs")?,
+                );
+            }
+            "--k-best" => {
+                options.k_best = Some(
+                    flag_value(&mut args, "--k-best")?
+                        .parse()
+                        .map_err(|_| "--k-best expects a number of tours")?,
+                );
+            }
+            "--resume" => options.resume = Some(flag_value(&mut args, "--resume")?),
+            "--at-position" => {
+                options.at_position = Some(parse_fixed_position(&flag_value(&mut args, "--at-position")?)?);
+            }
+            "--precedence" => options.precedence = Some(flag_value(&mut args, "--precedence")?),
+            "--groups" => options.groups = Some(flag_value(&mut args, "--groups")?),
+            "--service-times" => options.service_times = Some(flag_value(&mut args, "--service-times")?),
+            "--topology" => options.topology = Some(flag_value(&mut args, "--topology")?),
+            "--weights" => options.weights = Some(flag_value(&mut args, "--weights")?),
+            "--layer-report" => options.layer_report = true,
+            "--timing-histogram" => options.timing_histogram = true,
+            "--show-ties" => options.show_ties = true,
+            "--no-sep" => options.separators = false,
+            "--no-depot-highlight" => options.depot_highlight = false,
+            "--cost-only" => options.cost_only = true,
+            "--trace-states" => options.trace_states = true,
+            "--strict-format" => options.strict_format = true,
+            "--self-check" => options.self_check = true,
+            "--delimiter" => options.delimiter = Some(parse_delimiter(&flag_value(&mut args, "--delimiter")?)?),
+            "--has-header" => options.has_header = true,
+            "--has-index-col" => options.has_index_col = true,
+            "--format" => options.input_format = Some(InputFormat::parse(&flag_value(&mut args, "--format")?)?),
+            "--safe" => {
+                options.safe.get_or_insert_with(SafeLimits::default);
+            }
+            "--safe-max-cities" => {
+                options.safe.get_or_insert_with(SafeLimits::default).max_cities =
+                    flag_value(&mut args, "--safe-max-cities")?
+                        .parse()
+                        .map_err(|_| "--safe-max-cities expects a number of cities")?;
+            }
+            "--safe-max-weight" => {
+                options.safe.get_or_insert_with(SafeLimits::default).max_weight =
+                    flag_value(&mut args, "--safe-max-weight")?
+                        .parse()
+                        .map_err(|_| "--safe-max-weight expects a weight")?;
+            }
+            "--safe-max-file-size" => {
+                options.safe.get_or_insert_with(SafeLimits::default).max_file_bytes =
+                    flag_value(&mut args, "--safe-max-file-size")?
+                        .parse()
+                        .map_err(|_| "--safe-max-file-size expects a size in bytes")?;
+            }
+            "--repeat" => {
+                options.repeat = flag_value(&mut args, "--repeat")?
+                    .parse()
+                    .ok()
+                    .filter(|&runs| runs > 0)
+                    .ok_or("--repeat expects a positive number of runs")?;
+            }
+            flag if flag.starts_with("--") => {
+                return Err(format!("Unknown option '{}'", flag));
+            }
+            _ => {
+                if options.file_path.is_some() {
+                    return Err(format!("Unexpected extra argument '{}'", arg));
+                }
+                options.file_path = Some(arg);
+            }
+        }
+    }
+
+    options.color = color.unwrap_or(!options.headless);
+    // The dashboard takes over the terminal, the progress bar included
+    options.progress = progress.unwrap_or(!options.headless) && !options.tui;
+    options.json = json.unwrap_or(options.headless);
+    if options.headless {
+        options.quiet = true;
+        if !options.has_instance_source() && options.dir.is_none() {
+            options.file_path = Some("-".to_string());
+        }
+    }
+    if options.json {
+        // Everything else on stdout would break the JSON
+        options.quiet = true;
+        let conflicts = [
+            ("--check", options.check),
+            ("--matrix-stats", options.matrix_stats),
+            ("--evaluate", options.evaluate.is_some()),
+            ("--start-tour", options.start_tour.is_some()),
+            ("--stream", options.stream),
+            ("--dir", options.dir.is_some()),
+            ("--interactive-matrix", options.interactive_matrix),
+            ("--cost-only", options.cost_only),
+            ("--emit-matrix", options.emit_matrix.is_some()),
+            ("--emit-binary", options.emit_binary.is_some()),
+            ("--markdown", options.markdown.is_some()),
+            ("--png", options.png.is_some()),
+            ("--geojson", options.geojson.is_some()),
+            ("--permutation-export", options.permutation_export.is_some()),
+            ("--legs-csv", options.legs_csv.is_some()),
+            ("--cycle-notation", options.cycle_notation),
+            ("--state-graph", options.state_graph.is_some()),
+            ("--grade-log", options.grade_log.is_some()),
+            ("--dump-dp", options.dump_dp.is_some()),
+            ("--trace-states", options.trace_states),
+            ("--show-ties", options.show_ties),
+            ("--all-starts", options.all_starts),
+            ("--k-best", options.k_best.is_some()),
+            ("--at-position", options.at_position.is_some()),
+            ("--mod", options.modulus.is_some()),
+            ("--count-tours", options.count_tours),
+            ("--assignment-bound", options.assignment_bound),
+            ("--savings", options.savings),
+            ("--bearings", options.bearings),
+            ("--layer-report", options.layer_report),
+            ("--timing-histogram", options.timing_histogram),
+            ("--repeat", options.repeat > 1),
+            ("--validate-against", options.validate_against.is_some()),
+        ];
+        if let Some((flag, _)) = conflicts.iter().find(|(_, given)| *given) {
+            return Err(format!("--json only prints the result record; it cannot be combined with {}", flag));
+        }
+    }
+
+    if let Some(unit) = options.unit {
+        if options.weight_format == WeightFormat::Duration {
+            return Err("--unit cannot be combined with --time-format".to_string());
+        }
+        options.weight_format = WeightFormat::Labeled(unit);
+    }
+    if options.convert && !matches!(options.unit, Some(Unit::Km | Unit::Mi)) {
+        return Err("--convert needs --unit km (input in miles) or --unit mi (input in kilometers)".to_string());
+    }
+
+    if let Some(value) = max_leg {
+        options.max_leg = Some(
+            parse_weight(&value, options.weight_format)
+                .filter(|&max| max < INF)
+                .ok_or_else(|| format!("--max-leg expects a distance, got '{}'", value))?,
+        );
+    }
+    if let Some(value) = skip_penalty {
+        options.skip_penalty = Some(
+            parse_weight(&value, options.weight_format)
+                .filter(|&penalty| (0..INF).contains(&penalty))
+                .ok_or_else(|| format!("--skip-penalty expects a non-negative distance, got '{}'", value))?,
+        );
+    }
+    if options.optional.is_empty() != options.skip_penalty.is_none() {
+        return Err("--optional and --skip-penalty must be given together".to_string());
+    }
+    if !options.optional.is_empty() {
+        let conflicts = [
+            ("--maximize", options.maximize),
+            ("--evaluate", options.evaluate.is_some()),
+            ("--start-tour", options.start_tour.is_some()),
+            ("--stream", options.stream),
+            ("--dir", options.dir.is_some()),
+            ("--k-best", options.k_best.is_some()),
+            ("--prefer-uniform", options.prefer_uniform),
+            ("--at-position", options.at_position.is_some()),
+            ("--count-tours", options.count_tours),
+            ("--state-graph", options.state_graph.is_some()),
+            ("--grade-log", options.grade_log.is_some()),
+            ("--assignment-bound", options.assignment_bound),
+            ("--show-ties", options.show_ties),
+            ("--all-starts", options.all_starts),
+        ];
+        if let Some((flag, _)) = conflicts.iter().find(|(_, given)| *given) {
+            return Err(format!("--optional cannot be combined with {}", flag));
+        }
+    }
+
+    if !options.leg_profile.is_empty() {
+        // These assume a tour costs the same whichever way and wherever it starts
+        let conflicts = [
+            ("--evaluate", options.evaluate.is_some()),
+            ("--start-tour", options.start_tour.is_some()),
+            ("--stream", options.stream),
+            ("--dir", options.dir.is_some()),
+            ("--directed", options.directed),
+            ("--savings", options.savings),
+            ("--assignment-bound", options.assignment_bound),
+            ("--all-starts", options.all_starts),
+        ];
+        if let Some((flag, _)) = conflicts.iter().find(|(_, given)| *given) {
+            return Err(format!("--leg-profile cannot be combined with {}", flag));
+        }
+    }
+
+    match (&options.topology, &options.weights) {
+        (Some(_), None) | (None, Some(_)) => {
+            return Err("--topology and --weights must be given together".to_string());
+        }
+        (Some(_), Some(_)) if options.file_path.is_some() => {
+            return Err("Give either a matrix file or --topology/--weights, not both".to_string());
+        }
+        _ => {}
+    }
+
+    if options.dir.is_some() && (options.file_path.is_some() || options.topology.is_some()) {
+        return Err("--dir solves a whole directory; don't also give an instance file".to_string());
+    }
+
+    if options.groups.is_some() {
+        let conflicts = [
+            ("--evaluate", options.evaluate.is_some()),
+            ("--start-tour", options.start_tour.is_some()),
+            ("--precedence", options.precedence.is_some()),
+            ("--optional", !options.optional.is_empty()),
+            ("--skip", options.skip.is_some()),
+            ("--leg-profile", !options.leg_profile.is_empty()),
+            ("--at-position", options.at_position.is_some()),
+            ("--cost-only", options.cost_only),
+            ("--k-best", options.k_best.is_some()),
+            ("--prefer-uniform", options.prefer_uniform),
+            ("--cycle-notation", options.cycle_notation),
+            ("--stream", options.stream),
+            ("--dir", options.dir.is_some()),
+            ("--json", options.json),
+            ("--resume", options.resume.is_some()),
+            ("--checkpoint", options.checkpoint.is_some()),
+            ("--grade-log", options.grade_log.is_some()),
+        ];
+        if let Some((flag, _)) = conflicts.iter().find(|(_, given)| *given) {
+            return Err(format!("--groups cannot be combined with {}", flag));
+        }
+    }
+
+    if options.b64.is_some() && (options.file_path.is_some() || options.topology.is_some() || options.dir.is_some()) {
+        return Err("--b64 gives the instance inline; don't also give an instance file, --topology or --dir".to_string());
+    }
+
+    if options.load_binary.is_some()
+        && (options.file_path.is_some() || options.topology.is_some() || options.b64.is_some() || options.dir.is_some())
+    {
+        return Err("--load-binary reads the instance from a binary file; don't also give an instance file, --topology, --b64 or --dir".to_string());
+    }
+
+    if options.service_times.is_some() && (options.dir.is_some() || options.stream) {
+        return Err("--service-times holds one time per city of a single instance; not with --dir or --stream".to_string());
+    }
+
+    if (options.emit_matrix.is_some() || options.emit_binary.is_some()) && (options.dir.is_some() || options.stream) {
+        return Err("--emit-matrix and --emit-binary write a single instance's matrix; not with --dir or --stream".to_string());
+    }
+
+    if options.interactive_matrix && (options.has_instance_source() || options.dir.is_some()) {
+        return Err("--interactive-matrix types the matrix in; don't also give an instance file or --dir".to_string());
+    }
+
+    if options.assignment_bound && options.maximize {
+        return Err("--assignment-bound only bounds shortest tours; it can't be combined with --maximize".to_string());
+    }
+
+    if options.stream && options.convert {
+        return Err("--convert works on distance matrices and can't be combined with --stream".to_string());
+    }
+
+    if options.matrix_cache.is_some() && (options.dir.is_some() || options.stream) {
+        return Err("--cache-matrix and --load-matrix work on a single instance; not with --dir or --stream".to_string());
+    }
+
+    if options.stream && options.file_path.is_none() {
+        return Err("--stream needs a coordinate file: tsp_solver <file> --stream".to_string());
+    }
+
+    if options.nn_random_ties.is_some() && !matches!(options.start_tour, Some(StartTour::NearestNeighbor)) {
+        return Err("--nn-random-ties only applies to --start-tour nn".to_string());
+    }
+    if options.tui {
+        // Anything else drawing on the terminal mid-solve would tear the dashboard
+        let conflicts = [
+            ("--json", options.json),
+            ("--headless", options.headless),
+            ("--trace-states", options.trace_states),
+            ("--checkpoint", options.checkpoint.is_some()),
+            ("--dir", options.dir.is_some()),
+            ("--stream", options.stream),
+            ("--interactive-matrix", options.interactive_matrix),
+        ];
+        if let Some((flag, _)) = conflicts.iter().find(|(_, given)| *given) {
+            return Err(format!("--tui cannot be combined with {}", flag));
+        }
+    }
+
+    if options.restarts > 1 && (options.start_tour.is_none() || options.stream) {
+        return Err("--restarts needs --start-tour on a distance matrix (not --stream)".to_string());
+    }
+    if options.require_metric && (options.start_tour.is_none() || options.stream) {
+        return Err("--require-metric needs --start-tour on a distance matrix (not --stream)".to_string());
+    }
+
+    if options.cost_only {
+        let needs_path = [
+            ("--stats-json", options.stats_json.is_some()),
+            ("--markdown", options.markdown.is_some()),
+            ("--png", options.png.is_some()),
+            ("--geojson", options.geojson.is_some()),
+            ("--show-ties", options.show_ties),
+            ("--all-starts", options.all_starts),
+            ("--permutation-export", options.permutation_export.is_some()),
+            ("--legs-csv", options.legs_csv.is_some()),
+            ("--cycle-notation", options.cycle_notation),
+            ("--k-best", options.k_best.is_some()),
+            ("--prefer-uniform", options.prefer_uniform),
+            ("--at-position", options.at_position.is_some()),
+            ("--repeat", options.repeat > 1),
+            ("--grade-log", options.grade_log.is_some()),
+        ];
+        if let Some((flag, _)) = needs_path.iter().find(|(_, given)| *given) {
+            return Err(format!("--cost-only cannot be combined with {}", flag));
+        }
+    }
+
+    Ok(options)
+}
+
+/// Creates a solver for `distances` configured from the command line.
+fn build_solver(
+    distances: Vec<Vec<Weight>>,
+    options: &CliOptions,
+    precedence: &[(usize, usize)],
+    optional: &[usize],
+    logger: Option<RunLogger>,
+) -> Result<TSPSolver, String> {
+    let mut solver = TSPSolver::new(distances)?;
+    solver.set_maximize(options.maximize);
+    solver.set_precedence(precedence);
+    if let Some(penalty) = options.skip_penalty {
+        solver.set_optional(optional, penalty);
+    }
+    solver.set_leg_profile(&options.leg_profile);
+    if let Some(backend) = options.memo {
+        solver.set_memo_backend(backend);
+    }
+    if options.progress {
+        solver.set_progress_bar(create_progress_bar());
+    }
+    solver.set_announce(!options.json && !options.tui);
+    if let Some(logger) = logger {
+        solver.set_logger(logger);
+    }
+    solver.set_trace(options.trace_states);
+    solver.set_force_dp(
+        options.state_graph.is_some()
+            || options.grade_log.is_some()
+            || options.timing_histogram
+            || options.show_ties
+            || options.dump_dp.is_some(),
+    );
+    solver.set_layer_timing(options.timing_histogram);
+    Ok(solver)
+}
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let options = parse_args(std::env::args().skip(1))?;
+    if !options.color {
+        colored::control::set_override(false);
+    }
+
+    if !options.quiet {
+        print_banner();
+        print_instructions();
+    }
+
+    if options.selftest {
+        return run_selftest(options.seed.unwrap_or(1));
+    }
+    if let Some(dir) = &options.dir {
+        return run_directory(dir, &options);
+    }
+    if let (true, Some(path)) = (options.stream, &options.file_path) {
+        return run_streamed(path, &options);
+    }
+
+    let interactive = !options.has_instance_source();
+    let mut entered = None;
+    let file_path = if let (Some(topology), Some(weights)) = (&options.topology, &options.weights) {
+        if !options.quiet {
+            println!(
+                "📂 Reading topology {} with weights {}",
+                topology.bright_blue(),
+                weights.bright_blue()
+            );
+        }
+        None
+    } else if let Some(file_path) = &options.file_path {
+        // Command line argument provided
+        if !options.quiet {
+            println!("📂 Reading from file: {}", file_path.bright_blue());
+        }
+        Some(file_path.clone())
+    } else if options.b64.is_some() {
+        if !options.quiet {
+            println!("📂 Reading the instance from --b64");
+        }
+        None
+    } else if let Some(path) = &options.load_binary {
+        if !options.quiet {
+            println!("📂 Reading binary matrix: {}", path.bright_blue());
+        }
+        None
+    } else {
+        // Interactive mode, which needs a terminal for the prompts
+        if !std::io::stdin().is_terminal() || !std::io::stderr().is_terminal() {
+            return Err(
+                "No terminal for interactive input; pass the matrix file as an argument: tsp_solver <file> [options]"
+                    .into(),
+            );
+        }
+        if !options.interactive_matrix && get_input_method()? {
+            let file_path = get_file_path()?;
+            println!("📂 Reading matrix file...");
+            Some(file_path)
+        } else {
+            entered = Some(enter_matrix_grid(&options.parse_options(), options.depot_highlight)?);
+            None
+        }
+    };
+
+    if let Some(limits) = &options.safe {
+        let files = [&file_path, &options.topology, &options.weights, &options.load_binary, &options.service_times];
+        for path in files.into_iter().flatten().filter(|path| *path != "-") {
+            limits.check_file(path)?;
+        }
+    }
+
+    let load_start = Instant::now();
+    let (mut distances, format_name, coords, metric, names) = match (&file_path, &options.topology, &options.weights) {
+        (Some(file_path), _, _) => {
+            let instance = options.instance_loader().load_file(file_path)?;
+            (instance.distances, instance.format.name(), instance.coords, instance.metric, instance.names)
+        }
+        (None, Some(topology), Some(weights)) => (
+            load_split_files(topology, weights, &options.parse_options())?,
+            "topology + weights",
+            None,
+            Metric::Euclidean,
+            None,
+        ),
+        _ => match (&options.b64, &options.load_binary) {
+            (Some(text), _) => {
+                let instance = options.instance_loader().load_base64(text)?;
+                (instance.distances, instance.format.name(), instance.coords, instance.metric, instance.names)
+            }
+            (None, Some(path)) => (read_binary_matrix(path)?, "binary", None, Metric::Euclidean, None),
+            (None, None) => {
+                let Some(distances) = entered else {
+                    unreachable!("without a matrix file, --topology, --b64 or --load-binary, the matrix was entered by hand");
+                };
+                (distances, "grid entry", None, Metric::Euclidean, None)
+            }
+        },
+    };
+    let load_time = load_start.elapsed();
+    if distances.len() > 1 && !has_finite_edge(&distances) {
+        return Err(NO_FINITE_EDGES.into());
+    }
+    if let (true, Some(unit)) = (options.convert, options.unit) {
+        convert_distance_units(&mut distances, unit);
+    }
+    let service_total = match &options.service_times {
+        Some(path) => {
+            let service = load_service_times(path, distances.len(), options.weight_format)?;
+            Some(add_service_times(&mut distances, &service)?)
+        }
+        None => None,
+    };
+    forbid_edges(&mut distances, &options.forbid, options.directed)?;
+    let capped = options.max_leg.map(|max| (max, cap_leg_length(&mut distances, max)));
+
+    let n = distances.len();
+
+    if !options.quiet {
+        println!("✅ Matrix loaded successfully! ({})\n", format_name);
+        print_matrix(&distances, options.depot_highlight);
+        if let Some(names) = &names {
+            print_city_names(names);
+        }
+        if let Some(total) = service_total {
+            println!(
+                "🧾 Service times: {} in total, included above in every leg into a city\n",
+                options.weight_format.render(total)
+            );
+        }
+        if let Some((max, removed)) = capped {
+            println!(
+                "🚧 Max leg {}: {} edge(s) longer than that are forbidden\n",
+                options.weight_format.render(max),
+                removed
+            );
+        }
+    }
+    if interactive && n > 1 {
+        edit_matrix(&mut distances, options.weight_format, options.directed, options.depot_highlight)?;
+    }
+    if let Some(limits) = &options.safe {
+        limits.check_matrix(&distances)?;
+    }
+    check_weight_range(&distances)?;
+    if let Some(&worst) = options.leg_profile.iter().max() {
+        let scaled: Vec<Vec<Weight>> = distances
+            .iter()
+            .map(|row| row.iter().map(|&d| if d < INF { d.saturating_mul(worst).min(INF - 1) } else { d }).collect())
+            .collect();
+        check_weight_range(&scaled).map_err(|e| format!("With --leg-profile multipliers up to {}: {}", worst, e))?;
+    }
+    if let Some(path) = &options.emit_matrix {
+        let matrix = render_matrix_file(&distances, options.weight_format, names.as_deref());
+        if path == "-" {
+            print!("{}", matrix);
+            println!();
+        } else {
+            fs::write(path, matrix).map_err(|e| format!("Error writing matrix file '{}': {}", path, e))?;
+            println!("💾 Matrix written to {}\n", path.bright_blue());
+        }
+    }
+
+    if let Some(path) = &options.emit_binary {
+        let bytes = encode_binary_matrix(&distances)?;
+        fs::write(path, bytes).map_err(|e| format!("Error writing binary matrix '{}': {}", path, e))?;
+        println!("💾 Binary matrix written to {}\n", path.bright_blue());
+    }
+    if !options.quiet && n > 1 {
+        print_degree_summary(&DegreeSummary::new(&distances));
+        let negative = count_negative_edges(&distances);
+        if negative > 0 {
+            println!(
+                "{}",
+                format!(
+                    "ℹ️  {} negative weights (profits): the optimum is still exact, and INF edges are never used\n",
+                    negative
+                )
+                .dimmed()
+            );
+        }
+    }
+    if options.matrix_stats {
+        print_matrix_stats(&MatrixStats::new(&distances), options.weight_format);
+    }
+
+    let closure = if options.shortest_path_complete {
+        let closure = ShortestPaths::new(&distances)?;
+        distances = closure.dist.clone();
+        if !options.quiet {
+            println!(
+                "{}",
+                "🧭 Solving on the shortest-path closure: a leg may stand for a multi-hop path.\n"
+                    .bright_cyan()
+            );
+        }
+        Some(closure)
+    } else {
+        None
+    };
+    let mut warnings = instance_warnings(&distances, options.directed, options.weight_format);
+
+    if options.check {
+        println!("✅ Instance is valid ({} cities)", n);
+        print_warnings(&warnings);
+        return Ok(());
+    }
+
+    // Solve on the reduced matrix but keep the full one for reporting
+    let subset = match &options.skip {
+        Some(skip) => {
+            let (reduced, subset) = CitySubset::reduce(&distances, skip)?;
+            if !options.json {
+                println!(
+                    "⏭️  Skipping {} (solving over {} remaining cities)\n",
+                    format_city_list(skip).bright_blue(),
+                    subset.kept.len()
+                );
+            }
+            Some((std::mem::replace(&mut distances, reduced), subset))
+        }
+        None => None,
+    };
+    let n = distances.len();
+
+    if options.trace_states && n > TRACE_MAX_CITIES {
+        return Err(format!(
+            "--trace-states is limited to {} cities ({} given), the trace would be too long to read",
+            TRACE_MAX_CITIES, n
+        )
+        .into());
+    }
+    if options.grade_log.is_some() && n > GRADE_LOG_MAX_CITIES {
+        return Err(format!(
+            "--grade-log is limited to {} cities ({} given), the log would be too long to diff",
+            GRADE_LOG_MAX_CITIES, n
+        )
+        .into());
+    }
+    if options.state_graph.is_some() && n > STATE_GRAPH_MAX_CITIES {
+        return Err(format!(
+            "--state-graph is limited to {} cities ({} given), the graph would be too large to draw",
+            STATE_GRAPH_MAX_CITIES, n
+        )
+        .into());
+    }
+
+    let original_n = subset.as_ref().map_or(n, |(full, _)| full.len());
+    let mut precedence = match &options.precedence {
+        Some(path) => load_precedence(path, original_n)?,
+        None => Vec::new(),
+    };
+    if !precedence.is_empty() && has_precedence_cycle(&precedence, original_n) {
+        return Err("Precedence constraints form a cycle, so no tour can satisfy them".into());
+    }
+    if let Some((_, subset)) = &subset {
+        precedence = precedence
+            .iter()
+            .map(|&(before, after)| Ok((subset.to_reduced(&[before])?[0], subset.to_reduced(&[after])?[0])))
+            .collect::<Result<_, String>>()
+            .map_err(|e| format!("Invalid precedence: {}", e))?;
+    }
+
+    let expected = match &options.validate_against {
+        Some(path) => Some(load_expected(path, options.weight_format)?),
+        None => None,
+    };
+
+    if let Some(&city) = options.optional.iter().find(|&&city| city == 0 || city >= original_n) {
+        return Err(format!("Invalid optional city {}: choose from City1 to City{}", city, original_n - 1).into());
+    }
+    let optional = match &subset {
+        Some((_, subset)) => subset.to_reduced(&options.optional).map_err(|e| format!("Invalid optional city: {}", e))?,
+        None => options.optional.clone(),
+    };
+    if let Some(penalty) = options.skip_penalty {
+        let limit = (INF - 1) / original_n as Weight;
+        if penalty > limit {
+            return Err(format!("--skip-penalty {} is out of range: with {} cities it must stay within {}", penalty, original_n, limit).into());
+        }
+    }
+
+    let mut logger = match &options.log_path {
+        Some(path) => Some(
+            RunLogger::create(path).map_err(|e| format!("Error creating log file '{}': {}", path, e))?,
+        ),
+        None => None,
+    };
+    if let Some(ref mut logger) = logger {
+        logger.log(&format!("Instance loaded ({} cities)", n));
+    }
+
+    if let Some(path) = &options.groups {
+        let groups = load_groups(path, n)?;
+        let start_time = Instant::now();
+        let (cost, path) = solve_groups(&distances, &groups, options.maximize)?;
+        if !options.quiet {
+            println!("⏱️  Solved over {} groups in {:.3?}\n", groups.len(), start_time.elapsed());
+        }
+        print_group_tour(cost, &path, &groups, options.weight_format, options.separators, options.depot_highlight);
+        print_detailed_route(
+            cost,
+            &path,
+            &[],
+            &distances,
+            options.weight_format,
+            options.separators,
+            options.route_limit,
+            options.depot_highlight,
+        );
+        print_warnings(&warnings);
+        print_farewell();
+        return Ok(());
+    }
+
+    if let Some(tour) = &options.evaluate {
+        let tour = match &subset {
+            Some((_, subset)) => subset.to_reduced(tour).map_err(|e| format!("Invalid tour: {}", e))?,
+            None => tour.clone(),
+        };
+        let path = validate_tour(&tour, n).map_err(|e| format!("Invalid tour: {}", e))?;
+        let stop = |city: usize| path.iter().position(|&c| c == city);
+        if let Some(&(before, after)) = precedence.iter().find(|&&(before, after)| stop(after) < stop(before)) {
+            let (before, after) = match &subset {
+                Some((_, subset)) => (subset.kept[before], subset.kept[after]),
+                None => (before, after),
+            };
+            return Err(format!("Invalid tour: City{} must come before City{}", before, after).into());
+        }
+        let cost = tour_cost(&distances, &path).unwrap_or(INF);
+
+        let optimum = if n <= EVALUATE_EXACT_LIMIT {
+            let mut solver = build_solver(distances.clone(), &options, &precedence, &[], logger.take())?;
+            Some(solver.solve())
+        } else {
+            None
+        };
+
+        let original_path = match &subset {
+            Some((_, subset)) => subset.to_original(&path),
+            None => path.clone(),
+        };
+        print_evaluation(
+            cost,
+            &original_path,
+            optimum.as_ref().map(|(best, _)| *best),
+            options.weight_format,
+            options.depot_highlight,
+        );
+        if let Some((best, best_path)) = optimum.filter(|(best, _)| !is_infeasible(*best)) {
+            let subset = subset.as_ref().map(|(_, subset)| subset);
+            print_tour_diff("your tour", (cost, &path), (best, &best_path), &distances, subset, options.weight_format);
+        }
+        print_warnings(&warnings);
+        return Ok(());
+    }
+
+    if let Some(tour) = &options.start_tour {
+        if !precedence.is_empty() {
+            return Err("--start-tour cannot be combined with --precedence".into());
+        }
+        // 2-opt's and nearest neighbor's quality guarantees assume the triangle
+        // inequality; --require-metric checks past METRIC_CHECK_MAX_CITIES too
+        if options.require_metric || n <= METRIC_CHECK_MAX_CITIES {
+            if let Some((i, j, k)) = triangle_violation(&distances) {
+                let city = |c: usize| subset.as_ref().map_or(c, |(_, subset)| subset.kept[c]);
+                let shortcut = format!("City{} → City{} is longer than going via City{}", city(i), city(k), city(j));
+                if options.require_metric {
+                    return Err(format!("--require-metric: the matrix is not metric ({})", shortcut).into());
+                }
+                // Said for 2-opt, this replaces the general non-metric warning
+                warnings.retain(|warning| warning.kind != "non-metric");
+                warnings.push(Warning::new(
+                    "heuristic-non-metric",
+                    format!("Not metric ({}): the 2-opt tour carries no quality guarantee", shortcut),
+                ));
+            }
+        }
+        let tour = match (tour, &subset) {
+            (StartTour::NearestNeighbor, _) => options.nearest_neighbor(&distances, n),
+            (StartTour::Given(tour), Some((_, subset))) => {
+                subset.to_reduced(tour).map_err(|e| format!("Invalid start tour: {}", e))?
+            }
+            (StartTour::Given(tour), None) => tour.clone(),
+        };
+        let start_cost = tour_cost(&distances, &validate_tour(&tour, n).map_err(|e| format!("Invalid start tour: {}", e))?).unwrap_or(INF);
+
+        // 2-opt and the bounds need no bitmask, so this runs past MAX_BITMASK_CITIES
+        let start_time = Instant::now();
+        let (cost, path, agreeing) =
+            restart_two_opt(&distances, tour, options.restarts, options.maximize, options.seed.unwrap_or(1))
+                .map_err(|e| format!("Invalid start tour: {}", e))?;
+        let elapsed = start_time.elapsed();
+
+        let original_path = match &subset {
+            Some((_, subset)) => subset.to_original(&path),
+            None => path.clone(),
+        };
+        // The 1-tree bound only bounds shortest tours
+        let bound = (!options.maximize).then(|| held_karp_bound(&distances, options.bound_iterations));
+        print_heuristic_result(start_cost, cost, &original_path, elapsed, bound, options.weight_format, options.depot_highlight);
+        if options.restarts > 1 {
+            print_restart_agreement(agreeing, options.restarts);
+        }
+        if options.assignment_bound {
+            print_assignment_bound(assignment_lower_bound(&distances, n), cost, options.weight_format);
+        }
+        // Small instances are cheap to solve exactly, so show where 2-opt went wrong
+        if n <= EVALUATE_EXACT_LIMIT {
+            let mut exact = TSPSolver::new(distances.clone())?;
+            exact.set_maximize(options.maximize);
+            exact.set_announce(false);
+            let (best, best_path) = exact.solve();
+            if !is_infeasible(best) {
+                let subset = subset.as_ref().map(|(_, subset)| subset);
+                print_tour_diff("the 2-opt tour", (cost, &path), (best, &best_path), &distances, subset, options.weight_format);
+            }
+        }
+        print_warnings(&warnings);
+        print_farewell();
+        return Ok(());
+    }
+
+    check_bitmask_cities(n)?;
+    if n > 20 {
+        if !options.json {
+            println!(
+                "{}",
+                format!("⚠️  Large matrix detected ({} cities). This will take exponential time!", n)
+                    .yellow()
+                    .bold()
+            );
+        }
+        warnings.push(Warning::new(
+            "large-instance",
+            format!("{} cities: the DP takes exponential time and memory beyond 20", n),
+        ));
+        // Headless runs have nobody to ask, so they go ahead with the warning recorded
+        let continue_anyway = options.headless
+            || Confirm::with_theme(&ColorfulTheme::default())
+                .with_prompt("Continue anyway? (Not recommended for n > 20)")
+                .default(false)
+                .interact()?;
+
+        if !continue_anyway {
+            println!("🛑 Operation cancelled.");
+            return Ok(());
+        }
+    } else if n > 15 {
+        if !options.json {
+            println!(
+                "{}",
+                format!("⚠️  Medium-large matrix ({} cities). This may take some time.", n)
+                    .yellow()
+            );
+        }
+        warnings.push(Warning::new(
+            "large-instance",
+            format!("{} cities: the DP may take some time", n),
+        ));
+    }
+
+    if options.repeat > 1 && options.resume.is_some() {
+        return Err("--repeat cannot be combined with --resume".into());
+    }
+
+    // Extra timing runs, each on a fresh solver; the last run below is reported
+    let mut repeat_runs = Vec::new();
+    for _ in 1..options.repeat {
+        let start_time = Instant::now();
+        let result = build_solver(distances.clone(), &options, &precedence, &optional, None)?.solve();
+        repeat_runs.push((start_time.elapsed(), result));
+    }
+
+    let start_time = Instant::now();
+    
+    let mut solver = build_solver(distances, &options, &precedence, &optional, logger)?;
+    if let Some(path) = &options.resume {
+        solver.resume_from(path)?;
+        if !options.json {
+            println!("♻️  Resuming from checkpoint: {}", path.bright_blue());
+        }
+    }
+    if let Some(path) = &options.checkpoint {
+        solver.set_checkpoint(path.clone(), options.checkpoint_every);
+    }
+    
+    if options.tui {
+        attach_dashboard(&mut solver)?;
+    }
+
+    if options.cost_only {
+        let min_cost = solver.solve_cost();
+        solver.close_dashboard();
+        print_cost_only(min_cost, start_time.elapsed(), &solver, options.weight_format, options.separators);
+        if options.modulus.is_some() || options.count_tours {
+            let tours = options.count_tours.then(|| solver.count_optimal_tours(min_cost, options.modulus));
+            print_counting_report(min_cost, options.modulus, tours);
+        }
+        if options.layer_report {
+            print_layer_report(&solver.layer_sizes());
+        }
+        if let Some(layers) = solver.layer_timings() {
+            print_timing_histogram(&layers);
+        }
+        if let Some(path) = &options.dump_dp {
+            write_dp_dump(path, &solver)?;
+            println!("🗃️  DP table ({} states) written to {}", solver.dp.len(), path.bright_blue());
+        }
+        if let (Some(expected), Some(path)) = (&expected, &options.validate_against) {
+            if !check_expected(expected, min_cost, None, &solver.dist, options.weight_format) {
+                return Err(format!("Result doesn't match the expected answer in '{}'", path).into());
+            }
+        }
+        print_warnings(&warnings);
+        print_farewell();
+        return Ok(());
+    }
+
+    let (min_cost, optimal_path) = solver.solve();
+    solver.close_dashboard();
+    let elapsed = start_time.elapsed();
+    solver.check_reconstruction(min_cost, &optimal_path)?;
+
+    // One-way instances are always checked: a path that only costs the
+    // optimum when driven backwards would be a wrong answer, not a tie
+    if options.self_check || options.directed {
+        solver
+            .verify_path(min_cost, &optimal_path)
+            .map_err(|e| format!("Self-check failed: {}", e))?;
+        if options.self_check && !options.json {
+            println!("{}", "✅ Self-check passed: the path's cost matches the DP optimum".green());
+        }
+    }
+
+    for (run, (_, (cost, path))) in repeat_runs.iter().enumerate() {
+        if *cost != min_cost || *path != optimal_path {
+            return Err(format!(
+                "Run {} found cost {} but the final run found {}",
+                run + 1,
+                cost,
+                min_cost
+            )
+            .into());
+        }
+    }
+
+    let optimal_path = match options.prefer_uniform.then(|| solver.most_uniform_optimal(min_cost)).flatten() {
+        Some((path, distinct, compared)) => {
+            if !options.json {
+                println!(
+                    "🎚️  Most uniform of {} optimal tour(s): {} distinct leg weight(s)",
+                    compared,
+                    distinct.to_string().bright_yellow()
+                );
+            }
+            path
+        }
+        None => optimal_path,
+    };
+
+    let ties = (options.show_ties && !is_infeasible(min_cost)).then(|| {
+        let ties = solver.tie_points(&optimal_path);
+        match &subset {
+            Some((_, subset)) => ties.into_iter().map(|(stop, cities)| (stop, subset.to_original(&cities))).collect(),
+            None => ties,
+        }
+    });
+
+    let runner_ups = if is_infeasible(min_cost) { Vec::new() } else { solver.runner_ups(&optimal_path) };
+    let runner_ups: Vec<Option<(usize, Weight)>> = match &subset {
+        Some((_, subset)) => runner_ups.into_iter().map(|alt| alt.map(|(city, delta)| (subset.kept[city], delta))).collect(),
+        None => runner_ups,
+    };
+
+    // Report in original city indices against the full matrix
+    let (full_dist, optimal_path) = match &subset {
+        Some((full, subset)) => (full.as_slice(), subset.to_original(&optimal_path)),
+        None => (solver.dist.as_slice(), optimal_path),
+    };
+    if is_infeasible(min_cost) && !options.forbid.is_empty() {
+        warnings.push(Warning::new(
+            "forbid-infeasible",
+            format!(
+                "{} edge(s) were forbidden with --forbid; they may have removed every valid tour",
+                options.forbid.len()
+            ),
+        ));
+    }
+    if let (true, Some((max, removed))) = (is_infeasible(min_cost), capped) {
+        warnings.push(Warning::new(
+            "max-leg-infeasible",
+            format!(
+                "--max-leg {} forbade {} edge(s); no tour keeps every leg within it",
+                options.weight_format.render(max),
+                removed
+            ),
+        ));
+    }
+
+    if options.json {
+        let stats = RunStats::collect(&solver, min_cost, &optimal_path, load_time, load_time + elapsed, &warnings);
+        println!("{}", stats.to_json()?);
+        if let Some(path) = &options.stats_json {
+            stats.write(path)?;
+        }
+        return Ok(());
+    }
+
+    print_solution(
+        min_cost,
+        &optimal_path,
+        elapsed,
+        &solver,
+        full_dist,
+        options.weight_format,
+        options.separators,
+        options.depot_highlight,
+    );
+    print_detailed_route(
+        min_cost,
+        &optimal_path,
+        &runner_ups,
+        full_dist,
+        options.weight_format,
+        options.separators,
+        options.route_limit,
+        options.depot_highlight,
+    );
+    print_recurrence_footer(&solver, options.weight_format);
+    if let (Some(names), false) = (&names, is_infeasible(min_cost)) {
+        println!("🏷️  Named tour: {}\n", format_named_path(&optimal_path, names).bright_white());
+    }
+    if options.cycle_notation && !is_infeasible(min_cost) {
+        println!("🔄 Cycle: {}\n", format_cycle_notation(full_dist, &optimal_path, options.weight_format).bright_white());
+    }
+    if let Some(ties) = &ties {
+        print_ties(&optimal_path, ties);
+    }
+    // Counting follows the DP's moves, which don't include --optional's early returns
+    if !is_infeasible(min_cost) && options.skip_penalty.is_none() {
+        print_uniqueness(solver.distinct_optimal_tours(min_cost), solver.reverse_is_same_tour());
+    }
+    if !options.leg_profile.is_empty() && !is_infeasible(min_cost) {
+        print_leg_profile(&optimal_path, full_dist, &options.leg_profile, options.weight_format);
+    }
+    if let Some(penalty) = options.skip_penalty {
+        print_optional_stops(min_cost, &optimal_path, &options.optional, penalty, options.weight_format);
+    }
+    if options.modulus.is_some() || options.count_tours {
+        let tours = options.count_tours.then(|| solver.count_optimal_tours(min_cost, options.modulus));
+        print_counting_report(min_cost, options.modulus, tours);
+    }
+    if options.assignment_bound {
+        print_assignment_bound(assignment_lower_bound(&solver.dist, solver.n), min_cost, options.weight_format);
+    }
+
+    if options.savings && !is_infeasible(min_cost) && optimal_path.len() <= 12 {
+        print_savings(full_dist, &optimal_path, options.weight_format);
+    }
+
+    if options.bearings && !is_infeasible(min_cost) {
+        match &coords {
+            Some(coords) => print_bearings(coords, full_dist, &optimal_path, options.weight_format),
+            None => println!(
+                "{}",
+                "ℹ️  --bearings needs city coordinates (TSPLIB or coordinate input); skipped".dimmed()
+            ),
+        }
+    }
+
+    if options.all_starts && !is_infeasible(min_cost) {
+        if options.precedence.is_some() || options.at_position.is_some() {
+            println!(
+                "{}",
+                "ℹ️  --all-starts skipped: --precedence and --at-position are defined relative to City0\n".dimmed()
+            );
+        } else {
+            print_all_starts(&optimal_path, full_dist, options.maximize, options.weight_format);
+        }
+    }
+
+    if options.directed && !is_infeasible(min_cost) {
+        print_direction_report(full_dist, &optimal_path, options.weight_format);
+    }
+
+    if let Some(closure) = &closure {
+        if !is_infeasible(min_cost) {
+            print_expanded_legs(closure, &optimal_path, options.weight_format);
+        }
+    }
+
+    if let Some(k) = options.k_best {
+        let mut tours = solver.solve_k_best(k);
+        if let Some((_, subset)) = &subset {
+            for (_, path) in &mut tours {
+                *path = subset.to_original(path);
+            }
+        }
+        print_k_best(&tours, k, options.weight_format);
+    }
+
+    if options.layer_report {
+        print_layer_report(&solver.layer_sizes());
+    }
+    if let Some(layers) = solver.layer_timings() {
+        print_timing_histogram(&layers);
+    }
+    if let Some(path) = &options.dump_dp {
+        write_dp_dump(path, &solver)?;
+        println!("🗃️  DP table ({} states) written to {}", solver.dp.len(), path.bright_blue());
+    }
+
+    if let Some(path) = &options.state_graph {
+        let nodes = solver.state_graph();
+        write_state_graph(path, &nodes, n, options.weight_format)?;
+        println!("🕸️  State graph ({} reachable states) written to {}", nodes.len(), path.bright_blue());
+    }
+
+    if let Some(path) = &options.grade_log {
+        fs::write(path, render_grade_log(&solver, min_cost))
+            .map_err(|e| format!("Error writing grade log '{}': {}", path, e))?;
+        println!("📝 Grade log written to {}", path.bright_blue());
+    }
+
+    if let Some((city, stop)) = options.at_position {
+        let reduced_city = match &subset {
+            Some((_, subset)) => subset.to_reduced(&[city])?[0],
+            None => city,
+        };
+        let (cost, path) = solver.solve_at_position(reduced_city, stop)?;
+        let path = match &subset {
+            Some((_, subset)) => subset.to_original(&path),
+            None => path,
+        };
+        print_fixed_position(city, stop, (cost, &path), min_cost, options.weight_format);
+    }
+
+    if options.repeat > 1 {
+        let mut samples: Vec<Duration> = repeat_runs.iter().map(|(time, _)| *time).collect();
+        samples.push(elapsed);
+        print_repeat_stats(&samples);
+    }
+
+    if let Some(path) = &options.stats_json {
+        RunStats::collect(&solver, min_cost, &optimal_path, load_time, load_time + elapsed, &warnings).write(path)?;
+    }
+
+    if let Some(path) = &options.png {
+        match &coords {
+            Some(coords) if !is_infeasible(min_cost) => {
+                let title = format!("Optimal tour, cost {}", options.weight_format.render(min_cost));
+                render_png(path, coords, &optimal_path, &title)?;
+                println!("🖼️  Tour image written to {}", path.bright_blue());
+            }
+            Some(_) => println!("{}", "ℹ️  No valid tour, so no image was drawn".dimmed()),
+            None => println!(
+                "{}",
+                "ℹ️  --png needs city coordinates (TSPLIB or coordinate input); skipped".dimmed()
+            ),
+        }
+    }
+
+    if let Some(path) = &options.geojson {
+        match &coords {
+            Some(coords) if !is_infeasible(min_cost) => {
+                fs::write(path, render_geojson(coords, metric, &optimal_path, names.as_deref()))
+                    .map_err(|e| format!("Error writing GeoJSON file '{}': {}", path, e))?;
+                println!("🗺️  Tour GeoJSON written to {}", path.bright_blue());
+            }
+            Some(_) => println!("{}", "ℹ️  No valid tour, so no GeoJSON was written".dimmed()),
+            None => println!(
+                "{}",
+                "ℹ️  --geojson needs city coordinates (TSPLIB or coordinate input); skipped".dimmed()
+            ),
+        }
+    }
+
+    if let Some(path) = &options.markdown {
+        let full_dist = subset.as_ref().map_or(solver.dist.as_slice(), |(full, _)| full.as_slice());
+        let report = render_markdown(full_dist, min_cost, &optimal_path, elapsed, &solver, options.weight_format);
+        if path == "-" {
+            println!();
+            print!("{}", report);
+        } else {
+            fs::write(path, report)
+                .map_err(|e| format!("Error writing Markdown report '{}': {}", path, e))?;
+        }
+    }
+
+    if let Some(path) = &options.permutation_export {
+        if is_infeasible(min_cost) {
+            return Err("No valid tour to export as a permutation".into());
+        }
+        fs::write(path, format_permutation(&optimal_path, options.append_return) + "\n")
+            .map_err(|e| format!("Error writing permutation file '{}': {}", path, e))?;
+    }
+
+    if let Some(path) = &options.legs_csv {
+        if is_infeasible(min_cost) {
+            return Err("No valid tour to export as legs CSV".into());
+        }
+        let full_dist = subset.as_ref().map_or(solver.dist.as_slice(), |(full, _)| full.as_slice());
+        fs::write(path, render_legs_csv(full_dist, &optimal_path, coords.as_deref(), options.weight_format))
+            .map_err(|e| format!("Error writing legs CSV '{}': {}", path, e))?;
+    }
+
+    if let (Some(expected), Some(path)) = (&expected, &options.validate_against) {
+        let full_dist = subset.as_ref().map_or(solver.dist.as_slice(), |(full, _)| full.as_slice());
+        if !check_expected(expected, min_cost, Some(&optimal_path), full_dist, options.weight_format) {
+            return Err(format!("Result doesn't match the expected answer in '{}'", path).into());
+        }
+    }
+
+    if interactive && !is_infeasible(min_cost) && solver.has_table() {
+        let reduced_path = match &subset {
+            Some((_, subset)) => subset.to_reduced(&optimal_path)?,
+            None => optimal_path.clone(),
+        };
+        explain_legs(&solver, &reduced_path, subset.as_ref().map(|(_, subset)| subset), options.weight_format)?;
+    }
+    
+    print_warnings(&warnings);
+    print_farewell();
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The matrix of `test/input/input1.txt` (optimum 80 via 0 → 1 → 3 → 2 → 0).
+    fn input1() -> Vec<Vec<Weight>> {
+        vec![
+            vec![0, 10, 15, 20],
+            vec![10, 0, 35, 25],
+            vec![15, 35, 0, 30],
+            vec![20, 25, 30, 0],
+        ]
+    }
+
+    fn quiet_solver(dist: Vec<Vec<Weight>>) -> TSPSolver {
+        let mut solver = TSPSolver::new(dist).unwrap();
+        solver.set_announce(false);
+        solver
+    }
+
+    /// A file under the temp directory, unique to this process and test.
+    fn temp_path(name: &str) -> String {
+        let path = std::env::temp_dir().join(format!("tsp_test_{}_{}", std::process::id(), name));
+        path.to_string_lossy().into_owned()
+    }
+
+    #[test]
+    fn selftest_harness_passes_a_few_seeds() {
+        for seed in [1, 2, 3] {
+            assert_eq!(run_selftest_instances(seed, 25), 0, "seed {}", seed);
+        }
+    }
+
+    /// The plain optimum ends with its cheapest leg (City1 → City0), but
+    /// starts the tour with it instead once a multiplier of 5 on the last
+    /// leg makes the return expensive.
+    #[test]
+    fn leg_profile_moves_the_cheap_leg_off_the_end() {
+        assert_eq!(quiet_solver(input1()).solve(), (80, vec![0, 1, 3, 2, 0]));
+        let mut profiled = quiet_solver(input1());
+        profiled.set_leg_profile(&[1, 1, 1, 5]);
+        assert_eq!(profiled.solve(), (120, vec![0, 2, 3, 1, 0]));
+    }
+
+    /// The first leg ties with the reverse tour, the second has a clear
+    /// runner-up (City2, 85 against 70 from City1), and the last two legs
+    /// are forced.
+    #[test]
+    fn runner_ups_along_input1() {
+        let mut solver = quiet_solver(input1());
+        solver.set_force_dp(true);
+        let (_, path) = solver.solve();
+        assert_eq!(solver.runner_ups(&path), [Some((2, 0)), Some((2, 15)), None, None]);
+    }
+
+    /// Four cities all equally far apart have three distinct optimal tours;
+    /// a cheap one-way cycle through otherwise expensive edges has one.
+    #[test]
+    fn distinct_optimal_tours() {
+        let square = vec![vec![0, 10, 10, 10], vec![10, 0, 10, 10], vec![10, 10, 0, 10], vec![10, 10, 10, 0]];
+        let cycle = vec![vec![0, 1, 9, 9], vec![9, 0, 1, 9], vec![9, 9, 0, 1], vec![1, 9, 9, 0]];
+        for (dist, expected) in [(square, 3), (cycle, 1)] {
+            let mut solver = quiet_solver(dist);
+            let (cost, _) = solver.solve();
+            assert_eq!(solver.distinct_optimal_tours(cost), expected);
+        }
+    }
+
+    /// A one-way edge list cheap only along 0 → 2 → 1 → 3 → 0: the path
+    /// must come back in that direction, its forward cost the optimum.
+    #[test]
+    fn directed_edge_list_keeps_its_direction() {
+        let edges = "4\n0 2 1\n2 1 2\n1 3 3\n3 0 4\n2 0 50\n1 2 50\n3 1 50\n0 3 50\n0 1 20\n1 0 30\n2 3 40\n3 2 60\n";
+        let parse = ParseOptions { directed: true, ..ParseOptions::default() };
+        let dist = parse_edge_list(edges, &parse).unwrap();
+        let mut solver = quiet_solver(dist.clone());
+        let (cost, path) = solver.solve();
+        assert_eq!((cost, path.as_slice()), (10, &[0, 2, 1, 3, 0][..]));
+        assert_eq!(tour_cost(&dist, &path), Some(cost));
+        solver.verify_path(cost, &path).unwrap();
+    }
+
+    /// Three roads between City0 and City1 (one written `1 0`) resolve to the
+    /// cheapest by default, and to the dearest, their sum or an error on
+    /// request, in edge lists and topology files alike.
+    #[test]
+    fn parallel_edge_policies() {
+        let edges = "3\n0 1 7\n0 1 3\n1 0 5\n1 2 4\n0 2 6\n";
+        let (topology, weights) = ("3\n0 1\n0 1\n1 0\n1 2\n0 2\n", "7\n3\n5\n4\n6\n");
+        for (policy, expected) in [
+            (ParallelEdges::Min, Some(3)),
+            (ParallelEdges::Max, Some(7)),
+            (ParallelEdges::Sum, Some(15)),
+            (ParallelEdges::Error, None),
+        ] {
+            let parse = ParseOptions { parallel_edges: policy, ..ParseOptions::default() };
+            for parsed in [parse_edge_list(edges, &parse), parse_split_input(topology, weights, &parse)] {
+                let road = parsed.ok().map(|dist| (dist[0][1], dist[1][0], dist[1][2]));
+                assert_eq!(road, expected.map(|weight| (weight, weight, 4)), "{:?}", policy);
+            }
+        }
+    }
+
+    /// A header plus one row per leg of the closed tour, the last cumulative
+    /// equal to the cost, and four more columns when coordinates are given.
+    #[test]
+    fn legs_csv_rows_and_columns() {
+        let mut solver = quiet_solver(input1());
+        let (cost, path) = solver.solve();
+        let coords: Vec<(f64, f64)> = (0..4).map(|city| (city as f64, 0.5)).collect();
+        for (columns, coords) in [(5, None), (9, Some(coords.as_slice()))] {
+            let csv = render_legs_csv(&solver.dist, &path, coords, WeightFormat::Plain);
+            let rows: Vec<Vec<&str>> = csv.lines().skip(1).map(|line| line.split(',').collect()).collect();
+            assert_eq!(rows.len(), 4);
+            assert!(rows.iter().all(|row| row.len() == columns), "{}", csv);
+            assert_eq!(rows[3][4], cost.to_string());
+        }
+    }
+
+    /// Once, at the start, on a closed tour; at the depot's first visit on a
+    /// rotated one; nowhere with `--no-depot-highlight`. The marker brings
+    /// the depot color with it.
+    #[test]
+    fn depot_marker_placement() {
+        for (path, marker, marked) in [
+            (&[0, 2, 1, 3, 0][..], true, &[0][..]),
+            (&[2, 1, 0, 3, 2][..], true, &[2][..]),
+            (&[0, 2, 1, 3, 0][..], false, &[][..]),
+        ] {
+            let text = render_path(path, marker);
+            let stops: Vec<&str> = text.split(" → ").collect();
+            assert_eq!(stops.len(), path.len(), "{}", text);
+            for (stop, &city) in stops.iter().zip(path) {
+                assert!(stop.contains(&format!("City{}", city)), "{}", text);
+            }
+            let found: Vec<usize> = (0..stops.len()).filter(|&k| stops[k].contains('🏠')).collect();
+            assert_eq!(found, marked, "{}", text);
+        }
+        assert_eq!(depot_label(0).fgcolor, Some(Color::BrightYellow));
+    }
+
+    /// The detailed route follows the same rule: the depot's first visit is
+    /// marked, as the start of step 1 on a closed tour or on both sides of
+    /// it on a rotated one, and the return leg is not.
+    #[test]
+    fn detailed_route_marks_the_depot_once() {
+        let route = |path: &[usize], marker: bool| {
+            let lines = render_detailed_route(path, &[], &input1(), WeightFormat::Plain, false, None, marker);
+            assert_eq!(lines.len(), path.len() - 1);
+            lines
+        };
+        let marked = |lines: &[String]| (0..lines.len()).filter(|&k| lines[k].contains('🏠')).collect::<Vec<_>>();
+
+        let closed = route(&[0, 1, 3, 2, 0], true);
+        assert_eq!(marked(&closed), [0]);
+        assert_eq!(closed[0].matches('🏠').count(), 1);
+        assert!(closed[0].find('🏠') < closed[0].find('→'), "{}", closed[0]);
+
+        let rotated = route(&[2, 1, 0, 3, 2], true);
+        assert_eq!(marked(&rotated), [1, 2]);
+        assert!(rotated[1].find('🏠') > rotated[1].find('→'), "{}", rotated[1]);
+        assert!(rotated[2].find('🏠') < rotated[2].find('→'), "{}", rotated[2]);
+
+        assert!(marked(&route(&[0, 1, 3, 2, 0], false)).is_empty());
+    }
+
+    /// Open or closed, and from any start, input1's optimal tour scores 80.
+    #[test]
+    fn tour_cost_of_a_valid_tour() {
+        for path in [&[0, 1, 3, 2][..], &[0, 1, 3, 2, 0], &[2, 0, 1, 3]] {
+            assert_eq!(tour_cost(&input1(), path), Some(80), "{:?}", path);
+        }
+    }
+
+    /// A forbidden leg, a forbidden way back, and legs just under `INF` whose
+    /// sum would overflow.
+    #[test]
+    fn tour_cost_of_a_forbidden_tour() {
+        let mut dist = input1();
+        dist[1][3] = INF;
+        dist[2][0] = INF;
+        for path in [&[0, 1, 3, 2][..], &[0, 3, 1, 2]] {
+            assert_eq!(tour_cost(&dist, path), None, "{:?}", path);
+        }
+        let near = vec![vec![0, INF - 1, INF - 1], vec![INF - 1, 0, INF - 1], vec![INF - 1, INF - 1, 0]];
+        assert_eq!(tour_cost(&near, &[0, 1, 2, 0]), None);
+    }
+
+    #[test]
+    fn tour_cost_matches_the_solver() {
+        for dist in [input1(), vec![vec![0, 1, 9, 9], vec![9, 0, 1, 9], vec![9, 9, 0, 1], vec![1, 9, 9, 0]]] {
+            let (cost, path) = quiet_solver(dist.clone()).solve();
+            assert_eq!(tour_cost(&dist, &path), Some(cost));
+            assert_eq!(tour_cost(&dist, &path[..path.len() - 1]), Some(cost));
+        }
+    }
+
+    #[test]
+    fn cycle_notation_breaks_out_the_return_leg() {
+        let dist = input1();
+        let (_, path) = quiet_solver(dist.clone()).solve();
+        let last = path[path.len() - 2];
+        let cities: Vec<String> = path[..path.len() - 1].iter().map(usize::to_string).collect();
+        let expected = format!("({} | back to 0, +{})", cities.join(" "), dist[last][0]);
+        assert_eq!(format_cycle_notation(&dist, &path, WeightFormat::Plain), expected);
+        assert_eq!(format_cycle_notation(&dist, &[0], WeightFormat::Plain), "(0)");
+    }
+
+    /// The optimum rises by exactly the total service time, whatever tour
+    /// the DP picks; a short list and a negative time are refused.
+    #[test]
+    fn service_times_add_to_the_optimum() {
+        let service = parse_service_times("# one per city\n4\n0\n7\n2\n", 4, WeightFormat::Plain).unwrap();
+        let mut served = input1();
+        assert_eq!(add_service_times(&mut served, &service), Ok(13));
+        assert_eq!(quiet_solver(served).solve().0, 80 + 13);
+        assert!(parse_service_times("1\n2\n3\n", 4, WeightFormat::Plain).is_err());
+        assert!(parse_service_times("1\n-2\n3\n4\n", 4, WeightFormat::Plain).is_err());
+    }
+
+    /// From 1 to 8 restarts with one seed the best never gets worse, and the
+    /// agreement stays within `1..=restarts`.
+    #[test]
+    fn more_restarts_never_do_worse() {
+        let mut rng = SplitMix64(42);
+        let n = 12;
+        let mut dist = vec![vec![0; n]; n];
+        for (i, j) in (0..n).flat_map(|i| (i + 1..n).map(move |j| (i, j))) {
+            dist[i][j] = 1 + rng.below(100) as Weight;
+            dist[j][i] = dist[i][j];
+        }
+        let first: Vec<usize> = (0..n).chain([0]).collect();
+        let mut previous = INF;
+        for restarts in 1..=8 {
+            let (cost, path, agreeing) = restart_two_opt(&dist, first.clone(), restarts, false, 7).unwrap();
+            assert!(cost <= previous, "{} restarts found {}, worse than {}", restarts, cost, previous);
+            assert!((1..=restarts).contains(&agreeing));
+            assert_eq!(tour_cost(&dist, &path), Some(cost));
+            previous = cost;
+        }
+    }
+
+    /// At the bitmask limit and one city past it, which must be refused
+    /// rather than overflow `1 << n`.
+    #[test]
+    fn too_many_cities_for_the_bitmask() {
+        let square = |n: usize| vec![vec![1; n]; n];
+        let cities = MAX_BITMASK_CITIES + 1;
+        assert_eq!(TSPSolver::new(square(cities)).err(), Some(TspError::TooManyCities { cities }));
+        let solver = TSPSolver::new(square(MAX_BITMASK_CITIES)).unwrap();
+        assert!(solver.total_states > 0);
+    }
+
+    /// Every tour costs 20; only 0 → 2 → 1 → 3 → 0 (or its reverse) uses a
+    /// single leg weight, 5.
+    #[test]
+    fn prefer_uniform_picks_the_even_tour() {
+        let dist = vec![vec![0, 3, 5, 5], vec![3, 0, 5, 5], vec![5, 5, 0, 7], vec![5, 5, 7, 0]];
+        let mut solver = quiet_solver(dist);
+        let (cost, _) = solver.solve();
+        assert_eq!(cost, 20);
+        let (path, distinct, compared) = solver.most_uniform_optimal(cost).unwrap();
+        assert!(path == [0, 2, 1, 3, 0] || path == [0, 3, 1, 2, 0], "{}", format_path(&path));
+        assert_eq!((distinct, compared), (1, 3));
+    }
+
+    /// `--route-limit 3` on a 20-city tour shows steps 1-3, an ellipsis
+    /// totalling the 14 legs between, and steps 18-20; a 5-city tour is
+    /// shown in full.
+    #[test]
+    fn route_limit_elides_the_middle() {
+        assert_eq!(detailed_route_legs(20, Some(3)), [Some(0), Some(1), Some(2), None, Some(17), Some(18), Some(19)]);
+        let n = 20;
+        let dist: Vec<Vec<Weight>> = (0..n).map(|i| (0..n).map(|j| Weight::from(i != j)).collect()).collect();
+        let path: Vec<usize> = (0..n).chain([0]).collect();
+        let lines = render_detailed_route(&path, &[], &dist, WeightFormat::Plain, false, Some(3), true);
+        assert_eq!(lines.len(), 7);
+        assert_eq!(lines.iter().filter(|line| line.contains("Step")).count(), 6);
+        assert!(lines[3].contains('⋯') && lines[3].contains("14 more legs, 14 in total"), "{}", lines[3]);
+        assert_eq!(detailed_route_legs(5, Some(3)).len(), 5);
+        assert!(detailed_route_legs(20, None).is_empty());
+    }
+
+    /// Two solves, one per memo backend, log the same bytes, the start
+    /// state chooses the optimal tour's first city, and a changed matrix
+    /// changes the log.
+    #[test]
+    fn grade_log_is_deterministic() {
+        let log_for = |dist: Vec<Vec<Weight>>, backend: MemoBackend| {
+            let mut solver = quiet_solver(dist);
+            solver.set_memo_backend(backend);
+            solver.set_force_dp(true);
+            let (cost, path) = solver.solve();
+            (render_grade_log(&solver, cost), path)
+        };
+        let (first, path) = log_for(input1(), MemoBackend::Vec);
+        assert_eq!(log_for(input1(), MemoBackend::HashMap).0, first);
+        let start = format!("1 0001 0 {} 80", path[1]);
+        assert!(first.lines().any(|line| line == start), "no start line '{}'", start);
+        let mut changed = input1();
+        changed[1][2] = 5;
+        changed[2][1] = 5;
+        assert_ne!(log_for(changed, MemoBackend::Vec).0, first);
+    }
+
+    /// Input1 read from text, and a matrix with `INF` and a negative weight,
+    /// round trip through a file; a wrong magic, a newer version and a
+    /// truncated file are refused.
+    #[test]
+    fn binary_matrix_round_trip() {
+        let text = render_matrix_file(&input1(), WeightFormat::Plain, None);
+        let loaded = parse_matrix(&text, &ParseOptions::default()).unwrap();
+        let mut gaps = loaded.clone();
+        gaps[1][2] = INF;
+        gaps[3][0] = -5;
+        let path = temp_path("matrix.tspb");
+        for matrix in [&loaded, &gaps] {
+            let bytes = encode_binary_matrix(matrix).unwrap();
+            assert_eq!(bytes.len(), 12 + 4 * 16);
+            fs::write(&path, &bytes).unwrap();
+            assert_eq!(&read_binary_matrix(&path).unwrap(), matrix);
+        }
+        let _ = fs::remove_file(&path);
+
+        let good = encode_binary_matrix(&loaded).unwrap();
+        let mut bad_magic = good.clone();
+        bad_magic[0] = b'X';
+        let mut newer = good.clone();
+        newer[4] = 2;
+        for bytes in [bad_magic, newer, good[..good.len() - 1].to_vec()] {
+            assert!(decode_binary_matrix(&bytes).is_err());
+        }
+    }
+
+    /// All `INF` off the diagonal is infeasible without a single DP state;
+    /// one finite edge is enough to run the DP.
+    #[test]
+    fn no_finite_edges_skips_the_dp() {
+        let empty: Vec<Vec<Weight>> = (0..5).map(|i| (0..5).map(|j| if i == j { 0 } else { INF }).collect()).collect();
+        assert!(!has_finite_edge(&empty));
+        let mut solver = quiet_solver(empty.clone());
+        let (cost, _) = solver.solve();
+        assert!(is_infeasible(cost));
+        assert_eq!(solver.computed_states, 0);
+        let mut one_edge = empty;
+        one_edge[3][1] = 7;
+        assert!(has_finite_edge(&one_edge));
+    }
+
+    /// City0 plus groups {1, 2} and {3, 4} (ids 7 and 9). Of the four ways
+    /// to pick one city per group, City2 with City4 is much the cheapest
+    /// (9); a city without a group and one listed twice are refused.
+    #[test]
+    fn groups_pick_one_city_each() {
+        let dist = vec![
+            vec![0, 10, 3, 10, 4],
+            vec![10, 0, 1, 5, 10],
+            vec![3, 1, 0, 10, 2],
+            vec![10, 5, 10, 0, 1],
+            vec![4, 10, 2, 1, 0],
+        ];
+        let groups = parse_groups("0 0\n1 7\n2 7  # a comment\n3 9\n4 9\n", 5).unwrap();
+        let (cost, path) = solve_groups(&dist, &groups, false).unwrap();
+        assert_eq!((cost, path.len()), (9, 4));
+        assert!(path.contains(&2) && path.contains(&4), "{:?}", path);
+        let (cost, path) = solve_groups(&dist, &groups, true).unwrap();
+        assert_eq!(cost, 25);
+        assert!(path.contains(&1) && path.contains(&3), "{:?}", path);
+        for broken in ["0 0\n1 7\n2 7\n3 9\n", "0 0\n1 7\n2 7\n3 9\n4 9\n2 9\n"] {
+            assert!(parse_groups(broken, 5).is_err(), "{:?}", broken);
+        }
+    }
+
+    #[test]
+    fn throughput_is_rounded_and_grouped() {
+        assert_eq!(format_throughput(5_000, Duration::from_secs(2)).as_deref(), Some("2,500 states/s"));
+        assert_eq!(format_throughput(1_000_000, Duration::from_millis(300)).as_deref(), Some("3,333,333 states/s"));
+        assert_eq!(format_throughput(100, Duration::ZERO), None);
+    }
+
+    /// Passes input1's optimum and catches corrupted copies of it: a repeated
+    /// city, a missing one, one out of range, and a path that doesn't return
+    /// to City0.
+    #[test]
+    fn reconstruction_guard_catches_corrupted_paths() {
+        let mut solver = quiet_solver(input1());
+        let (cost, path) = solver.solve();
+        solver.check_reconstruction(cost, &path).unwrap();
+        for corrupted in [&[0, 1, 1, 2, 0][..], &[0, 1, 3, 0], &[0, 1, 3, 7, 0], &[0, 1, 3, 2]] {
+            match solver.check_reconstruction(cost, corrupted) {
+                Err(TspError::ReconstructionBug { cities: 4, cost: 80, path, .. }) => assert_eq!(path, corrupted),
+                other => panic!("the corrupted path {:?} gave {:?}", corrupted, other),
+            }
+        }
+    }
+
+    /// Input1 encoded with padding solves to 80, a URL-safe string without
+    /// padding to 6, and broken strings are refused.
+    #[test]
+    fn base64_instances() {
+        let loader = InstanceLoader::new(ParseOptions::default());
+        for (text, expected) in [
+            ("NAowIDEwIDE1IDIwCjEwIDAgMzUgMjUKMTUgMzUgMCAzMAoyMCAyNSAzMCAwCg==", 80),
+            ("MyAjIH5-fiA_Pz8KMCAxIDIKMSAwIDMKMiAzIDAK", 6),
+        ] {
+            let instance = loader.load_base64(text).unwrap();
+            assert_eq!(quiet_solver(instance.distances).solve().0, expected, "{}", text);
+        }
+        for broken in ["NAowIDEw*DE1", "NAowI", "/w=="] {
+            assert!(loader.load_base64(broken).is_err(), "{}", broken);
+        }
+    }
+
+    /// The cells are asked in row order, mirrored unless directed, with
+    /// `INF` kept and the diagonal 0.
+    #[test]
+    fn grid_entry_fills_in_row_order() {
+        let cases = [
+            (false, &["10", "INF", "20"][..], vec![vec![0, 10, INF], vec![10, 0, 20], vec![INF, 20, 0]]),
+            (true, &["1", "2", "3", "4", "inf", "6"][..], vec![vec![0, 1, 2], vec![3, 0, 4], vec![INF, 6, 0]]),
+        ];
+        for (directed, answers, expected) in cases {
+            let mut script = answers.iter();
+            let mut asked = Vec::new();
+            let mut rows = 0;
+            let filled = fill_matrix_grid(
+                3,
+                directed,
+                |i, j| {
+                    asked.push((i, j));
+                    parse_entry(script.next().ok_or("ran out of answers")?, WeightFormat::Plain)
+                },
+                |_| rows += 1,
+            );
+            let order: Vec<(usize, usize)> = (0..3)
+                .flat_map(|i| (0..3).map(move |j| (i, j)))
+                .filter(|&(i, j)| if directed { i != j } else { i < j })
+                .collect();
+            assert_eq!(filled.unwrap(), expected);
+            assert_eq!(asked, order);
+            assert_eq!(rows, if directed { 3 } else { 2 });
+        }
+    }
+
+    /// Dashboards on a test backend: one watching a solve of input1 that must
+    /// still find 80, one fed a finished state that must show the matrix and
+    /// a full gauge.
+    #[cfg(feature = "tui")]
+    #[test]
+    fn tui_dashboard_draws_the_solve() {
+        use ratatui::backend::TestBackend;
+        use ratatui::Terminal;
+
+        let dist = input1();
+        let dashboard = |dist: &[Vec<Weight>]| Dashboard::new(Terminal::new(TestBackend::new(100, 16)).unwrap(), dist, false);
+        let mut solver = quiet_solver(dist.clone());
+        solver.set_force_dp(true);
+        solver.set_dashboard(Box::new(dashboard(&dist)));
+        assert_eq!(solver.solve().0, 80);
+        solver.close_dashboard();
+
+        let mut finished = dashboard(&dist);
+        let total = solver.total_states;
+        finished.update(DashboardState { n: 4, computed: total, total, mask: 1, pos: 0, value: 80, next: Some(1) });
+        finished.finish();
+        let screen = finished.terminal.backend().to_string();
+        for expected in ["Distance Matrix", "10  0 35 25", "100%", "Best:   80 to finish, via City1"] {
+            assert!(screen.contains(expected), "the dashboard doesn't show '{}'", expected);
+        }
+    }
+
+    /// Input1 scaled by 10⁸, so every weight and the optimum (8·10⁹) are
+    /// beyond `i32::MAX`, read from text and solved.
+    #[cfg(feature = "wide")]
+    #[test]
+    fn wide_weights_beyond_i32() {
+        let text = "4\n0 1000000000 1500000000 2000000000\n1000000000 0 3500000000 2500000000\n\
+                    1500000000 3500000000 0 3000000000\n2000000000 2500000000 3000000000 0\n";
+        let dist = parse_matrix(text, &ParseOptions::default()).unwrap();
+        check_weight_range(&dist).unwrap();
+        let mut solver = quiet_solver(dist);
+        solver.set_force_dp(true);
+        assert_eq!(solver.solve(), (8_000_000_000, vec![0, 1, 3, 2, 0]));
+    }
+
+    /// A missing file must fail without sitting through the retry delays.
+    #[test]
+    fn read_file_present_and_missing() {
+        let path = temp_path("read.txt");
+        fs::write(&path, "2\n0 1\n1 0\n").unwrap();
+        assert_eq!(read_file(&path).unwrap(), "2\n0 1\n1 0\n");
+        fs::remove_file(&path).unwrap();
+
+        let start = Instant::now();
+        assert_eq!(read_file(&path).unwrap_err().kind(), std::io::ErrorKind::NotFound);
+        assert!(start.elapsed() < READ_RETRY_DELAY, "a missing file was retried");
+    }
+
+    fn geo_coordinates() -> CoordinateDistances {
+        CoordinateDistances {
+            coords: vec![(38.24, 20.42), (39.57, 26.15), (40.56, 25.32), (36.26, 23.12)],
+            rounding: Rounding::Floor,
+            metric: Metric::Geo,
+        }
+    }
+
+    #[test]
+    fn matrix_cache_round_trip() {
+        let path = temp_path("cache");
+        let mut coordinates = geo_coordinates();
+        let direct = coordinates.materialize().unwrap();
+        write_matrix_cache(&path, coordinates.fingerprint(), &direct).unwrap();
+        assert_eq!(read_matrix_cache(&path, coordinates.fingerprint()).unwrap(), direct);
+        coordinates.coords[3].0 += 0.01;
+        assert!(read_matrix_cache(&path, coordinates.fingerprint()).is_err());
+        let _ = fs::remove_file(&path);
+    }
+
+    /// `--emit-matrix` must read back unchanged, `INF`, names and durations
+    /// included.
+    #[test]
+    fn emit_matrix_round_trip() {
+        let mut emitted = geo_coordinates().materialize().unwrap();
+        emitted[1][2] = INF;
+        emitted[3][0] = -5;
+        let names: Vec<String> = ["Patras", "Izmir", "Lesbos", "Kythira"].iter().map(|name| name.to_string()).collect();
+        for format in [WeightFormat::Plain, WeightFormat::Duration] {
+            let text = render_matrix_file(&emitted, format, Some(&names));
+            let parse = ParseOptions { weight_format: format, ..ParseOptions::default() };
+            assert_eq!(parse_matrix(&text, &parse).unwrap(), emitted, "{:?}", format);
+            assert_eq!(row_comment_names(&text, emitted.len()), Some(names.clone()), "{:?}", format);
+        }
+    }
+
+
+    /// `--evaluate` on input1: a tour is closed back to its start and costed,
+    /// and repeated, missing or unknown cities are refused.
+    #[test]
+    fn evaluate_costs_a_given_tour() {
+        let tour = validate_tour(&parse_city_list("0, 2,1,3").unwrap(), 4).unwrap();
+        assert_eq!(tour, [0, 2, 1, 3, 0]);
+        assert_eq!(tour_cost(&input1(), &tour), Some(15 + 35 + 25 + 20));
+        assert_eq!(validate_tour(&[0, 1, 3, 2, 0], 4).map(|tour| tour_cost(&input1(), &tour)), Ok(Some(80)));
+        for broken in [&[0, 1, 1, 2][..], &[0, 1, 3], &[0, 1, 2, 4], &[]] {
+            assert!(validate_tour(broken, 4).is_err(), "{:?}", broken);
+        }
+    }
+
+
+    /// Narrow widths: centered lines never run past the width, lines are left
+    /// alone below `MIN_CENTERED_WIDTH`, and the terminal never widens the
+    /// report.
+    #[test]
+    fn narrow_widths_stay_inside_the_report() {
+        let text = "Dynamic Programming";
+        for width in [text.len() + 1, MIN_CENTERED_WIDTH - 1, MIN_CENTERED_WIDTH, 55, REPORT_WIDTH] {
+            let line = center_text(text, width);
+            assert!(line.chars().count() <= width, "{} chars at width {}", line.chars().count(), width);
+            assert_eq!(line.starts_with(' '), width >= MIN_CENTERED_WIDTH, "width {}", width);
+        }
+        assert_eq!(center_text(text, 10), text);
+        assert!(report_width() <= REPORT_WIDTH);
+    }
+
+
+    /// `--maximize` on input1 finds the longest tour (95, through the 35 edge)
+    /// and both results are valid tours; with that edge forbidden, only the
+    /// 80 tour is left.
+    #[test]
+    fn maximize_finds_the_longest_tour() {
+        let mut shortest = quiet_solver(input1());
+        let (low, low_path) = shortest.solve();
+        let mut longest = quiet_solver(input1());
+        longest.set_maximize(true);
+        let (high, high_path) = longest.solve();
+        assert_eq!((low, high), (80, 95));
+        shortest.verify_path(low, &low_path).unwrap();
+        longest.verify_path(high, &high_path).unwrap();
+        assert_eq!(tour_cost(&input1(), &high_path), Some(95));
+
+        let mut dist = input1();
+        dist[1][2] = INF;
+        dist[2][1] = INF;
+        let mut longest = quiet_solver(dist.clone());
+        longest.set_maximize(true);
+        let (high, path) = longest.solve();
+        assert_eq!((high, tour_cost(&dist, &path)), (80, Some(80)));
+    }
+
+
+    /// `--log`: a solve writes timestamped start and finish records, in
+    /// that order.
+    #[test]
+    fn log_records_start_and_finish() {
+        let path = temp_path("run.log");
+        let mut solver = quiet_solver(input1());
+        solver.set_logger(RunLogger::create(&path).unwrap());
+        solver.solve();
+        drop(solver);
+        let log = fs::read_to_string(&path).unwrap();
+        let _ = fs::remove_file(&path);
+        let started = log.lines().position(|line| line.contains("DP started (4 cities"));
+        let solved = log.lines().position(|line| line.contains("Solved: cost 80"));
+        assert!(started.is_some() && solved > started, "{}", log);
+        assert!(log.lines().all(|line| line.starts_with("[20") && line.contains("s] ")), "{}", log);
+    }
+
+
+    /// `--rounding` on a 2.5 distance (and an exact 5): nearest and ceil go
+    /// up, floor down, and `none` refuses the fraction.
+    #[test]
+    fn rounding_modes_on_a_half() {
+        let distances = |rounding| {
+            CoordinateDistances { coords: vec![(0.0, 0.0), (2.5, 0.0), (3.0, 4.0)], rounding, metric: Metric::Euclidean }
+                .materialize()
+                .map(|dist| (dist[0][1], dist[0][2]))
+        };
+        assert_eq!(distances(Rounding::Nearest), Ok((3, 5)));
+        assert_eq!(distances(Rounding::Ceil), Ok((3, 5)));
+        assert_eq!(distances(Rounding::Floor), Ok((2, 5)));
+        assert!(distances(Rounding::Exact).is_err());
+        assert_eq!(Rounding::Exact.apply(5.0), Ok(5));
+    }
+
+
+    /// A symmetric `n`-city matrix of weights 1..=100 drawn from `seed`.
+    fn random_matrix(n: usize, seed: u64) -> Vec<Vec<Weight>> {
+        let mut rng = SplitMix64(seed);
+        let mut dist = vec![vec![0; n]; n];
+        for (i, j) in (0..n).flat_map(|i| (i + 1..n).map(move |j| (i, j))) {
+            dist[i][j] = 1 + rng.below(100) as Weight;
+            dist[j][i] = dist[i][j];
+        }
+        dist
+    }
+
+    /// `--checkpoint` saving once, halfway through the fill, stands in for
+    /// an interrupted run: `--resume` from it gives the uninterrupted
+    /// result, and another instance refuses it.
+    #[test]
+    fn resume_from_a_halfway_checkpoint() {
+        let dist = random_matrix(8, 5);
+        let path = temp_path("resume.ckpt");
+        let uninterrupted = quiet_solver(dist.clone()).solve();
+
+        let mut interrupted = quiet_solver(dist.clone());
+        let halfway = interrupted.total_states / 2;
+        interrupted.set_checkpoint(path.clone(), halfway);
+        interrupted.solve();
+
+        let mut resumed = quiet_solver(dist.clone());
+        resumed.resume_from(&path).unwrap();
+        assert!((halfway..interrupted.total_states).contains(&resumed.computed_states));
+        assert_eq!(resumed.solve(), uninterrupted);
+
+        assert!(quiet_solver(random_matrix(8, 6)).resume_from(&path).is_err());
+        let _ = fs::remove_file(&path);
+    }
+
+
+    /// An edge list where City4 has no edges and City3 only one: the degree
+    /// counts, the isolated city and the dead end are reported, and the
+    /// instance has no tour.
+    #[test]
+    fn degree_summary_flags_an_isolated_city() {
+        let dist = parse_edge_list("5\n0 1 3\n1 2 4\n2 0 5\n3 1 2\n", &ParseOptions::default()).unwrap();
+        let summary = DegreeSummary::new(&dist);
+        assert_eq!(summary.degrees, [2, 3, 2, 1, 0]);
+        assert_eq!((summary.min(), summary.max(), summary.average()), (0, 3, 1.6));
+        assert_eq!(summary.isolated(), [4]);
+        assert_eq!(summary.dead_ends(), [3]);
+        assert!(is_infeasible(quiet_solver(dist).solve().0));
+    }
+
+
+    /// Edges just under `INF` sum past `Weight::MAX` along any tour; with
+    /// plain adds that wrapped to a small negative cost. Saturating adds
+    /// keep every such tour, and a forbidden edge, at or above `INF`.
+    #[test]
+    fn near_inf_sums_never_wrap() {
+        let n = 5;
+        let mut dist: Vec<Vec<Weight>> = (0..n).map(|i| (0..n).map(|j| if i == j { 0 } else { INF - 1 }).collect()).collect();
+        let mut solver = quiet_solver(dist.clone());
+        solver.set_force_dp(true);
+        assert!(is_infeasible(solver.solve_cost()));
+        for row in dist.iter_mut().skip(1) {
+            row[0] = INF;
+        }
+        assert!(is_infeasible(quiet_solver(dist).solve().0));
+    }
+
+
+    /// Both `--memo` backends give the same tour and the same table on
+    /// random instances, including one-way and maximizing ones.
+    #[test]
+    fn memo_backends_agree() {
+        for seed in 0..20 {
+            let mut dist = random_matrix(2 + seed as usize % 7, seed);
+            if seed % 3 == 0 {
+                dist[1][0] += 7;
+            }
+            let solve = |backend| {
+                let mut solver = quiet_solver(dist.clone());
+                solver.set_memo_backend(backend);
+                solver.set_maximize(seed % 4 == 0);
+                solver.set_force_dp(true);
+                let result = solver.solve();
+                let mut table: Vec<_> = solver.dp.entries().collect();
+                table.sort_unstable();
+                (result, table)
+            };
+            assert_eq!(solve(MemoBackend::Vec), solve(MemoBackend::HashMap), "seed {}", seed);
+        }
+        assert_eq!(MemoBackend::default_for(VEC_MEMO_MAX_CITIES), MemoBackend::Vec);
+        assert_eq!(MemoBackend::default_for(VEC_MEMO_MAX_CITIES + 1), MemoBackend::HashMap);
+    }
+
+
+    /// Input1 breaks the triangle inequality (City1 → City2 is 35, via City0
+    /// 25), which voids the heuristics' guarantees; an equidistant square
+    /// doesn't.
+    #[test]
+    fn non_metric_matrix_is_warned_about() {
+        let warnings = instance_warnings(&input1(), false, WeightFormat::Plain);
+        let non_metric: Vec<&Warning> = warnings.iter().filter(|warning| warning.kind == "non-metric").collect();
+        assert_eq!(non_metric.len(), 1);
+        assert!(non_metric[0].message.contains("City1 → City2 (35)") && non_metric[0].message.contains("heuristics"));
+        let square = vec![vec![0, 10, 10, 10], vec![10, 0, 10, 10], vec![10, 10, 0, 10], vec![10, 10, 10, 0]];
+        assert!(instance_warnings(&square, false, WeightFormat::Plain).is_empty());
+    }
+
+
+    /// `--stats-json` writes a file that reads back as JSON with every
+    /// telemetry key, the cost and tour those of the solve.
+    #[test]
+    fn stats_json_has_the_expected_keys() {
+        let mut solver = quiet_solver(input1());
+        solver.set_force_dp(true);
+        let (cost, path) = solver.solve();
+        let file = temp_path("stats.json");
+        RunStats::collect(&solver, cost, &path, Duration::from_millis(2), Duration::from_millis(5), &[])
+            .write(&file)
+            .unwrap();
+        let stats: serde_json::Value = serde_json::from_str(&fs::read_to_string(&file).unwrap()).unwrap();
+        let _ = fs::remove_file(&file);
+        for key in [
+            "n", "algorithm", "objective", "memo_backend", "cost", "optimality_gap", "tour", "computed_states",
+            "states_stored", "cache_hits", "timings_ms", "memory_estimate_bytes", "seed", "warnings",
+        ] {
+            assert!(stats.get(key).is_some(), "no '{}' in {}", key, stats);
+        }
+        for phase in ["load", "dp", "reconstruct", "total"] {
+            assert!(stats["timings_ms"][phase].is_number(), "no '{}' timing", phase);
+        }
+        assert_eq!((stats["n"].as_u64(), stats["cost"].as_i64()), (Some(4), Some(80)));
+        assert_eq!(stats["tour"], serde_json::json!(path));
+        assert_eq!(stats["computed_states"].as_u64(), Some(solver.computed_states as u64));
+    }
+
+
+    /// Blank lines, comment lines and trailing comments don't count towards
+    /// the `n + 1` lines of a matrix, so it isn't mistaken for an edge list;
+    /// a commented edge list stays one.
+    #[test]
+    fn comments_and_blank_lines_keep_the_matrix_a_matrix() {
+        let loader = InstanceLoader::new(ParseOptions::default());
+        for text in [
+            "4\n0 10 15 20\n10 0 35 25\n15 35 0 30\n20 25 30 0\n\n\n",
+            "# input1\n4\n\n0 10 15 20  # City0\n10 0 35 25\n# row 2 next\n15 35 0 30\n20 25 30 0\n   \n# end\n",
+        ] {
+            assert_eq!(InputFormat::detect(text), InputFormat::Matrix, "{:?}", text);
+            assert_eq!(loader.parse(text).unwrap().distances, input1());
+        }
+        let edges = "4\n# roads\n0 1 4\n\n1 2 5  # bridge\n2 3 6\n3 0 7\n1 3 8\n";
+        assert_eq!(InputFormat::detect(edges), InputFormat::EdgeList);
+        assert_eq!(loader.parse(edges).unwrap().distances[1], [4, 0, 5, 8]);
+    }
+
+
+    /// `--permutation-export` of a solved 7-city tour: `n` distinct indices
+    /// starting at City0, plus City0 again with `--append-return`.
+    #[test]
+    fn permutation_export_lists_each_city_once() {
+        let (_, path) = quiet_solver(random_matrix(7, 3)).solve();
+        let line = format_permutation(&path, false);
+        let cities: Vec<usize> = line.split(' ').map(|city| city.parse().unwrap()).collect();
+        assert_eq!(cities.len(), 7);
+        assert_eq!(cities.iter().collect::<HashSet<_>>().len(), 7);
+        assert_eq!(cities[0], 0);
+        assert_eq!(format_permutation(&path, true), format!("{} 0", line));
+    }
+
+
+    /// A matrix file saved with a UTF-8 byte order mark loads like one
+    /// without.
+    #[test]
+    fn bom_prefixed_file_parses() {
+        let path = temp_path("bom.txt");
+        fs::write(&path, "\u{feff}4\r\n0 10 15 20\r\n10 0 35 25\r\n15 35 0 30\r\n20 25 30 0\r\n").unwrap();
+        let loaded = InstanceLoader::new(ParseOptions::default()).load_file(&path);
+        let _ = fs::remove_file(&path);
+        let instance = loaded.unwrap();
+        assert_eq!(instance.format, InputFormat::Matrix);
+        assert_eq!(instance.distances, input1());
+    }
+
+
+    /// The k best tours start with the DP optimum, never get cheaper, are
+    /// costed correctly and are all different: input1 has exactly three
+    /// tours up to direction, and a 6-city one-way instance all 5! = 120.
+    #[test]
+    fn k_best_tours_are_ordered() {
+        let mut solver = quiet_solver(input1());
+        let best = solver.solve_k_best(10);
+        assert_eq!(best.iter().map(|&(cost, _)| cost).collect::<Vec<_>>(), [80, 95, 95]);
+
+        let mut dist = random_matrix(6, 11);
+        dist[2][4] += 13;
+        let mut solver = quiet_solver(dist.clone());
+        let (optimum, _) = solver.solve();
+        let tours = solver.solve_k_best(200);
+        assert_eq!(tours.len(), 120);
+        assert_eq!(tours[0].0, optimum);
+        assert!(tours.windows(2).all(|pair| pair[0].0 <= pair[1].0));
+        assert!(tours.iter().all(|(cost, path)| tour_cost(&dist, path) == Some(*cost)));
+        assert_eq!(tours.iter().map(|(_, path)| path).collect::<HashSet<_>>().len(), tours.len());
+    }
+
+
+    /// A scripted edit session on input1: making City1 ↔ City2 cheap
+    /// (mirrored, as input1 is symmetric) moves the optimum onto it, a
+    /// one-way edit only changes its own cell, and bad edits are refused.
+    #[test]
+    fn scripted_edits_change_the_solve() {
+        let mut dist = input1();
+        for command in ["1 2 5", "2 3 INF"] {
+            apply_edit(&mut dist, command, WeightFormat::Plain, true).unwrap();
+        }
+        assert_eq!((dist[2][1], dist[3][2]), (5, INF));
+        assert_eq!(quiet_solver(dist.clone()).solve(), (25 + 5 + 15 + 20, vec![0, 2, 1, 3, 0]));
+
+        apply_edit(&mut dist, "0 1 3", WeightFormat::Plain, false).unwrap();
+        assert_eq!((dist[0][1], dist[1][0]), (3, 10));
+        for broken in ["1 1 4", "0 4 2", "0 1", "0 1 x"] {
+            assert!(apply_edit(&mut dist, broken, WeightFormat::Plain, true).is_err(), "{}", broken);
+        }
+    }
+
+
+    /// `--time-format`: `01:30:00` is 5400 seconds, `INF` stays forbidden,
+    /// and a duration matrix solves to a total rendered back as `HH:MM:SS`.
+    #[test]
+    fn durations_parse_and_render() {
+        assert_eq!(parse_weight("01:30:00", WeightFormat::Duration), Some(5400));
+        assert_eq!(parse_weight("INF", WeightFormat::Duration), Some(INF));
+        assert_eq!(parse_weight("0", WeightFormat::Duration), Some(0));
+        for broken in ["01:60:00", "1:30", "-1:00:00"] {
+            assert_eq!(parse_weight(broken, WeightFormat::Duration), None, "{}", broken);
+        }
+        let text = "3\n0 01:30:00 00:20:15\n01:30:00 0 100:00:00\n00:20:15 100:00:00 0\n";
+        let parse = ParseOptions { weight_format: WeightFormat::Duration, ..ParseOptions::default() };
+        let (cost, _) = quiet_solver(parse_matrix(text, &parse).unwrap()).solve();
+        assert_eq!(cost, 5400 + 1215 + 360_000);
+        assert_eq!(WeightFormat::Duration.render(cost), "101:50:15");
+    }
+
+
+    /// `--shortest-path-complete` on the path graph 0 - 1 - 2 - 3: no tour
+    /// exists on its edges, but the closure's tour walks the path there and
+    /// back (cost 2 × 6), its legs expanding into the hops they stand for.
+    #[test]
+    fn closure_makes_a_path_graph_feasible() {
+        let dist = parse_edge_list("4\n0 1 1\n1 2 2\n2 3 3\n", &ParseOptions::default()).unwrap();
+        assert!(is_infeasible(quiet_solver(dist.clone()).solve().0));
+        let closure = ShortestPaths::new(&dist).unwrap();
+        assert_eq!(closure.dist[0][3], 6);
+        let (cost, path) = quiet_solver(closure.dist.clone()).solve();
+        assert_eq!(cost, 12);
+        let hops: Vec<Vec<usize>> = path.windows(2).map(|leg| closure.hops(leg[0], leg[1])).collect();
+        assert!(hops.contains(&vec![3, 2, 1, 0]) || hops.contains(&vec![0, 1, 2, 3]), "{:?}", hops);
+    }
+
+
+    /// `--directed` on a one-way cycle cheap along 0 → 1 → 2 → 3 → 0: the
+    /// optimum (4) runs forward, the reverse costs 32 more, and a forbidden
+    /// reverse leg leaves only the forward direction.
+    #[test]
+    fn direction_report_costs_both_ways() {
+        let mut dist = vec![vec![0, 1, 9, 9], vec![9, 0, 1, 9], vec![9, 9, 0, 1], vec![1, 9, 9, 0]];
+        let (cost, path) = quiet_solver(dist.clone()).solve();
+        assert_eq!((cost, path.as_slice()), (4, &[0, 1, 2, 3, 0][..]));
+        let lines = render_direction_report(&dist, &path, WeightFormat::Plain);
+        assert!(lines[0].contains("Forward:") && lines[0].contains('4'), "{}", lines[0]);
+        assert!(lines[1].contains("Reverse:") && lines[1].contains("36") && lines[1].contains("City0 → City3"), "{}", lines[1]);
+        assert!(lines[2].contains("forward direction") && lines[2].contains("+32"), "{}", lines[2]);
+
+        dist[3][2] = INF;
+        let lines = render_direction_report(&dist, &path, WeightFormat::Plain);
+        assert!(lines[1].contains('∞') && lines[2].contains("Only the forward direction"), "{:?}", lines);
+    }
+
+
+    /// `--matrix-stats`: input1 is complete and symmetric; forbidding one
+    /// direction of two edges drops the density to 10 of 12 (83.3%) and
+    /// breaks the symmetry.
+    #[test]
+    fn matrix_stats_density_and_symmetry() {
+        let stats = MatrixStats::new(&input1());
+        assert_eq!((stats.finite_edges, stats.forbidden_edges, stats.symmetric), (12, 0, true));
+        assert_eq!(stats.density(), 100.0);
+        assert_eq!((stats.min_weight, stats.max_weight, stats.mean_weight), (Some(10), Some(35), Some(22.5)));
+
+        let mut dist = input1();
+        dist[0][3] = INF;
+        dist[2][1] = INF;
+        let stats = MatrixStats::new(&dist);
+        assert_eq!((stats.finite_edges, stats.forbidden_edges, stats.symmetric), (10, 2, false));
+        assert!((stats.density() - 250.0 / 3.0).abs() < 1e-9);
+        assert_eq!(MatrixStats::new(&[vec![0]]).density(), 100.0);
+    }
+
+
+    /// The lazy coordinate provider agrees with its materialized matrix on
+    /// every pair, for each metric and rounding, and 2-opt run on either
+    /// returns the same tour.
+    #[test]
+    fn lazy_distances_match_the_matrix() {
+        let mut rng = SplitMix64(9);
+        let coords: Vec<(f64, f64)> = (0..12).map(|_| (rng.below(9000) as f64 / 100.0, rng.below(9000) as f64 / 100.0)).collect();
+        for (metric, rounding) in [
+            (Metric::Euclidean, Rounding::Nearest),
+            (Metric::Euclidean, Rounding::Ceil),
+            (Metric::Att, Rounding::Ceil),
+            (Metric::Geo, Rounding::Floor),
+        ] {
+            let lazy = CoordinateDistances { coords: coords.clone(), rounding, metric };
+            let matrix = lazy.materialize().unwrap();
+            for (i, j) in (0..12).flat_map(|i| (0..12).map(move |j| (i, j))) {
+                assert_eq!(lazy.dist(i, j), matrix[i][j], "{:?} {} → {}", metric, i, j);
+            }
+            let start: Vec<usize> = (0..12).chain([0]).collect();
+            assert_eq!(two_opt(&lazy, start.clone(), false), two_opt(&matrix, start, false));
+        }
+    }
+
+
+    /// `--skip 1,3` on a 6-city instance solves over cities 0, 2, 4 and 5:
+    /// the optimum equals solving that submatrix by hand, the path comes
+    /// back in original indices, and City0 can't be skipped.
+    #[test]
+    fn skip_solves_the_remaining_cities() {
+        let dist = random_matrix(6, 21);
+        let (reduced, subset) = CitySubset::reduce(&dist, &[1, 3]).unwrap();
+        let kept = [0, 2, 4, 5];
+        let by_hand: Vec<Vec<Weight>> = kept.iter().map(|&i| kept.iter().map(|&j| dist[i][j]).collect()).collect();
+        assert_eq!(reduced, by_hand);
+
+        let (cost, path) = quiet_solver(reduced).solve();
+        assert_eq!(cost, brute_force_optimum(&by_hand, false));
+        let original = subset.to_original(&path);
+        assert_eq!(original.iter().copied().collect::<HashSet<_>>(), kept.into_iter().collect());
+        assert_eq!(tour_cost(&dist, &original), Some(cost));
+        assert_eq!(subset.to_reduced(&original).unwrap(), path);
+        assert!(CitySubset::reduce(&dist, &[0]).is_err());
+        assert!(CitySubset::reduce(&dist, &[6]).is_err());
+    }
+
+
+    /// The complete-graph fast path is taken for a fully finite matrix
+    /// (the diagonal doesn't count) and not once an edge is `INF`, which
+    /// then turns the optimum around.
+    #[test]
+    fn complete_graph_detection() {
+        let mut dist = input1();
+        dist[2][2] = INF;
+        assert!(is_complete(&dist));
+        assert!(quiet_solver(dist.clone()).complete);
+        dist[1][3] = INF;
+        assert!(!is_complete(&dist));
+        let mut solver = quiet_solver(dist.clone());
+        assert!(!solver.complete);
+        let (cost, path) = solver.solve();
+        assert_eq!((cost, tour_cost(&dist, &path)), (80, Some(80)));
+        assert_eq!(path, [0, 2, 3, 1, 0]);
+    }
+
+
+    /// `--at-position X:k` on a 6-city instance: every city at every stop
+    /// has it there, costs no less than the optimum, and matches the best
+    /// such tour found by exhaustive search; the optimum's own stops are
+    /// free.
+    #[test]
+    fn at_position_puts_the_city_at_that_stop() {
+        let dist = random_matrix(6, 13);
+        let mut solver = quiet_solver(dist.clone());
+        let (optimum, best) = solver.solve();
+        for (city, stop) in (1..6).flat_map(|city| (1..6).map(move |stop| (city, stop))) {
+            let (cost, path) = solver.solve_at_position(city, stop).unwrap();
+            assert_eq!(path[stop], city, "{:?}", path);
+            assert_eq!(tour_cost(&dist, &path), Some(cost));
+            assert!(cost >= optimum);
+            let exhaustive = brute_force_by(6, false, &|tour| {
+                if tour[stop] == city { tour_cost(&dist, tour).unwrap_or(INF) } else { INF }
+            });
+            assert_eq!(cost, exhaustive, "City{} at stop {}", city, stop);
+            if best[stop] == city {
+                assert_eq!(cost, optimum);
+            }
+        }
+        assert!(solver.solve_at_position(0, 1).is_err());
+        assert!(solver.solve_at_position(1, 6).is_err());
+    }
+
+
+    /// `--precedence`: City3 before City1 turns input1's optimum around;
+    /// on a 6-city instance the constrained optimum is exhaustive search's
+    /// over the tours that respect the pairs; a cycle is reported and
+    /// leaves the DP without a tour.
+    #[test]
+    fn precedence_orders_the_tour() {
+        let path = temp_path("precedence.txt");
+        fs::write(&path, "# before after\n3 1\n").unwrap();
+        let pairs = load_precedence(&path, 4).unwrap();
+        let _ = fs::remove_file(&path);
+        assert_eq!(pairs, [(3, 1)]);
+        let mut solver = quiet_solver(input1());
+        solver.set_precedence(&pairs);
+        assert_eq!(solver.solve(), (80, vec![0, 2, 3, 1, 0]));
+
+        let dist = random_matrix(6, 17);
+        let pairs = [(4, 1), (2, 3), (5, 2)];
+        let mut solver = quiet_solver(dist.clone());
+        solver.set_precedence(&pairs);
+        let (cost, path) = solver.solve();
+        let stop = |tour: &[usize], city| tour.iter().position(|&c| c == city);
+        assert!(pairs.iter().all(|&(before, after)| stop(&path, before) < stop(&path, after)), "{:?}", path);
+        let exhaustive = brute_force_by(6, false, &|tour| {
+            let ordered = pairs.iter().all(|&(before, after)| stop(tour, before) < stop(tour, after));
+            if ordered { tour_cost(&dist, tour).unwrap_or(INF) } else { INF }
+        });
+        assert_eq!(cost, exhaustive);
+
+        let cycle = [(1, 2), (2, 3), (3, 1)];
+        assert!(has_precedence_cycle(&cycle, 4));
+        assert!(!has_precedence_cycle(&[(1, 2), (2, 3)], 4));
+        let mut solver = quiet_solver(input1());
+        solver.set_precedence(&cycle);
+        assert!(is_infeasible(solver.solve().0));
+    }
+
+
+    /// The DP leaves the bar at most at 95; walking the table back moves it
+    /// on from there.
+    #[test]
+    fn progress_bar_advances_during_reconstruction() {
+        let mut solver = quiet_solver(random_matrix(8, 3));
+        solver.set_progress_bar(ProgressBar::hidden());
+        let min_cost = solver.run_dp();
+        assert!(!is_infeasible(min_cost));
+        let after_dp = solver.progress_bar.as_ref().unwrap().position();
+        assert!(after_dp <= 95, "{}", after_dp);
+        let path = solver.reconstruct_path();
+        let after_reconstruction = solver.progress_bar.as_ref().unwrap().position();
+        assert!(after_reconstruction > after_dp.max(95), "{} -> {}", after_dp, after_reconstruction);
+        assert_eq!(tour_cost(&solver.dist, &path), Some(min_cost));
+    }
+
+
+    /// A topology and a weights file pair up line by line into the mirrored
+    /// matrix; a missing weight names the topology line that lacks one.
+    #[test]
+    fn topology_and_weights_combine_into_a_matrix() {
+        let topology = "3\n0 1\n1 2\n0 2\n";
+        let parse = ParseOptions::default();
+        let dist = parse_split_input(topology, "4\n7\n9\n", &parse).unwrap();
+        assert_eq!(dist, vec![vec![0, 4, 9], vec![4, 0, 7], vec![9, 7, 0]]);
+
+        let dist = parse_split_input(topology, "4\n7\n9\n", &ParseOptions { directed: true, ..ParseOptions::default() }).unwrap();
+        assert_eq!(dist, vec![vec![0, 4, 9], vec![INF, 0, 7], vec![INF, INF, 0]]);
+
+        let err = parse_split_input(topology, "4\n7\n", &parse).unwrap_err();
+        assert!(err.contains("3 edges but weights has 2 values") && err.contains("topology line 4"), "{}", err);
+        let err = parse_split_input(topology, "4\n7\n9\n1\n", &parse).unwrap_err();
+        assert!(err.contains("weights line 4 has no edge"), "{}", err);
+    }
+
+
+    /// On a complete graph layer `k` holds `C(n-1, k-1)·(k-1)` states, all
+    /// finite; with edges removed, the finite counts still sum to the states
+    /// an exhaustive search can finish from.
+    #[test]
+    fn layer_counts_sum_to_the_finite_states() {
+        let binomial = |n: usize, k: usize| (0..k).fold(1, |acc, i| acc * (n - i) / (i + 1));
+        let mut solver = quiet_solver(random_matrix(6, 5));
+        solver.solve();
+        let layers = solver.layer_sizes();
+        assert_eq!(layers[0], (1, 1, 1));
+        for &(visited, stored, finite) in &layers[1..] {
+            assert_eq!(stored, binomial(5, visited - 1) * (visited - 1), "layer {}", visited);
+            assert_eq!(finite, stored, "layer {}", visited);
+        }
+
+        let mut dist = random_matrix(6, 5);
+        for (from, to) in [(0, 1), (2, 3), (3, 2), (4, 0)] {
+            dist[from][to] = INF;
+        }
+        fn can_finish(dist: &[Vec<Weight>], mask: usize, pos: usize) -> bool {
+            let n = dist.len();
+            if mask == (1 << n) - 1 {
+                return dist[pos][0] < INF;
+            }
+            (0..n).any(|city| mask & (1 << city) == 0 && dist[pos][city] < INF && can_finish(dist, mask | (1 << city), city))
+        }
+        let mut solver = quiet_solver(dist.clone());
+        solver.solve();
+        let finishable = solver.dp.entries().filter(|&(mask, pos, _)| can_finish(&dist, mask, pos)).count();
+        let reported: usize = solver.layer_sizes().iter().map(|&(_, _, finite)| finite).sum();
+        assert_eq!(reported, finishable);
+        assert!(reported < solver.dp.len());
+    }
+
+
+    /// Costs group by thousands with `,` unless `--no-sep` turns it off.
+    #[test]
+    fn costs_render_with_thousands_separators() {
+        assert_eq!(WeightFormat::Plain.render_grouped(1_234_567, true), "1,234,567");
+        assert_eq!(WeightFormat::Plain.render_grouped(1_234_567, false), "1234567");
+        assert_eq!(WeightFormat::Plain.render_grouped(-1_234, true), "-1,234");
+        assert_eq!(WeightFormat::Plain.render_grouped(999, true), "999");
+        assert_eq!(group_thousands("1234567.25"), "1,234,567.25");
+        assert_eq!(WeightFormat::Labeled(Unit::Km).render_grouped(1_234_567, true), "1,234,567 km");
+    }
+
+
+    /// `solve_cost` finds the full solve's cost from the same DP fill,
+    /// minimizing and maximizing.
+    #[test]
+    fn solve_cost_matches_the_full_solve() {
+        for seed in 1..=5 {
+            for maximize in [false, true] {
+                let dist = random_matrix(7, seed);
+                let mut full = quiet_solver(dist.clone());
+                full.set_maximize(maximize);
+                let mut cost_only = quiet_solver(dist);
+                cost_only.set_maximize(maximize);
+                assert_eq!(cost_only.solve_cost(), full.solve().0, "seed {}, maximize {}", seed, maximize);
+                assert_eq!(cost_only.computed_states, full.computed_states);
+            }
+        }
+        assert_eq!(quiet_solver(input1()).solve_cost(), 80);
+    }
+
+
+    /// One sample per format is detected from its content alone and parsed
+    /// into the same kind of instance; `--format` overrides the guess.
+    #[test]
+    fn each_format_is_detected() {
+        let matrix = "3\n0 3 4\n3 0 5\n4 5 0\n";
+        let samples = [
+            (matrix, InputFormat::Matrix),
+            // With three edges it would be n + 1 lines, a matrix; the pricier road drops out
+            ("3\n0 1 3\n1 2 5\n0 2 4\n2 0 9\n", InputFormat::EdgeList),
+            ("0,3,4\n3,0,5\n4,5,0\n", InputFormat::Csv),
+            ("NAME: triangle\nTYPE: TSP\nDIMENSION: 3\nEDGE_WEIGHT_TYPE: EUC_2D\nNODE_COORD_SECTION\n1 0 0\n2 3 0\n3 0 4\nEOF\n", InputFormat::Tsplib),
+            ("3\n0 0\n3 0\n0 4\n", InputFormat::Coordinates),
+        ];
+        let loader = InstanceLoader::new(ParseOptions::default());
+        let expected = parse_matrix(matrix, &ParseOptions::default()).unwrap();
+        for (text, format) in samples {
+            let instance = loader.parse(text).unwrap();
+            assert_eq!((instance.format, &instance.distances), (format, &expected), "{}", format.name());
+            assert_eq!(instance.coords.is_some(), matches!(format, InputFormat::Tsplib | InputFormat::Coordinates));
+        }
+
+        // Two coordinate pairs look like a 2×2 matrix until told otherwise
+        let pair = "2\n0 0\n3 4\n";
+        assert_eq!(loader.parse(pair).unwrap().format, InputFormat::Matrix);
+        let mut loader = InstanceLoader::new(ParseOptions::default());
+        loader.set_format(InputFormat::Coordinates);
+        assert_eq!(loader.parse(pair).unwrap().distances, vec![vec![0, 5], vec![5, 0]]);
+    }
+
+
+    /// The Markdown report has the matrix as a table with a header row, the
+    /// tour as a numbered list and the cost in the statistics table.
+    #[test]
+    fn markdown_report_has_the_table_and_tour() {
+        let mut solver = quiet_solver(input1());
+        let (cost, path) = solver.solve();
+        let md = render_markdown(&input1(), cost, &path, Duration::from_millis(1), &solver, WeightFormat::Plain);
+        let lines: Vec<&str> = md.lines().collect();
+        let header = lines.iter().position(|&line| line == "| | City0 | City1 | City2 | City3 |").expect(&md);
+        assert_eq!(lines[header + 1], "|---|---:|---:|---:|---:|");
+        assert_eq!(lines[header + 2], "| **City0** | 0 | 10 | 15 | 20 |");
+        for leg in ["1. City0 → City1 (10)", "2. City1 → City3 (25)", "3. City3 → City2 (30)", "4. City2 → City0 (15)"] {
+            assert!(lines.contains(&leg), "no {:?} in\n{}", leg, md);
+        }
+        assert!(lines.contains(&"| Minimum cost | 80 |"), "{}", md);
+    }
+
+
+    /// Forbidding the 1–3 leg of input1's optimum leaves 0-1-2-3 at 95; a
+    /// directed forbid only closes the one direction.
+    #[test]
+    fn forbidding_the_optimal_leg_costs_more() {
+        let mut dist = input1();
+        forbid_edges(&mut dist, &[(1, 3)], false).unwrap();
+        assert_eq!((dist[1][3], dist[3][1]), (INF, INF));
+        let (cost, path) = quiet_solver(dist.clone()).solve();
+        assert_eq!(cost, 95);
+        assert!(path.windows(2).all(|leg| leg != [1, 3] && leg != [3, 1]), "{:?}", path);
+        assert_eq!(tour_cost(&dist, &path), Some(95));
+
+        let mut dist = input1();
+        forbid_edges(&mut dist, &[(1, 3)], true).unwrap();
+        assert_eq!((dist[1][3], dist[3][1]), (INF, 25));
+        assert_eq!(quiet_solver(dist).solve(), (80, vec![0, 2, 3, 1, 0]));
+
+        assert!(forbid_edges(&mut input1(), &[(2, 2)], false).is_err());
+        assert!(forbid_edges(&mut input1(), &[(0, 4)], false).is_err());
+        assert_eq!(parse_forbidden_edge(" 1:3"), Ok((1, 3)));
+    }
+
+
+    /// 2-opt from the DP's optimal tour has nothing to improve and returns
+    /// it as given; start tours that aren't permutations from City0 are
+    /// refused.
+    #[test]
+    fn optimal_start_tour_is_left_unchanged() {
+        for seed in 1..=4 {
+            let mut solver = quiet_solver(random_matrix(8, seed));
+            let (cost, path) = solver.solve();
+            let open = path[..path.len() - 1].to_vec();
+            assert_eq!(solver.solve_heuristic_from(open).unwrap(), (cost, path.clone()), "seed {}", seed);
+        }
+
+        let solver = quiet_solver(input1());
+        let Ok(StartTour::Given(tour)) = StartTour::parse("0,3,1,2") else { panic!("not a tour") };
+        assert_eq!(solver.solve_heuristic_from(tour).unwrap().0, 80);
+        for (tour, message) in [
+            (vec![1, 0, 2, 3], "begin at City0"),
+            (vec![0, 1, 1, 2], "visited more than once"),
+            (vec![0, 1, 2], "City 3 is missing"),
+            (vec![0, 1, 2, 4], "out of range"),
+        ] {
+            let err = solver.solve_heuristic_from(tour).unwrap_err();
+            assert!(err.contains(message), "{}", err);
+        }
+    }
+
+
+    /// Two and three cities are solved without the table, to the same cost
+    /// and path the DP finds, directed, maximizing or with a missing leg.
+    #[test]
+    fn tiny_instances_in_closed_form() {
+        let mut rng = SplitMix64(42);
+        for n in [2, 3] {
+            for round in 0..40 {
+                let mut dist: Vec<Vec<Weight>> = (0..n)
+                    .map(|i| (0..n).map(|j| if i == j { 0 } else { 1 + (rng.next_u64() % 9) as Weight }).collect())
+                    .collect();
+                if round % 4 == 0 {
+                    dist[0][1] = INF;
+                }
+                let maximize = round % 3 == 0;
+                let mut closed = quiet_solver(dist.clone());
+                closed.set_maximize(maximize);
+                let mut dp = quiet_solver(dist.clone());
+                dp.set_maximize(maximize);
+                dp.set_force_dp(true);
+                let (closed_result, dp_result) = (closed.solve(), dp.solve());
+                assert_eq!(closed.computed_states, 0);
+                if is_infeasible(dp_result.0) {
+                    assert!(is_infeasible(closed_result.0), "{:?}", dist);
+                } else {
+                    assert_eq!(closed_result, dp_result, "{:?}, maximize {}", dist, maximize);
+                }
+            }
+        }
+        let pair = vec![vec![0, 4], vec![6, 0]];
+        assert_eq!(quiet_solver(pair).solve(), (10, vec![0, 1, 0]));
+    }
+
+
+    /// Input1's tour 0-1-3-2-0 has two customer legs: 1→3 saves 10 + 20 - 25
+    /// and 3→2 saves 20 + 15 - 30. The detour is `from → City0 → to`, so on
+    /// a directed matrix only those two depot entries count, and a forbidden
+    /// one leaves the saving unbounded.
+    #[test]
+    fn savings_are_against_the_depot_distances() {
+        let path = [0, 1, 3, 2, 0];
+        assert_eq!(leg_savings(&input1(), &path), [(1, 3, 25, 30, Some(5)), (3, 2, 30, 35, Some(5))]);
+
+        let mut dist = input1();
+        dist[3][0] = INF;
+        dist[0][2] = 40;
+        assert_eq!(leg_savings(&dist, &path), [(1, 3, 25, 30, Some(5)), (3, 2, 30, WideWeight::from(INF), None)]);
+        dist[3][0] = 2;
+        dist[0][3] = 5;
+        assert_eq!(leg_savings(&dist, &path), [(1, 3, 25, 15, Some(-10)), (3, 2, 30, 42, Some(12))]);
+    }
+
+
+    /// Three edges among three cities are `n + 1` lines, so auto-detection
+    /// reads them as a matrix; strict mode refuses to guess and parses them
+    /// as intended once they are declared.
+    #[test]
+    fn strict_format_refuses_the_ambiguous_shape() {
+        let edges = "3\n0 2 7\n1 0 6\n2 1 0\n";
+        let loader = InstanceLoader::new(ParseOptions::default());
+        let guessed = loader.parse(edges).unwrap();
+        assert_eq!(guessed.format, InputFormat::Matrix);
+        assert_eq!(guessed.distances, vec![vec![0, 2, 7], vec![1, 0, 6], vec![2, 1, 0]]);
+
+        let mut strict = InstanceLoader::new(ParseOptions::default());
+        strict.set_strict(true);
+        let Err(err) = strict.parse(edges) else { panic!("strict mode guessed a format") };
+        assert!(err.contains("Strict format"), "{}", err);
+        let declared = strict.parse(&format!("EDGES\n{}", edges)).unwrap();
+        assert_eq!(declared.format, InputFormat::EdgeList);
+        assert_eq!(declared.distances, vec![vec![0, 6, 7], vec![6, 0, 0], vec![7, 0, 0]]);
+        strict.set_format(InputFormat::EdgeList);
+        assert_eq!(strict.parse(edges).unwrap().distances, declared.distances);
+    }
+
+
+    /// `--png` writes a nonempty PNG of a solved coordinate instance.
+    #[cfg(feature = "png")]
+    #[test]
+    fn png_of_the_tour_is_written() {
+        let coords = [(0.0, 0.0), (3.0, 0.0), (3.0, 4.0), (0.0, 4.0)];
+        let path = temp_path("tour.png");
+        render_png(&path, &coords, &[0, 1, 2, 3, 0], "Cost: 14").unwrap();
+        let image = fs::read(&path).unwrap();
+        let _ = fs::remove_file(&path);
+        assert!(image.starts_with(b"\x89PNG\r\n\x1a\n"), "not a PNG ({} bytes)", image.len());
+        assert!(image.len() > 1_000);
+    }
+
+
+    /// The 1-tree bound never passes the DP optimum, and more subgradient
+    /// rounds only tighten it: from the plain 1-tree it gains on some
+    /// instance and reaches the optimum on input1.
+    #[test]
+    fn held_karp_bound_stays_below_the_optimum() {
+        let mut gained = false;
+        for seed in 1..=6 {
+            let mut solver = quiet_solver(random_matrix(8, seed));
+            let optimum = solver.solve().0;
+            let bounds: Vec<Weight> = [1, 10, 100].iter().map(|&rounds| solver.held_karp_bound(rounds)).collect();
+            assert!(bounds.windows(2).all(|pair| pair[0] <= pair[1]), "seed {}: {:?}", seed, bounds);
+            assert!(bounds[2] <= optimum, "seed {}: {:?} above {}", seed, bounds, optimum);
+            gained |= bounds[2] > bounds[0];
+        }
+        assert!(gained);
+        assert_eq!(quiet_solver(input1()).held_karp_bound(100), 80);
+    }
+
+
+    /// A CSV with a corner cell, a header row and an index column parses to
+    /// the bare matrix named by the header; an index column alone names the
+    /// cities too, and a short header is refused.
+    #[test]
+    fn header_row_and_index_column() {
+        let mut loader = InstanceLoader::new(ParseOptions::default());
+        loader.set_headers(true, true);
+        let csv = ",Depot,Mill,Port\nDepot,0,3,4\nMill,3,0,5\nPort,4,5,0\n";
+        let instance = loader.parse(csv).unwrap();
+        assert_eq!(instance.distances, vec![vec![0, 3, 4], vec![3, 0, 5], vec![4, 5, 0]]);
+        assert_eq!(instance.names.unwrap(), ["Depot", "Mill", "Port"]);
+
+        let mut loader = InstanceLoader::new(ParseOptions::default());
+        loader.set_headers(false, true);
+        let instance = loader.parse("3\nA 0 3 4\nB 3 0 5\nC 4 5 0\n").unwrap();
+        assert_eq!((instance.format, instance.distances.len()), (InputFormat::Matrix, 3));
+        assert_eq!(instance.names.unwrap(), ["A", "B", "C"]);
+
+        let mut loader = InstanceLoader::new(ParseOptions::default());
+        loader.set_headers(true, false);
+        let Err(err) = loader.parse("A,B\n0,3,4\n3,0,5\n4,5,0\n") else { panic!("accepted a short header") };
+        assert!(err.contains("Header has 2 names but there are 3 rows"), "{}", err);
+    }
+
+
+    /// `verify_path` accepts every normal solve, including directed ones, and
+    /// catches a tour that differs from the optimum only in cost: a swapped
+    /// stop, the backwards drive of a one-way optimum, a broken precedence.
+    #[test]
+    fn self_check_catches_a_corrupted_path() {
+        for seed in 1..=5 {
+            let mut dist = random_matrix(7, seed);
+            dist[1][2] += 50;
+            let mut solver = quiet_solver(dist.clone());
+            let (cost, path) = solver.solve();
+            solver.verify_path(cost, &path).unwrap();
+
+            let backwards: Vec<usize> = path.iter().rev().copied().collect();
+            if tour_cost(&dist, &backwards) != Some(cost) {
+                let err = solver.verify_path(cost, &backwards).unwrap_err();
+                assert!(err.contains(&format!("but the DP optimum is {}", cost)), "{}", err);
+            }
+        }
+
+        let mut solver = quiet_solver(input1());
+        let (cost, _) = solver.solve();
+        let err = solver.verify_path(cost, &[0, 1, 2, 3, 0]).unwrap_err();
+        assert!(err.contains("costs 95 but the DP optimum is 80"), "{}", err);
+        solver.set_precedence(&[(3, 1)]);
+        let err = solver.verify_path(cost, &[0, 1, 3, 2, 0]).unwrap_err();
+        assert!(err.contains("breaks a constraint"), "{}", err);
+    }
+
+
+    /// `--delimiter ';'` reads a European CSV, comma decimals included once
+    /// `--rounding` says how to round them; a row that falls back to commas
+    /// is refused.
+    #[test]
+    fn semicolon_delimited_matrix() {
+        let parse = ParseOptions { delimiter: Some(';'), ..ParseOptions::default() };
+        let csv = "0;10;15;20\n10;0;35;25\n15;35;0;30\n20;25;30;0\n";
+        assert_eq!(parse_csv(csv, &parse).unwrap(), input1());
+        let loader = InstanceLoader::new(parse);
+        assert_eq!(loader.parse(csv).unwrap().format, InputFormat::Csv);
+
+        let decimals = "0;2,4;3,6\n2,4;0;5\n3,6;5;0\n";
+        let err = parse_csv(decimals, &parse).unwrap_err();
+        assert!(err.starts_with("Line 1:"), "{}", err);
+        let rounded = ParseOptions { rounding: Some(Rounding::Nearest), ..parse };
+        assert_eq!(parse_csv(decimals, &rounded).unwrap(), vec![vec![0, 2, 4], vec![2, 0, 5], vec![4, 5, 0]]);
+
+        let err = parse_csv("0;10;15\n10,0,35\n15;35;0\n", &parse).unwrap_err();
+        assert!(err.contains("Line 2: No ';' between the values"), "{}", err);
+    }
+
+
+    /// Bearings are clockwise from north (`+y`): axis-aligned legs land
+    /// exactly on E, N, W and S, diagonals on the intercardinal points.
+    #[test]
+    fn bearings_of_axis_aligned_legs() {
+        for (to, degrees, point) in [
+            ((5.0, 0.0), 90.0, "E"),
+            ((0.0, 5.0), 0.0, "N"),
+            ((-5.0, 0.0), 270.0, "W"),
+            ((0.0, -5.0), 180.0, "S"),
+            ((3.0, 3.0), 45.0, "NE"),
+            ((-3.0, -3.0), 225.0, "SW"),
+        ] {
+            let bearing = compass_bearing((0.0, 0.0), to);
+            assert!((bearing - degrees).abs() < 1e-9, "{:?}: {}", to, bearing);
+            assert_eq!(compass_point(bearing), point);
+        }
+        assert_eq!(compass_point(359.0), "N");
+        assert_eq!((compass_point(112.0), compass_point(113.0)), ("E", "SE"));
+    }
+
+
+    /// Negative weights (profits) still give the exhaustive optimum, and the
+    /// cheapest edges all meeting a forbidden one can't pull a tour over it.
+    #[test]
+    fn negative_edges_keep_forbidden_ones_forbidden() {
+        let mut rng = SplitMix64(7);
+        for _ in 0..10 {
+            let n = 6;
+            let mut dist: Vec<Vec<Weight>> = (0..n)
+                .map(|i| (0..n).map(|j| if i == j { 0 } else { (rng.next_u64() % 100) as Weight - 60 }).collect())
+                .collect();
+            // A profit of 1000 on each side of the 2 → 3 leg, which is closed
+            dist[1][2] = -1_000;
+            dist[3][4] = -1_000;
+            dist[2][3] = INF;
+            let (cost, path) = quiet_solver(dist.clone()).solve();
+            assert!(path.windows(2).all(|leg| leg != [2, 3]), "{:?}", path);
+            assert_eq!(tour_cost(&dist, &path), Some(cost));
+            assert_eq!(cost, brute_force_by(n, false, &|tour| tour_cost(&dist, tour).unwrap_or(INF)));
+        }
+    }
+
+
+    /// Asking about each leg of input1's tour in turn: the first stop weighs
+    /// City1 and City2 at 80 each and City3 at 95, and every answer marks the
+    /// city the tour went on to.
+    #[test]
+    fn explain_leg_lists_the_alternatives() {
+        let mut solver = quiet_solver(input1());
+        let (_, path) = solver.solve();
+        let lines = render_leg_explanation(&solver, &path, 1, None, WeightFormat::Plain);
+        assert!(lines[0].contains("Leg 1: City0 → City1, distance 10"), "{}", lines[0]);
+        let options = &lines[2..];
+        assert_eq!(options.len(), 3, "{:?}", options);
+        for (line, (city, sum)) in options.iter().zip([("City1", "10 + 70 = "), ("City2", "15 + 65 = "), ("City3", "20 + 75 = ")]) {
+            assert!(line.contains(city) && line.contains(sum), "{}", line);
+        }
+        assert!(options[0].contains("chosen") && !options[1].contains("chosen"));
+
+        for leg in 2..path.len() {
+            let lines = render_leg_explanation(&solver, &path, leg, None, WeightFormat::Plain);
+            let chosen: Vec<&String> = lines.iter().filter(|line| line.contains("chosen")).collect();
+            assert_eq!(chosen.len(), 1, "leg {}: {:?}", leg, lines);
+            assert!(chosen[0].contains(&format!("City{}", path[leg])), "{}", chosen[0]);
+        }
+    }
+
+
+    /// Fifty thousand generated points stream into a vector sized exactly
+    /// once from the count line, the same points `parse_coordinates` finds;
+    /// a short file is refused.
+    #[test]
+    fn large_coordinate_file_streams() {
+        let n = 50_000;
+        let mut text = String::from("COORDS\n# generated\n");
+        text += &format!("{}\n", n);
+        for i in 0..n {
+            text += &format!("{} {}.5\n", i % 1000, i / 1000);
+        }
+        let path = temp_path("stream.txt");
+        fs::write(&path, &text).unwrap();
+        let streamed = stream_coordinates(&path, &ParseOptions::default());
+        let _ = fs::remove_file(&path);
+        let streamed = streamed.unwrap();
+        assert_eq!((streamed.coords.len(), streamed.coords.capacity()), (n, n));
+        assert_eq!((streamed.coords[0], streamed.coords[n - 1]), ((0.0, 0.5), (999.0, 49.5)));
+        let whole = parse_coordinates(&text.replacen("COORDS\n", "", 1), &ParseOptions::default()).unwrap();
+        assert!(whole.coords == streamed.coords);
+
+        fs::write(&path, "3\n0 0\n1 1\n").unwrap();
+        let short = stream_coordinates(&path, &ParseOptions::default());
+        let _ = fs::remove_file(&path);
+        assert_eq!(short.err().as_deref(), Some("Expected 3 coordinate lines, found 2"));
+    }
+
+
+    /// Three cities reach five states: the start, two after one move and two
+    /// full ones. The edge list has a line per chosen move, the DOT file a
+    /// node per state, and a forbidden edge drops what only it reached.
+    #[test]
+    fn state_graph_of_three_cities() {
+        let dist = vec![vec![0, 1, 2], vec![1, 0, 3], vec![2, 3, 0]];
+        let mut solver = quiet_solver(dist.clone());
+        solver.set_force_dp(true);
+        assert_eq!(solver.solve().0, 6);
+        let nodes = solver.state_graph();
+        let states: Vec<(usize, usize)> = nodes.iter().map(|node| (node.mask, node.pos)).collect();
+        assert_eq!(states, [(0b001, 0), (0b011, 1), (0b101, 2), (0b111, 2), (0b111, 1)]);
+        assert!(nodes.iter().all(|node| node.next.is_some()));
+
+        let edges = temp_path("states.txt");
+        let dot = temp_path("states.dot");
+        write_state_graph(&edges, &nodes, 3, WeightFormat::Plain).unwrap();
+        write_state_graph(&dot, &nodes, 3, WeightFormat::Plain).unwrap();
+        let (edges_text, dot_text) = (fs::read_to_string(&edges).unwrap(), fs::read_to_string(&dot).unwrap());
+        let _ = (fs::remove_file(&edges), fs::remove_file(&dot));
+        assert_eq!(content_lines(&edges_text).len(), 5, "{}", edges_text);
+        assert_eq!(dot_text.matches("[label=\"").count() - dot_text.matches(" -> ").count(), 5, "{}", dot_text);
+
+        let mut dist = dist;
+        dist[0][2] = INF;
+        let mut solver = quiet_solver(dist);
+        solver.set_force_dp(true);
+        solver.solve();
+        assert_eq!(solver.state_graph().len(), 3);
+    }
+
+
+    /// The assignment bound is the cheapest derangement (successor for every
+    /// city, subtours allowed), so it equals one found exhaustively and never
+    /// exceeds the optimum, one-way instances included.
+    #[test]
+    fn assignment_bound_stays_below_the_optimum() {
+        fn cheapest_derangement(dist: &[Vec<Weight>], city: usize, used: usize) -> Weight {
+            if city == dist.len() {
+                return 0;
+            }
+            (0..dist.len())
+                .filter(|&to| to != city && used & (1 << to) == 0 && dist[city][to] < INF)
+                .map(|to| dist[city][to].saturating_add(cheapest_derangement(dist, city + 1, used | (1 << to))))
+                .min()
+                .unwrap_or(INF)
+        }
+        for seed in 1..=8 {
+            let mut dist = random_matrix(6, seed);
+            if seed % 2 == 0 {
+                dist[2][4] += 40;
+                dist[5][1] = INF;
+            }
+            let bound = assignment_lower_bound(&dist, 6);
+            assert_eq!(bound, cheapest_derangement(&dist, 0, 0), "seed {}", seed);
+            assert!(bound <= quiet_solver(dist).solve().0, "seed {}", seed);
+        }
+
+        // Two cheap pairs: the assignment takes two 2-cycles, a tour can't
+        let pairs = vec![vec![0, 1, 10, 10], vec![1, 0, 10, 10], vec![10, 10, 0, 1], vec![10, 10, 1, 0]];
+        assert_eq!((assignment_lower_bound(&pairs, 4), quiet_solver(pairs).solve().0), (4, 22));
+    }
+
+
+    /// Trailing `# name` comments are stripped before the numbers are read,
+    /// and when every row has one they name the cities; a single annotated
+    /// row is just a note.
+    #[test]
+    fn row_comments_become_city_names() {
+        let loader = InstanceLoader::new(ParseOptions::default());
+        let named = "4\n0 10 15 20 # Depot\n10 0 35 25  #Mill \n15 35 0 30 # North Port\n20 25 30 0 # Quarry\n";
+        let instance = loader.parse(named).unwrap();
+        assert_eq!(instance.distances, input1());
+        assert_eq!(instance.names.unwrap(), ["Depot", "Mill", "North Port", "Quarry"]);
+
+        let annotated = "4\n0 10 15 20\n10 0 35 25 # closed on Sundays\n15 35 0 30\n20 25 30 0\n";
+        let instance = loader.parse(annotated).unwrap();
+        assert_eq!(instance.distances, input1());
+        assert_eq!(instance.names, None);
+    }
+
+
+    /// On a matrix where every city is equally near, the default tie-break
+    /// always takes the lowest index; seeded random ties give the same tour
+    /// for the same seed and other tours for other seeds.
+    #[test]
+    fn nearest_neighbor_ties_are_reproducible() {
+        let n = 7;
+        let flat: Vec<Vec<Weight>> = (0..n).map(|i| (0..n).map(|j| if i == j { 0 } else { 5 }).collect()).collect();
+        for _ in 0..3 {
+            assert_eq!(nearest_neighbor_tour(&flat[..], n, false, None), [0, 1, 2, 3, 4, 5, 6, 0]);
+        }
+        let mut dist = random_matrix(9, 4);
+        dist[0][5] = 1;
+        dist[0][7] = 1;
+        let first = nearest_neighbor_tour(&dist[..], 9, false, None);
+        assert_eq!(first[1], 5);
+        assert_eq!(nearest_neighbor_tour(&dist[..], 9, false, None), first);
+
+        let seeded = |seed| nearest_neighbor_tour(&flat[..], n, false, Some(&mut SplitMix64(seed)));
+        assert_eq!(seeded(11), seeded(11));
+        assert!((0..5).any(|seed| seeded(seed) != [0, 1, 2, 3, 4, 5, 6, 0]));
+        let mut sorted = seeded(3);
+        assert_eq!(sorted.pop(), Some(0));
+        sorted.sort_unstable();
+        assert_eq!(sorted, [0, 1, 2, 3, 4, 5, 6]);
+    }
+
+
+    /// `--timing-histogram` buckets: one per number of visited cities the
+    /// fill loops over (1 to n-1), none without `set_layer_timing`, and
+    /// together no longer than the whole fill.
+    #[test]
+    fn timing_histogram_has_a_bucket_per_layer() {
+        let mut solver = quiet_solver(random_matrix(9, 2));
+        assert_eq!(solver.layer_timings(), None);
+        solver.set_layer_timing(true);
+        solver.solve();
+        let layers = solver.layer_timings().unwrap();
+        let visited: Vec<usize> = layers.iter().map(|&(visited, _)| visited).collect();
+        assert_eq!(visited, (1..9).collect::<Vec<_>>());
+        let total: Duration = layers.iter().map(|&(_, time)| time).sum();
+        assert!(total > Duration::ZERO && total <= solver.dp_time, "{:?} of {:?}", total, solver.dp_time);
+    }
+
+
+    /// `--max-leg`: input1 needs a 30 leg, so a cap of 29 leaves City2 with
+    /// a single road and no tour, while 30 keeps the optimum. On random
+    /// instances the capped optimum is the best tour without a longer leg.
+    #[test]
+    fn max_leg_caps_every_leg() {
+        let mut dist = input1();
+        assert_eq!(cap_leg_length(&mut dist, 29), 4);
+        assert!(is_infeasible(quiet_solver(dist).solve().0));
+        let mut dist = input1();
+        assert_eq!(cap_leg_length(&mut dist, 30), 2);
+        assert_eq!(quiet_solver(dist).solve(), (80, vec![0, 1, 3, 2, 0]));
+
+        for seed in 1..=5 {
+            let original = random_matrix(7, seed);
+            let mut dist = original.clone();
+            cap_leg_length(&mut dist, 60);
+            let (cost, path) = quiet_solver(dist).solve();
+            let capped = |tour: &[usize]| {
+                let short = tour.windows(2).all(|leg| original[leg[0]][leg[1]] <= 60);
+                if short { tour_cost(&original, tour).unwrap_or(INF) } else { INF }
+            };
+            assert_eq!(cost, brute_force_by(7, false, &capped), "seed {}", seed);
+            if !is_infeasible(cost) {
+                assert!(path.windows(2).all(|leg| original[leg[0]][leg[1]] <= 60), "{:?}", path);
+            }
+        }
+    }
+
+
+    /// 0-1-2-3-4 against 0-2-1-3-4: each tour has two edges the other lacks,
+    /// the three shared ones drop out, and an undirected edge matches either
+    /// way round while a directed one doesn't.
+    #[test]
+    fn tour_diff_is_the_symmetric_difference() {
+        let ours = [0, 1, 2, 3, 4, 0];
+        let theirs = [0, 2, 1, 3, 4, 0];
+        assert_eq!(edges_missing_from(&ours, &theirs, false), [(0, 1), (2, 3)]);
+        assert_eq!(edges_missing_from(&theirs, &ours, false), [(0, 2), (1, 3)]);
+        assert_eq!(edges_missing_from(&ours, &ours, false), []);
+
+        let reversed = [0, 4, 3, 2, 1, 0];
+        assert_eq!(edges_missing_from(&ours, &reversed, false), []);
+        assert_eq!(edges_missing_from(&ours, &reversed, true).len(), 5);
+    }
+
+
+    /// `--unit` labels every rendered cost; `--convert` rounds km to the
+    /// nearest mile (and back) and leaves forbidden edges alone.
+    #[test]
+    fn unit_labels_and_km_to_mi_rounding() {
+        let km = WeightFormat::Labeled(Unit::parse("km").unwrap());
+        assert_eq!(km.render(1234), "1234 km");
+        assert_eq!(km.render_grouped(1234, true), "1,234 km");
+        assert_eq!(WeightFormat::Labeled(Unit::Min).render(5), "5 min");
+        assert!(Unit::parse("furlongs").is_err());
+
+        let mut dist = vec![vec![0, 100, 80], vec![1, 0, INF], vec![161, 2, 0]];
+        convert_distance_units(&mut dist, Unit::Mi);
+        assert_eq!(dist, vec![vec![0, 62, 50], vec![1, 0, INF], vec![100, 1, 0]]);
+        convert_distance_units(&mut dist, Unit::Km);
+        assert_eq!(dist, vec![vec![0, 100, 80], vec![2, 0, INF], vec![161, 2, 0]]);
+    }
+
+
+    /// City4 is 60 from everywhere, so fitting it into input1's tour costs
+    /// 90 more (in place of the 30 leg). A skip penalty of 100 makes visiting
+    /// it cheaper; at 50 the tour leaves it out and pays the penalty.
+    #[test]
+    fn skip_penalty_decides_optional_cities() {
+        let mut dist = input1();
+        for row in &mut dist {
+            row.push(60);
+        }
+        dist.push(vec![60, 60, 60, 60, 0]);
+
+        let mut solver = quiet_solver(dist.clone());
+        solver.set_optional(&[4], 100);
+        let (cost, path) = solver.solve();
+        assert_eq!(cost, 170);
+        assert!(path.contains(&4), "{:?}", path);
+        solver.verify_path(cost, &path).unwrap();
+
+        let mut solver = quiet_solver(dist);
+        solver.set_optional(&[4], 50);
+        let (cost, path) = solver.solve();
+        assert_eq!((cost, path.as_slice()), (130, &[0, 1, 3, 2, 0][..]));
+        assert_eq!(solver.skip_penalty(&path), 50);
+        solver.verify_path(cost, &path).unwrap();
+    }
+
+
+    /// ulysses16 (`GEO`) reproduces the first row of its published matrix
+    /// and the known optimum 6859; the first att48 cities (`ATT`) give the
+    /// pseudo-Euclidean distances, rounded up where `nint` falls short.
+    #[test]
+    fn tsplib_geo_and_att_distances() {
+        let loader = InstanceLoader::new(ParseOptions::default());
+        let ulysses = loader.load_file("test/input/ulysses16.tsp").unwrap().distances;
+        assert_eq!(ulysses[0], [0, 509, 501, 312, 1019, 736, 656, 60, 1039, 726, 2314, 479, 448, 479, 619, 150]);
+        assert_eq!(quiet_solver(ulysses).solve_cost(), 6859);
+
+        let att = loader.load_file("test/input/att5.tsp").unwrap().distances;
+        assert_eq!(att[0], [0, 1495, 381, 2012, 1157]);
+        assert_eq!(att[1], [1495, 0, 1135, 637, 583]);
+    }
+
+
+    /// The GeoJSON tour is a closed `LineString` of `n + 1` positions in tour
+    /// order, followed by a `Point` per city; `GEO` input comes out as
+    /// `[longitude, latitude]` in decimal degrees.
+    #[test]
+    fn geojson_tour_is_a_closed_line_string() {
+        let coords = [(0.0, 0.0), (4.0, 0.0), (4.0, 3.0), (0.0, 3.0)];
+        let names: Vec<String> = ["Depot", "A", "B", "C"].iter().map(|name| name.to_string()).collect();
+        let text = render_geojson(&coords, Metric::Euclidean, &[0, 1, 2, 3, 0], Some(&names));
+        let collection: serde_json::Value = serde_json::from_str(&text).unwrap();
+        let features = collection["features"].as_array().unwrap();
+        assert_eq!(features.len(), 5);
+        assert_eq!(features[0]["geometry"]["type"], "LineString");
+        let line = features[0]["geometry"]["coordinates"].as_array().unwrap();
+        assert_eq!(line.len(), 5);
+        assert_eq!((&line[0], &line[2]), (&serde_json::json!([0.0, 0.0]), &serde_json::json!([4.0, 3.0])));
+        assert_eq!(line.first(), line.last());
+        for (city, point) in features[1..].iter().enumerate() {
+            assert_eq!(point["geometry"]["type"], "Point");
+            assert_eq!(point["properties"]["index"], city);
+            assert_eq!(point["properties"]["name"], names[city].as_str());
+        }
+
+        let geo = render_geojson(&[(38.30, 20.45), (39.0, 26.15)], Metric::Geo, &[0, 1, 0], None);
+        let geo: serde_json::Value = serde_json::from_str(&geo).unwrap();
+        assert_eq!(geo["features"][0]["geometry"]["coordinates"][0], serde_json::json!([20.75, 38.5]));
+    }
+
+
+    /// Input1's only tie is the first stop, where City1 and City2 start the
+    /// optimal tour and its reverse; on a flat matrix every stop but the last
+    /// two is a tie among all the cities left, and the path is unchanged.
+    #[test]
+    fn tie_points_are_reported() {
+        let mut solver = quiet_solver(input1());
+        let (_, path) = solver.solve();
+        assert_eq!(solver.tie_points(&path), [(0, vec![1, 2])]);
+
+        let mut dist = input1();
+        dist[2][0] = 16;
+        let mut solver = quiet_solver(dist);
+        let (_, path) = solver.solve();
+        assert_eq!(solver.tie_points(&path), []);
+
+        let flat: Vec<Vec<Weight>> = (0..5).map(|i| (0..5).map(|j| if i == j { 0 } else { 7 }).collect()).collect();
+        let mut solver = quiet_solver(flat);
+        let (_, path) = solver.solve();
+        assert_eq!(path, [0, 1, 2, 3, 4, 0]);
+        assert_eq!(solver.tie_points(&path), [(0, vec![1, 2, 3, 4]), (1, vec![2, 3, 4]), (2, vec![3, 4])]);
+    }
+
+
+    /// The footer follows the mode: the plain closed tour, then maximizing,
+    /// optional cities and precedence each change the objective and the
+    /// recurrence it prints.
+    #[test]
+    fn footer_names_the_solved_mode() {
+        let footer = |configure: &dyn Fn(&mut TSPSolver)| {
+            let mut solver = quiet_solver(input1());
+            configure(&mut solver);
+            solver.recurrence(WeightFormat::Plain)
+        };
+        let (objective, recurrence) = footer(&|_| {});
+        assert_eq!(objective, "Shortest closed tour from City0 through every city");
+        assert_eq!(recurrence, "C(S, i) = min over j ∉ S of d(i, j) + C(S ∪ {j}, j); C(V, i) = d(i, 0)");
+
+        let (objective, recurrence) = footer(&|solver| solver.set_maximize(true));
+        assert!(objective.starts_with("Longest closed tour") && recurrence.starts_with("C(S, i) = max over"), "{}", recurrence);
+
+        let (objective, recurrence) = footer(&|solver| solver.set_optional(&[3], 40));
+        assert!(objective.contains("plus 40 per skipped optional city"), "{}", objective);
+        assert!(recurrence.ends_with("once S holds every required city"), "{}", recurrence);
+
+        let (objective, recurrence) = footer(&|solver| solver.set_precedence(&[(3, 1)]));
+        assert!(objective.ends_with("respecting the precedence pairs"), "{}", objective);
+        assert!(recurrence.contains("j ∉ S whose predecessors are all in S"), "{}", recurrence);
+    }
+
+
+    /// With `--reflow` input1's rows may wrap anywhere, blank lines included;
+    /// one value short or over is refused with the count.
+    #[test]
+    fn reflowed_matrix_parses() {
+        let reflow = ParseOptions { reflow: true, ..ParseOptions::default() };
+        let loader = InstanceLoader::new(reflow);
+        assert_eq!(loader.load_file("test/input/reflowed.txt").unwrap().distances, input1());
+        let spread = "4\n0 10\n\n15 20 10\n0 35 25 15 35\n\n0 30 20\n25 30 0\n";
+        assert_eq!(parse_matrix(spread, &reflow).unwrap(), input1());
+
+        let err = parse_matrix("4\n0 10 15 20 10 0 35 25\n15 35 0 30 20 25 30\n", &reflow).unwrap_err();
+        assert!(err.contains("Expected 4 × 4 = 16 values, found 15"), "{}", err);
+        let err = parse_matrix("2\n0 1\n1 0 9\n", &reflow).unwrap_err();
+        assert!(err.contains("found 5"), "{}", err);
+        assert!(parse_matrix(spread, &ParseOptions::default()).is_err());
+    }
+
+
+    /// On a one-way instance the DP optimum rotated to each start is that
+    /// start's own exhaustive optimum: every start lists the same cost, which
+    /// is therefore the minimum the table stars.
+    #[test]
+    fn all_starts_on_an_asymmetric_instance() {
+        let mut dist = random_matrix(6, 9);
+        dist[0][3] += 40;
+        dist[4][2] += 25;
+        dist[5][1] = INF;
+        let (cost, path) = quiet_solver(dist.clone()).solve();
+        let tours = all_start_tours(&path, &dist);
+        assert_eq!(tours.iter().map(|(start, ..)| *start).collect::<Vec<_>>(), [0, 1, 2, 3, 4, 5]);
+        for (start, start_cost, tour) in &tours {
+            assert_eq!((tour[0], tour[tour.len() - 1]), (*start, *start));
+            // Relabel so the start is City0, then search every tour from it
+            let label = |city: usize| if city == 0 { *start } else if city == *start { 0 } else { city };
+            let relabeled: Vec<Vec<Weight>> = (0..6).map(|i| (0..6).map(|j| dist[label(i)][label(j)]).collect()).collect();
+            let exhaustive = brute_force_by(6, false, &|tour| tour_cost(&relabeled, tour).unwrap_or(INF));
+            assert_eq!((*start_cost, exhaustive), (cost, cost), "start {}", start);
+        }
+        assert_eq!(tours.iter().map(|&(_, cost, _)| cost).min(), Some(cost));
+    }
+}
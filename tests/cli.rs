@@ -0,0 +1,302 @@
+//! End-to-end runs of the `tsp_solver` binary.
+
+use std::io::Write;
+use std::process::{Command, Output, Stdio};
+
+const INPUT1: &str = "test/input/input1.txt";
+
+fn tsp_solver() -> Command {
+    Command::new(env!("CARGO_BIN_EXE_tsp_solver"))
+}
+
+/// Runs the binary with `args`, feeding `stdin` to it.
+fn run_with_stdin(args: &[&str], stdin: &str) -> Output {
+    let mut child = tsp_solver()
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("the binary didn't start");
+    child.stdin.take().unwrap().write_all(stdin.as_bytes()).unwrap();
+    child.wait_with_output().unwrap()
+}
+
+/// `--headless` from a file and from stdin: stdout is exactly one JSON
+/// record (cost 80) with nothing on stderr.
+#[test]
+fn headless_prints_one_json_record() {
+    let matrix = std::fs::read_to_string(INPUT1).unwrap();
+    for output in [run_with_stdin(&["--headless", INPUT1], ""), run_with_stdin(&["--headless"], &matrix)] {
+        assert!(output.status.success());
+        assert_eq!(String::from_utf8_lossy(&output.stderr), "");
+        let record: serde_json::Value = serde_json::from_slice(&output.stdout).expect("more than the JSON record");
+        assert_eq!(record["cost"], 80);
+    }
+}
+
+/// A matrix that is `INF` off the diagonal is refused with the "forgot to
+/// fill" message.
+#[test]
+fn all_inf_matrix_is_refused() {
+    let output = tsp_solver()
+        .args(["--quiet", "--b64", "MwowIElORiBJTkYKSU5GIDAgSU5GCklORiBJTkYgMAo="])
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    assert!(String::from_utf8_lossy(&output.stderr).contains("forget to fill the matrix"), "{}", String::from_utf8_lossy(&output.stderr));
+}
+
+/// `--repeat 3` prints the solution once and min/median/mean/stddev over
+/// three timed runs, which all found the same tour.
+#[test]
+fn repeat_reports_three_samples() {
+    let output = tsp_solver().args(["--quiet", "--repeat", "3", INPUT1]).stdin(Stdio::null()).output().unwrap();
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert_eq!(stdout.matches("Minimum Cost: 80").count(), 1, "{}", stdout);
+    assert!(stdout.contains("Timing over 3 runs"), "{}", stdout);
+    for statistic in ["Min:", "Median:", "Mean:", "Stddev:"] {
+        assert!(stdout.lines().any(|line| line.trim_start().starts_with(statistic)), "no {} in {}", statistic, stdout);
+    }
+}
+
+/// No file and no terminal: a usage hint instead of a prompt error, and a
+/// nonzero exit.
+#[test]
+fn no_terminal_explains_the_usage() {
+    let output = tsp_solver().arg("--quiet").stdin(Stdio::null()).output().unwrap();
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("No terminal for interactive input"), "{}", stderr);
+    assert!(stderr.contains("tsp_solver <file>"), "{}", stderr);
+}
+
+/// Each `--safe` cap turns an instance away that the same run without
+/// `--safe` solves.
+#[test]
+fn safe_mode_rejects_what_it_caps() {
+    let heavy = std::env::temp_dir().join(format!("tsp_cli_{}_heavy.txt", std::process::id()));
+    std::fs::write(&heavy, "3\n0 2000000 5\n2000000 0 7\n5 7 0\n").unwrap();
+    let heavy = heavy.to_str().unwrap();
+    let cases: [(&[&str], &str, &str); 3] = [
+        (&[], heavy, "weight 2000000 from City0 to City1 is outside ±1000000"),
+        (&["--safe-max-cities", "3"], INPUT1, "4 cities, the limit is 3"),
+        (&["--safe-max-file-size", "10"], INPUT1, "the limit is 10"),
+    ];
+    for (caps, file, message) in cases {
+        let output = tsp_solver().args(["--quiet", "--safe"]).args(caps).arg(file).stdin(Stdio::null()).output().unwrap();
+        assert!(!output.status.success(), "{:?} accepted {}", caps, file);
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(stderr.contains(message), "{}", stderr);
+
+        let output = tsp_solver().args(["--quiet", file]).stdin(Stdio::null()).output().unwrap();
+        assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    }
+    let _ = std::fs::remove_file(heavy);
+}
+
+/// `--trace-states` lists every base case before any composite state, and
+/// each state only after the state it continues into.
+#[test]
+fn trace_lists_base_cases_first() {
+    let output = tsp_solver().args(["--quiet", "--trace-states", INPUT1]).stdin(Stdio::null()).output().unwrap();
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let trace: Vec<(&str, usize, usize, usize)> = stdout
+        .lines()
+        .filter_map(|line| {
+            let words: Vec<&str> = line.split_whitespace().collect();
+            let ["mask", mask, "at", pos, _, "via", via] = words.get(1..)? else { return None };
+            let city = |text: &str| text.trim_start_matches("City").trim_end_matches(':').parse::<usize>().unwrap();
+            Some((words[0], usize::from_str_radix(mask, 2).unwrap(), city(pos), city(via)))
+        })
+        .collect();
+    assert_eq!(trace.len(), 13, "{}", stdout);
+
+    let first_state = trace.iter().position(|&(kind, ..)| kind == "state").unwrap();
+    assert_eq!(first_state, 3);
+    assert!(trace[first_state..].iter().all(|&(kind, ..)| kind == "state"), "{}", stdout);
+    for (i, &(kind, mask, _, via)) in trace.iter().enumerate() {
+        if kind == "state" {
+            let continued = trace[..i].iter().any(|&(_, earlier, pos, _)| earlier == mask | 1 << via && pos == via);
+            assert!(continued, "mask {:b} listed before the state it goes on to", mask);
+        }
+    }
+}
+
+/// Forbidding both of City0's cheap roads leaves no tour, and the warning
+/// says the forbids may be why.
+#[test]
+fn forbidding_every_tour_is_reported() {
+    let output = tsp_solver().args(["--quiet", "--forbid", "0:1", "--forbid", "0:2", INPUT1]).stdin(Stdio::null()).output().unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("No valid tour found"), "{}", stdout);
+    assert!(stdout.contains("2 edge(s) were forbidden with --forbid"), "{}", stdout);
+}
+
+/// `--dir` over two instances (and a file it should skip) writes one CSV row
+/// and one solution file per instance.
+#[test]
+fn directory_mode_writes_a_row_per_instance() {
+    let dir = std::env::temp_dir().join(format!("tsp_cli_{}_dir", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::copy(INPUT1, dir.join("a.txt")).unwrap();
+    std::fs::write(dir.join("b.txt"), "3\n0 1 2\n1 0 3\n2 3 0\n").unwrap();
+    std::fs::write(dir.join("notes.md"), "not an instance\n").unwrap();
+
+    let output = tsp_solver().args(["--quiet", "--jobs", "2", "--dir"]).arg(&dir).stdin(Stdio::null()).output().unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let csv = std::fs::read_to_string(dir.join("results/results.csv")).unwrap();
+    let rows: Vec<Vec<&str>> = csv.lines().skip(1).map(|line| line.split(',').collect()).collect();
+    assert_eq!(rows.len(), 2, "{}", csv);
+    assert_eq!(rows[0][..4], ["a.txt", "4", "ok", "80"]);
+    assert_eq!(rows[1][..4], ["b.txt", "3", "ok", "6"]);
+    let solution = std::fs::read_to_string(dir.join("results/a.txt.solution.txt")).unwrap();
+    assert!(solution.contains("cost: 80"), "{}", solution);
+    assert!(dir.join("results/b.txt.solution.txt").exists());
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+/// `--self-check` on a normal instance says it passed and changes nothing
+/// else about the run.
+#[test]
+fn self_check_passes_on_input1() {
+    let output = tsp_solver().args(["--quiet", "--self-check", INPUT1]).stdin(Stdio::null()).output().unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("Self-check passed"), "{}", stdout);
+    assert!(stdout.contains("Minimum Cost: 80"), "{}", stdout);
+}
+
+/// `--mod M` reports `80 % M` for input1 (and its two optimal directions
+/// mod `M` with `--count-tours`), whatever the size of `M`.
+#[test]
+fn mod_reports_the_reduced_cost() {
+    for (modulus, reduced, tours) in [("7", 3, 2), ("2", 0, 0), ("80", 0, 2), ("1000000007", 80, 2)] {
+        let output = tsp_solver()
+            .args(["--quiet", "--no-color", "--mod", modulus, "--count-tours", INPUT1])
+            .stdin(Stdio::null())
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        assert!(stdout.contains(&format!("Cost mod {}: {}\n", modulus, reduced)), "{}", stdout);
+        assert!(stdout.contains(&format!("Optimal tours mod {}: {}\n", modulus, tours)), "{}", stdout);
+        assert!(stdout.contains("Minimum Cost: 80"), "{}", stdout);
+    }
+    let output = tsp_solver().args(["--quiet", "--mod", "0", INPUT1]).stdin(Stdio::null()).output().unwrap();
+    assert!(!output.status.success());
+}
+
+/// A nonzero diagonal is warned about once, in the Warnings section after
+/// the solution rather than inline, and again in `--stats-json`.
+#[test]
+fn warnings_are_collected_at_the_end() {
+    let dir = std::env::temp_dir();
+    let matrix = dir.join(format!("tsp_cli_{}_diagonal.txt", std::process::id()));
+    let stats = dir.join(format!("tsp_cli_{}_diagonal.json", std::process::id()));
+    std::fs::write(&matrix, "3\n5 1 2\n1 0 3\n2 3 0\n").unwrap();
+    let output = tsp_solver()
+        .args(["--quiet", "--no-color", "--stats-json"])
+        .args([&stats, &matrix])
+        .stdin(Stdio::null())
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let message = "Nonzero diagonal entries: 1";
+    assert_eq!(stdout.matches(message).count(), 1, "{}", stdout);
+    let section = stdout.find("Warnings (1):").expect(&stdout);
+    assert!(stdout.find("Minimum Cost: 6").unwrap() < section && section < stdout.find(message).unwrap(), "{}", stdout);
+
+    let record: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(&stats).unwrap()).unwrap();
+    assert_eq!(record["warnings"][0]["kind"], "nonzero-diagonal");
+    let _ = (std::fs::remove_file(&matrix), std::fs::remove_file(&stats));
+}
+
+/// `--validate-against`: the shipped gold file for input1 exits 0, and a
+/// wrong cost or a wrong tour exits nonzero with the difference shown.
+#[test]
+fn validate_against_sets_the_exit_code() {
+    let validate = |expected: &str| tsp_solver().args(["--quiet", "--no-color", "--validate-against", expected, INPUT1]).stdin(Stdio::null()).output().unwrap();
+
+    let output = validate("test/input/input1.expected");
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert!(String::from_utf8_lossy(&output.stdout).contains("Matches the expected cost and tour (80)"));
+
+    let dir = std::env::temp_dir();
+    for (name, gold, diff) in [("cost", "79\n", "- cost 79"), ("tour", "80\n0 1 2 3\n", "- tour")] {
+        let path = dir.join(format!("tsp_cli_{}_wrong_{}.expected", std::process::id(), name));
+        std::fs::write(&path, gold).unwrap();
+        let output = validate(path.to_str().unwrap());
+        let _ = std::fs::remove_file(&path);
+        assert!(!output.status.success(), "a wrong {} passed", name);
+        assert_ne!(output.status.code(), Some(0));
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        assert!(stdout.contains(diff), "{}", stdout);
+        assert!(String::from_utf8_lossy(&output.stderr).contains("doesn't match the expected answer"));
+    }
+}
+
+/// Two separate runs write byte-identical `--dump-dp` files with either memo
+/// backend, so the hashmap's order doesn't depend on a per-process seed.
+#[test]
+fn dump_dp_is_identical_across_runs() {
+    let dir = std::env::temp_dir();
+    let id = std::process::id();
+    let matrix = dir.join(format!("tsp_cli_{}_dump.txt", id));
+    let n = 7;
+    let rows: Vec<String> = (0..n).map(|i| (0..n).map(|j| if i == j { 0 } else { (i * 7 + j * 3) % 20 + 1 }.to_string()).collect::<Vec<_>>().join(" ")).collect();
+    std::fs::write(&matrix, format!("{}\n{}\n", n, rows.join("\n"))).unwrap();
+
+    for memo in ["hashmap", "vec"] {
+        let dumps: Vec<String> = (0..2)
+            .map(|run| {
+                let dump = dir.join(format!("tsp_cli_{}_dump_{}_{}.txt", id, memo, run));
+                let output = tsp_solver().args(["--quiet", "--memo", memo, "--dump-dp"]).args([&dump, &matrix]).stdin(Stdio::null()).output().unwrap();
+                assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+                let text = std::fs::read_to_string(&dump).unwrap();
+                let _ = std::fs::remove_file(&dump);
+                text
+            })
+            .collect();
+        assert!(dumps[0].lines().count() > 100, "{}", dumps[0]);
+        assert_eq!(dumps[0], dumps[1], "--memo {} dumped in a different order", memo);
+    }
+    let _ = std::fs::remove_file(&matrix);
+}
+
+/// 2-opt on input1, which breaks the triangle inequality, warns once that
+/// its tour carries no quality guarantee; `--require-metric` refuses to
+/// run on it instead and lets an equidistant square through.
+#[test]
+fn non_metric_start_tour_warns_or_refuses() {
+    let square = std::env::temp_dir().join(format!("tsp_cli_{}_square.txt", std::process::id()));
+    std::fs::write(&square, "4\n0 10 10 10\n10 0 10 10\n10 10 0 10\n10 10 10 0\n").unwrap();
+    let square = square.to_str().unwrap();
+    let run = |args: &[&str]| {
+        tsp_solver().args(["--quiet", "--no-color", "--start-tour", "nn"]).args(args).stdin(Stdio::null()).output().unwrap()
+    };
+
+    let output = run(&[INPUT1]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.contains("Not metric (City1 → City2 is longer than going via City0): the 2-opt tour carries no quality guarantee"),
+        "{}",
+        stdout
+    );
+    assert_eq!(stdout.matches("Not metric").count(), 1, "{}", stdout);
+
+    let output = run(&["--require-metric", INPUT1]);
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("--require-metric: the matrix is not metric (City1 → City2"), "{}", stderr);
+
+    let output = run(&["--require-metric", square]);
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    assert!(!String::from_utf8_lossy(&output.stdout).contains("Not metric"));
+    let _ = std::fs::remove_file(square);
+}